@@ -0,0 +1,682 @@
+use crate::cpu::PROGRAM_START;
+use crate::instruction::Instruction;
+use crate::memory::XO_CHIP_MEMORY_SIZE;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// Deeper than this a file is most likely including itself
+const MAX_INCLUDE_DEPTH: usize = 16;
+// Same for constants defined with each other
+const MAX_EXPRESSION_DEPTH: usize = 64;
+// Names that are operands, they can't be labels or constants
+const RESERVED_NAMES: [&str; 9] = ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblyError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssemblyError {}
+
+pub struct Assembly {
+    // Loaded at 0x200, like any other ROM
+    pub rom: Vec<u8>,
+    // The labels and constants with their values
+    pub symbols: BTreeMap<String, i64>,
+}
+
+impl Assembly {
+    // One symbol on each line, ordered by value: `0x0202 loop`
+    pub fn symbol_map(&self) -> String {
+        let mut symbols: Vec<_> = self.symbols.iter().collect();
+        symbols.sort_by_key(|(name, value)| (**value, name.as_str()));
+        symbols
+            .into_iter()
+            .map(|(name, value)| format!("0x{:04X} {}\n", value, name))
+            .collect()
+    }
+}
+
+// The source uses the mnemonics printed by the disassembler, one instruction on each line:
+//  ; Comments start with a semicolon
+//  SPEED EQU 2            ; Constants can use any expression, labels and `$` (this address)
+//  start: LD V0, SPEED
+//  loop:  ADD V0, SPEED * 2 + 1
+//         JP loop
+//  ORG 0x300              ; Moves forward, filling the gap with zeros
+//  sprite: DB 0b11110000, 0x90, -1
+//          DW sprite, $ - start
+//  INCLUDE "other.asm"    ; Relative to the file including it
+// Mnemonics and registers are case insensitive, labels and constants are not
+pub fn assemble(source: &str) -> Result<Assembly, AssemblyError> {
+    let mut lines = Vec::new();
+    expand_includes(source, "<source>", Path::new("."), 0, &mut lines)?;
+    assemble_lines(&lines)
+}
+
+pub fn assemble_file(path: &Path) -> Result<Assembly, AssemblyError> {
+    let name = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|e| AssemblyError {
+        file: name.clone(),
+        line: 0,
+        message: format!("Failed to read the file: {}", e),
+    })?;
+    let mut lines = Vec::new();
+    let directory = path.parent().unwrap_or(Path::new("."));
+    expand_includes(&source, &name, directory, 0, &mut lines)?;
+    assemble_lines(&lines)
+}
+
+struct SourceLine {
+    file: String,
+    number: usize,
+    text: String,
+}
+
+impl SourceLine {
+    fn error(&self, message: impl Into<String>) -> AssemblyError {
+        AssemblyError {
+            file: self.file.clone(),
+            line: self.number,
+            message: message.into(),
+        }
+    }
+}
+
+fn expand_includes(
+    source: &str,
+    file: &str,
+    directory: &Path,
+    depth: usize,
+    lines: &mut Vec<SourceLine>,
+) -> Result<(), AssemblyError> {
+    for (index, text) in source.lines().enumerate() {
+        let line = SourceLine {
+            file: file.to_string(),
+            number: index + 1,
+            text: strip_comment(text).trim().to_string(),
+        };
+        let (word, rest) = split_first_word(&line.text);
+        if !word.eq_ignore_ascii_case("INCLUDE") {
+            lines.push(line);
+            continue;
+        }
+
+        if depth == MAX_INCLUDE_DEPTH {
+            return Err(line.error("Too many nested includes"));
+        }
+        let name = rest
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .ok_or_else(|| line.error("INCLUDE needs a path between quotes"))?;
+        let path = directory.join(name);
+        let included = fs::read_to_string(&path)
+            .map_err(|e| line.error(format!("Failed to include {}: {}", path.display(), e)))?;
+        let included_directory = path.parent().unwrap_or(directory).to_path_buf();
+        expand_includes(
+            &included,
+            &path.display().to_string(),
+            &included_directory,
+            depth + 1,
+            lines,
+        )?;
+    }
+    Ok(())
+}
+
+enum Statement {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<String>,
+    },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Origin,
+}
+
+struct Located<'a> {
+    line: &'a SourceLine,
+    address: i64,
+    statement: Statement,
+}
+
+fn assemble_lines(lines: &[SourceLine]) -> Result<Assembly, AssemblyError> {
+    // The first pass finds the address of every label, the sizes don't depend on them
+    let mut symbols = Symbols::default();
+    let mut statements = Vec::new();
+    let mut constants = Vec::new();
+    let mut address = PROGRAM_START as i64;
+    for line in lines {
+        let mut text = line.text.as_str();
+        while let Some((name, rest)) = split_label(text) {
+            symbols
+                .define(name, Symbol::Label(address))
+                .map_err(|e| line.error(e))?;
+            text = rest.trim_start();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (word, rest) = split_first_word(text);
+        let (second_word, expression) = split_first_word(rest);
+        if second_word.eq_ignore_ascii_case("EQU") {
+            let constant = Symbol::Constant(expression.to_string(), address);
+            symbols.define(word, constant).map_err(|e| line.error(e))?;
+            constants.push((line, word));
+            continue;
+        }
+
+        let operands = split_operands(rest);
+        let (statement, size) = match word.to_ascii_uppercase().as_str() {
+            "DB" => (Statement::Bytes(operands.clone()), operands.len() as i64),
+            "DW" => (
+                Statement::Words(operands.clone()),
+                operands.len() as i64 * 2,
+            ),
+            "ORG" => {
+                // Only the symbols defined before can be used, the labels after it depend on it
+                let [origin] = operands.as_slice() else {
+                    return Err(line.error("ORG needs one address"));
+                };
+                let origin = symbols
+                    .evaluate(origin, address, 0)
+                    .map_err(|e| line.error(e))?;
+                if origin < address {
+                    return Err(line.error(format!(
+                        "ORG 0x{:03X} goes back from 0x{:03X}",
+                        origin, address
+                    )));
+                }
+                address = origin;
+                (Statement::Origin, 0)
+            }
+            _ => {
+                let is_long = word.eq_ignore_ascii_case("LD")
+                    && operands.len() == 2
+                    && operands[0].eq_ignore_ascii_case("I")
+                    && matches!(classify(&operands[1]), Operand::Long(_));
+                let statement = Statement::Instruction {
+                    mnemonic: word.to_ascii_uppercase(),
+                    operands,
+                };
+                (statement, if is_long { 4 } else { 2 })
+            }
+        };
+        statements.push(Located {
+            line,
+            address,
+            statement,
+        });
+        address += size;
+        if address > XO_CHIP_MEMORY_SIZE as i64 {
+            return Err(line.error("The program doesn't fit in the memory"));
+        }
+    }
+
+    // Every constant is checked, even the ones never used
+    for (line, name) in constants {
+        symbols.value(name, 0).map_err(|e| line.error(e))?;
+    }
+
+    let mut rom = Vec::new();
+    for located in &statements {
+        let line = located.line;
+        // ORG leaves a gap until the next statement
+        rom.resize((located.address - PROGRAM_START as i64) as usize, 0x00);
+        let evaluate = |expression: &str| symbols.evaluate(expression, located.address, 0);
+        match &located.statement {
+            Statement::Instruction { mnemonic, operands } => {
+                let (instruction, long_address) =
+                    parse_instruction(mnemonic, operands, &evaluate).map_err(|e| line.error(e))?;
+                rom.extend_from_slice(&instruction.encode().to_be_bytes());
+                if let Some(long_address) = long_address {
+                    rom.extend_from_slice(&long_address.to_be_bytes());
+                }
+            }
+            Statement::Bytes(values) => {
+                for value in values {
+                    let value = evaluate(value).map_err(|e| line.error(e))?;
+                    rom.push(
+                        in_range(value, -0x80, 0xFF, "A byte").map_err(|e| line.error(e))? as u8,
+                    );
+                }
+            }
+            Statement::Words(values) => {
+                for value in values {
+                    let value = evaluate(value).map_err(|e| line.error(e))?;
+                    let word =
+                        in_range(value, -0x8000, 0xFFFF, "A word").map_err(|e| line.error(e))?;
+                    rom.extend_from_slice(&(word as u16).to_be_bytes());
+                }
+            }
+            Statement::Origin => {}
+        }
+    }
+
+    // Every symbol can be evaluated, the constants were checked above
+    let symbols = symbols
+        .names
+        .keys()
+        .map(|name| (name.clone(), symbols.value(name, 0).unwrap_or_default()))
+        .collect();
+    Ok(Assembly { rom, symbols })
+}
+
+enum Symbol {
+    Label(i64),
+    // The expression and the address where it was defined, for its `$`
+    Constant(String, i64),
+}
+
+#[derive(Default)]
+struct Symbols {
+    names: HashMap<String, Symbol>,
+}
+
+impl Symbols {
+    fn define(&mut self, name: &str, symbol: Symbol) -> Result<(), String> {
+        if !is_identifier(name) {
+            return Err(format!("Invalid name: {}", name));
+        }
+        if is_reserved(name) {
+            return Err(format!("{} is reserved", name));
+        }
+        if self.names.insert(name.to_string(), symbol).is_some() {
+            return Err(format!("{} is already defined", name));
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self, expression: &str, address: i64, depth: usize) -> Result<i64, String> {
+        if depth == MAX_EXPRESSION_DEPTH {
+            return Err(format!("{} depends on itself", expression));
+        }
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            symbols: self,
+            address,
+            depth,
+        };
+        let value = parser.expression()?;
+        if parser.position != tokens.len() {
+            return Err(format!("Invalid expression: {}", expression));
+        }
+        Ok(value)
+    }
+
+    fn value(&self, name: &str, depth: usize) -> Result<i64, String> {
+        match self.names.get(name) {
+            Some(Symbol::Label(address)) => Ok(*address),
+            Some(Symbol::Constant(expression, address)) => {
+                self.evaluate(expression, *address, depth + 1)
+            }
+            None => Err(format!("Unknown symbol: {}", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    // `$`, the address of the current statement
+    Here,
+    Operator(&'static str),
+}
+
+const OPERATORS: [&str; 13] = [
+    "<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~", "(", ")",
+];
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+    while !rest.is_empty() {
+        if let Some(operator) = OPERATORS
+            .iter()
+            .find(|operator| rest.starts_with(**operator))
+        {
+            tokens.push(Token::Operator(operator));
+            rest = &rest[operator.len()..];
+        } else if let Some(after) = rest.strip_prefix('$') {
+            tokens.push(Token::Here);
+            rest = after;
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("Unexpected character in {}", expression));
+            }
+            let word = &rest[..end];
+            tokens.push(if word.starts_with(|c: char| c.is_ascii_digit()) {
+                Token::Number(parse_number(word)?)
+            } else {
+                Token::Name(word.to_string())
+            });
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+fn parse_number(word: &str) -> Result<i64, String> {
+    let lowercase = word.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lowercase.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = lowercase.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+    } else {
+        lowercase.parse()
+    };
+    parsed.map_err(|_| format!("Invalid number: {}", word))
+}
+
+// From the lowest precedence to the highest: |, ^, &, << and >>, + and -, *, / and %, then the
+// unary -, ~ and +
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    symbols: &'a Symbols,
+    address: i64,
+    depth: usize,
+}
+
+const BINARY_LEVELS: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl Parser<'_> {
+    fn expression(&mut self) -> Result<i64, String> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, String> {
+        if level == BINARY_LEVELS.len() {
+            return self.unary();
+        }
+        let mut value = self.binary(level + 1)?;
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position) {
+            if !BINARY_LEVELS[level].contains(operator) {
+                break;
+            }
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            value = match *operator {
+                "|" => value | right,
+                "^" => value ^ right,
+                "&" => value & right,
+                "<<" => value.checked_shl(right as u32).unwrap_or(0),
+                ">>" => value.checked_shr(right as u32).unwrap_or(0),
+                "+" => value.wrapping_add(right),
+                "-" => value.wrapping_sub(right),
+                "*" => value.wrapping_mul(right),
+                "/" => value.checked_div(right).ok_or("Division by zero")?,
+                _ => value.checked_rem(right).ok_or("Division by zero")?,
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Operator("-")) => Ok(self.unary()?.wrapping_neg()),
+            Some(Token::Operator("~")) => Ok(!self.unary()?),
+            Some(Token::Operator("+")) => self.unary(),
+            Some(Token::Operator("(")) => {
+                let value = self.expression()?;
+                if self.tokens.get(self.position) != Some(&Token::Operator(")")) {
+                    return Err("Missing )".to_string());
+                }
+                self.position += 1;
+                Ok(value)
+            }
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Here) => Ok(self.address),
+            Some(Token::Name(name)) => self.symbols.value(&name, self.depth),
+            _ => Err("Missing a value in the expression".to_string()),
+        }
+    }
+}
+
+enum Operand<'a> {
+    Register(usize),
+    I,
+    // [I]
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    RplFlags,
+    Long(&'a str),
+    Value(&'a str),
+}
+
+fn classify(operand: &str) -> Operand<'_> {
+    let uppercase = operand.to_ascii_uppercase();
+    match uppercase.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "HF" => Operand::BigFont,
+        "B" => Operand::Bcd,
+        "R" => Operand::RplFlags,
+        _ => {
+            if let Some(register) = register_index(&uppercase) {
+                return Operand::Register(register);
+            }
+            let (word, rest) = split_first_word(operand);
+            if word.eq_ignore_ascii_case("LONG") {
+                return Operand::Long(rest);
+            }
+            Operand::Value(operand)
+        }
+    }
+}
+
+fn register_index(name: &str) -> Option<usize> {
+    let digit = name.strip_prefix(['V', 'v'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    usize::from_str_radix(digit, 16).ok()
+}
+
+fn parse_instruction(
+    mnemonic: &str,
+    operands: &[String],
+    evaluate: &dyn Fn(&str) -> Result<i64, String>,
+) -> Result<(Instruction, Option<u16>), String> {
+    let address = |expression: &str| -> Result<u16, String> {
+        Ok(in_range(evaluate(expression)?, 0, 0xFFF, "An address")? as u16)
+    };
+    let byte = |expression: &str| -> Result<u8, String> {
+        Ok(in_range(evaluate(expression)?, -0x80, 0xFF, "A byte")? as u8)
+    };
+    let nibble = |expression: &str| -> Result<u8, String> {
+        Ok(in_range(evaluate(expression)?, 0, 0xF, "A nibble")? as u8)
+    };
+
+    let operands: Vec<Operand> = operands.iter().map(|operand| classify(operand)).collect();
+    use Operand::*;
+    let instruction = match (mnemonic, operands.as_slice()) {
+        ("CLS", []) => Instruction::Clear,
+        ("RET", []) => Instruction::Return,
+        ("SYS", [Value(a)]) => Instruction::MachineCodeRoutine { nnn: address(a)? },
+        ("JP", [Value(a)]) => Instruction::Jump { nnn: address(a)? },
+        ("JP", [Register(0), Value(a)]) => Instruction::JumpToNnnPlusV0 { nnn: address(a)? },
+        // BXNN of the CHIP-48 and the SUPER-CHIP, the register is the first digit of the address
+        ("JP", [Register(x), Value(a)]) => {
+            let nnn = address(a)?;
+            if nnn as usize >> 8 != *x {
+                return Err(format!(
+                    "JP V{:X} needs an address from 0x{:X}00 to 0x{:X}FF",
+                    x, x, x
+                ));
+            }
+            Instruction::JumpToNnnPlusV0 { nnn }
+        }
+        ("CALL", [Value(a)]) => Instruction::Call { nnn: address(a)? },
+        ("SE", [Register(x), Register(y)]) => Instruction::SkipIfXEqualsY { x: *x, y: *y },
+        ("SE", [Register(x), Value(b)]) => Instruction::SkipIfXEqualsNn {
+            x: *x,
+            nn: byte(b)?,
+        },
+        ("SNE", [Register(x), Register(y)]) => Instruction::SkipIfXDiffersY { x: *x, y: *y },
+        ("SNE", [Register(x), Value(b)]) => Instruction::SkipIfXDiffersNn {
+            x: *x,
+            nn: byte(b)?,
+        },
+        ("LD", [Register(x), Register(y)]) => Instruction::StoreYToX { x: *x, y: *y },
+        ("LD", [Register(x), Value(b)]) => Instruction::SetNnToX {
+            x: *x,
+            nn: byte(b)?,
+        },
+        ("LD", [I, Value(a)]) => Instruction::SetNnnToI { nnn: address(a)? },
+        ("LD", [I, Long(a)]) => {
+            let long_address = in_range(evaluate(a)?, 0, 0xFFFF, "A long address")? as u16;
+            return Ok((Instruction::SetNextWordToI, Some(long_address)));
+        }
+        ("LD", [Register(x), DelayTimer]) => Instruction::SetDelayTimerToX { x: *x },
+        ("LD", [Register(x), Key]) => Instruction::WaitKeyToX { x: *x },
+        ("LD", [DelayTimer, Register(x)]) => Instruction::SetXToDelayTimer { x: *x },
+        ("LD", [SoundTimer, Register(x)]) => Instruction::SetXToSoundTimer { x: *x },
+        ("LD", [Font, Register(x)]) => Instruction::SetIToFontOfX { x: *x },
+        ("LD", [BigFont, Register(x)]) => Instruction::SetIToBigFontOfX { x: *x },
+        ("LD", [Bcd, Register(x)]) => Instruction::StoreBcdOfXOnI { x: *x },
+        ("LD", [IndirectI, Register(x)]) => Instruction::StoreV0ToXOnI { x: *x },
+        ("LD", [Register(x), IndirectI]) => Instruction::LoadV0ToXFromI { x: *x },
+        ("LD", [RplFlags, Register(x)]) => Instruction::StoreV0ToXOnFlags { x: *x },
+        ("LD", [Register(x), RplFlags]) => Instruction::LoadV0ToXFromFlags { x: *x },
+        ("ADD", [Register(x), Register(y)]) => Instruction::SumYToX { x: *x, y: *y },
+        ("ADD", [Register(x), Value(b)]) => Instruction::SumNnToX {
+            x: *x,
+            nn: byte(b)?,
+        },
+        ("ADD", [I, Register(x)]) => Instruction::SumXToI { x: *x },
+        ("OR", [Register(x), Register(y)]) => Instruction::OrYToX { x: *x, y: *y },
+        ("AND", [Register(x), Register(y)]) => Instruction::AndYToX { x: *x, y: *y },
+        ("XOR", [Register(x), Register(y)]) => Instruction::XorYToX { x: *x, y: *y },
+        ("SUB", [Register(x), Register(y)]) => Instruction::SubtractYFromX { x: *x, y: *y },
+        ("SUBN", [Register(x), Register(y)]) => Instruction::SubtractXFromY { x: *x, y: *y },
+        // Without VY the register is shifted in place with any of the quirks
+        ("SHR", [Register(x)]) => Instruction::ShiftRightX { x: *x, y: *x },
+        ("SHR", [Register(x), Register(y)]) => Instruction::ShiftRightX { x: *x, y: *y },
+        ("SHL", [Register(x)]) => Instruction::ShiftLeftX { x: *x, y: *x },
+        ("SHL", [Register(x), Register(y)]) => Instruction::ShiftLeftX { x: *x, y: *y },
+        ("RND", [Register(x), Value(b)]) => Instruction::RandomAndNnToX {
+            x: *x,
+            nn: byte(b)?,
+        },
+        ("DRW", [Register(x), Register(y), Value(n)]) => Instruction::Draw {
+            x: *x,
+            y: *y,
+            n: nibble(n)?,
+        },
+        ("SKP", [Register(x)]) => Instruction::SkipIfKeyOnXPressed { x: *x },
+        ("SKNP", [Register(x)]) => Instruction::SkipIfKeyOnXNotPressed { x: *x },
+        ("SCD", [Value(n)]) => Instruction::ScrollDown { n: nibble(n)? },
+        ("SCR", []) => Instruction::ScrollRight,
+        ("SCL", []) => Instruction::ScrollLeft,
+        ("EXIT", []) => Instruction::Exit,
+        ("LOW", []) => Instruction::LowResolution,
+        ("HIGH", []) => Instruction::HighResolution,
+        ("SCU", [Value(n)]) => Instruction::ScrollUp { n: nibble(n)? },
+        ("SAVE", [Register(x), Register(y)]) => Instruction::StoreXToYOnI { x: *x, y: *y },
+        ("LOAD", [Register(x), Register(y)]) => Instruction::LoadXToYFromI { x: *x, y: *y },
+        ("PLANE", [Value(n)]) => Instruction::SelectPlanes { n: nibble(n)? },
+        ("AUDIO", []) => Instruction::LoadAudioPattern,
+        ("PITCH", [Register(x)]) => Instruction::SetPitchToX { x: *x },
+        _ => {
+            return Err(if KNOWN_MNEMONICS.contains(&mnemonic) {
+                format!("Invalid operands for {}", mnemonic)
+            } else {
+                format!("Unknown instruction: {}", mnemonic)
+            });
+        }
+    };
+    Ok((instruction, None))
+}
+
+const KNOWN_MNEMONICS: [&str; 32] = [
+    "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN",
+    "SHR", "SHL", "RND", "DRW", "SKP", "SKNP", "SCD", "SCR", "SCL", "EXIT", "LOW", "HIGH", "SCU",
+    "SAVE", "LOAD", "PLANE", "AUDIO", "PITCH",
+];
+
+fn in_range(value: i64, min: i64, max: i64, what: &str) -> Result<i64, String> {
+    if value < min || value > max {
+        return Err(format!("{} can't be {}", what, value));
+    }
+    // Negative values are stored as two's complement
+    Ok(value & max)
+}
+
+fn strip_comment(text: &str) -> &str {
+    text.split(';').next().unwrap_or("")
+}
+
+fn split_first_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.find(char::is_whitespace) {
+        Some(end) => (&text[..end], text[end..].trim_start()),
+        None => (text, ""),
+    }
+}
+
+// `name:` at the start of the line
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let (name, rest) = text.split_once(':')?;
+    is_identifier(name).then_some((name, rest))
+}
+
+fn split_operands(text: &str) -> Vec<String> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+    text.split(',')
+        .map(|operand| operand.trim().to_string())
+        .collect()
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_reserved(name: &str) -> bool {
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(name))
+        || register_index(&name.to_ascii_uppercase()).is_some()
+}
@@ -0,0 +1,252 @@
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::time::Duration;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+pub const DEFAULT_FREQUENCY: f32 = 440.0;
+pub const DEFAULT_VOLUME: f32 = 0.25;
+const WAV_HEADER_SIZE: u32 = 44;
+// The sizes in the header are 32 bits, the recording stops once the RIFF size would overflow
+const MAX_WAV_DATA_SIZE: u32 = (u32::MAX - (WAV_HEADER_SIZE - 8)) & !1;
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+// The pitch that plays the pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name.to_ascii_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            "sawtooth" => Some(Waveform::Sawtooth),
+            _ => None,
+        }
+    }
+
+    // The value of the wave between -1.0 and 1.0 at the given phase (0.0 to 1.0 of a cycle)
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (phase * 2.0 * PI).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioConfig {
+    pub sample_rate: u32,
+    // Frequency of the tone in Hz
+    pub frequency: f32,
+    // Between 0.0 (silence) and 1.0 (full scale)
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for AudioConfig {
+    fn default() -> AudioConfig {
+        AudioConfig {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            frequency: DEFAULT_FREQUENCY,
+            volume: DEFAULT_VOLUME,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+// The XO-CHIP 128 bits played in a loop instead of the tone, each bit is a high or low sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioPattern {
+    pub buffer: [u8; AUDIO_PATTERN_SIZE],
+    pub pitch: u8,
+}
+
+impl AudioPattern {
+    // Bits per second, every 48 steps of pitch is an octave
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+    }
+
+    // The bit at the given phase (0.0 to 1.0 of the whole pattern)
+    fn sample(&self, phase: f32) -> f32 {
+        let bit = (phase * (AUDIO_PATTERN_SIZE * 8) as f32) as usize % (AUDIO_PATTERN_SIZE * 8);
+        if (self.buffer[bit / 8] >> (7 - bit % 8)) & 1 == 1 {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+}
+
+// Where the samples produced by the buzzer end up, all the samples are signed 16 bits mono
+pub trait AudioSink {
+    fn write_samples(&mut self, samples: &[i16]) -> io::Result<()>;
+    // Called once the session is over so the sink can flush anything pending
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+// Writes the whole session to a WAV file, the header is only completed on `finish` as the size
+// of the data is not known until then
+pub struct WavSink {
+    writer: BufWriter<File>,
+    sample_rate: u32,
+    data_size: u32,
+    finished: bool,
+}
+
+impl WavSink {
+    pub fn create(path: &str, sample_rate: u32) -> io::Result<WavSink> {
+        let mut sink = WavSink {
+            writer: BufWriter::new(File::create(path)?),
+            sample_rate,
+            data_size: 0,
+            finished: false,
+        };
+        sink.write_header()?;
+        Ok(sink)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let channels: u16 = 1;
+        let bits_per_sample: u16 = 16;
+        let block_align = channels * bits_per_sample / 8;
+        let byte_rate = self.sample_rate * block_align as u32;
+
+        let writer = &mut self.writer;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(WAV_HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // 1 is PCM without compression
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&self.sample_rate.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&bits_per_sample.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&self.data_size.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+impl AudioSink for WavSink {
+    fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        // About 13 hours at 44100 Hz, what doesn't fit is dropped
+        let room = ((MAX_WAV_DATA_SIZE - self.data_size) / 2) as usize;
+        let samples = &samples[..samples.len().min(room)];
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size += (samples.len() * 2) as u32;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+// Generates the tone while the sound timer is active and silence otherwise
+pub struct Buzzer {
+    config: AudioConfig,
+    sink: Box<dyn AudioSink>,
+    // Replaces the waveform while it is set
+    pattern: Option<AudioPattern>,
+    // Position inside of the current cycle of the wave (0.0 to 1.0), kept between calls so the
+    // tone doesn't click every time new samples are generated
+    phase: f32,
+    // Fraction of a sample that didn't fit in the previous call
+    pending_samples: f64,
+}
+
+pub trait BuzzerTrait {
+    // Produces the samples that cover the elapsed time and sends them to the sink
+    fn generate(&mut self, active: bool, elapsed: Duration) -> io::Result<()>;
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+impl BuzzerTrait for Buzzer {
+    fn generate(&mut self, active: bool, elapsed: Duration) -> io::Result<()> {
+        self.pending_samples += elapsed.as_secs_f64() * self.config.sample_rate as f64;
+        let count = self.pending_samples as usize;
+        self.pending_samples -= count as f64;
+        if count == 0 {
+            return Ok(());
+        }
+
+        let mut samples = Vec::with_capacity(count);
+        if active {
+            let amplitude = self.config.volume.clamp(0.0, 1.0) * i16::MAX as f32;
+            // With a pattern a cycle is the whole pattern instead of one wave of the tone
+            let frequency = match &self.pattern {
+                Some(pattern) => pattern.playback_rate() / (AUDIO_PATTERN_SIZE * 8) as f32,
+                None => self.config.frequency,
+            };
+            let phase_step = frequency / self.config.sample_rate as f32;
+            for _ in 0..count {
+                let value = match &self.pattern {
+                    Some(pattern) => pattern.sample(self.phase),
+                    None => self.config.waveform.sample(self.phase),
+                };
+                samples.push((value * amplitude) as i16);
+                self.phase = (self.phase + phase_step).fract();
+            }
+        } else {
+            samples.resize(count, 0);
+            self.phase = 0.0;
+        }
+
+        self.sink.write_samples(&samples)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.sink.finish()
+    }
+}
+
+impl Buzzer {
+    pub fn new(config: AudioConfig, sink: Box<dyn AudioSink>) -> Buzzer {
+        Buzzer {
+            config,
+            sink,
+            pattern: None,
+            phase: 0.0,
+            pending_samples: 0.0,
+        }
+    }
+
+    pub fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        self.pattern = pattern;
+    }
+}
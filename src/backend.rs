@@ -0,0 +1,67 @@
+use crate::display::Display;
+
+// A copy of the screen, each pixel is its colour from 0 to 3 (one bit for each plane)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    // Row by row, from the top left corner
+    pub pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn from_display(display: &Display) -> Framebuffer {
+        Framebuffer {
+            width: display.width(),
+            height: display.height(),
+            pixels: display.rows().flatten().copied().collect(),
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+}
+
+// Where the frames end up, the frontend calls `present` every time the screen changes
+pub trait DisplayBackend {
+    fn present(&mut self, display: &Display);
+}
+
+// Keeps the last frame in memory without printing anything, for runs without a terminal like
+// the tests and CI
+pub struct HeadlessBackend {
+    frame: Option<Framebuffer>,
+    frames_presented: u64,
+}
+
+impl DisplayBackend for HeadlessBackend {
+    fn present(&mut self, display: &Display) {
+        self.frame = Some(Framebuffer::from_display(display));
+        self.frames_presented += 1;
+    }
+}
+
+impl Default for HeadlessBackend {
+    fn default() -> HeadlessBackend {
+        HeadlessBackend::new()
+    }
+}
+
+impl HeadlessBackend {
+    pub fn new() -> HeadlessBackend {
+        HeadlessBackend {
+            frame: None,
+            frames_presented: 0,
+        }
+    }
+
+    // None until the first frame is presented
+    pub fn frame(&self) -> Option<&Framebuffer> {
+        self.frame.as_ref()
+    }
+
+    pub fn frames_presented(&self) -> u64 {
+        self.frames_presented
+    }
+}
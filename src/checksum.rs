@@ -0,0 +1,30 @@
+// The CRC-32 used by zip and PNG (polynomial 0xEDB88320), computed bit by bit as the inputs are
+// small
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+// Continues a CRC started with another part of the data, so it can be computed in pieces
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+// The checksum at the end of a zlib stream
+pub fn adler32(data: &[u8]) -> u32 {
+    const MODULO: u32 = 65521;
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    for &byte in data {
+        a = (a + byte as u32) % MODULO;
+        b = (b + a) % MODULO;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,345 @@
+use crate::terminal::BackendKind;
+use chip8_core::audio::{AudioConfig, Waveform};
+use chip8_core::quirks::Quirks;
+use chip8_core::rewind::{DEFAULT_REWIND_BUDGET, DEFAULT_REWIND_INTERVAL};
+use chip8_core::scheduler::{DEFAULT_INSTRUCTIONS_PER_SECOND, Speed};
+use chip8_core::screenshot::Palette;
+
+pub const USAGE: &str = "\
+Usage: chip-8-emulator [OPTIONS] <ROM>
+       chip-8-emulator disassemble [--quirks <PROFILE>] [--source] <ROM>
+       chip-8-emulator assemble [-o <ROM>] [--symbols <FILE>] <SOURCE>
+
+Arguments:
+  <ROM>                    Path of the ROM file to run, Octo sources (.8o) are compiled first
+
+Commands:
+  disassemble              Print the instructions and data of the ROM instead of running it, the
+                           quirk profile tells which instructions are available. With --source
+                           it is printed as source for the assembler
+  assemble                 Turn the source into a ROM, written next to it with the .ch8
+                           extension unless -o is given. Octo sources (.8o) are compiled.
+                           --symbols writes the address of every label and the value of every
+                           constant
+
+Options:
+  --clock <HZ>             Instructions executed per second (default: 700)
+  --speed <MULTIPLIER>     Emulation speed, above 1.0 is turbo and below it is slow motion
+                           (default: 1.0)
+  --unthrottled            Run as fast as the host allows
+  --quirks <PROFILE>       Interpreter the ROM was written for: modern, vip, chip48, schip or
+                           xochip (default: modern)
+  --display <BACKEND>      How the screen is printed: terminal, plain or headless
+                           (default: terminal)
+  --scale <N>              Characters printed for each pixel (default: 1)
+  --keymap <FILE>          Key map file, each line has a host key and the CHIP-8 key in hex
+  --seed <N>               Seed of the random number generator used by CXNN
+  --flags <FILE>           File where the SUPER-CHIP RPL flags (FX75/FX85) are kept between runs
+  --rewind-interval <N>    Frames between two rewind snapshots (default: 6)
+  --rewind-budget <MIB>    Memory used by the rewind snapshots, 0 disables it (default: 16)
+  --record <FILE>          Record the keys pressed on every frame to a movie file
+  --replay <FILE>          Play a movie file instead of reading the keyboard, the quirks, seed
+                           and clock come from the movie
+  --headless               Run without a terminal or keyboard, printing only the last frame
+  --debug                  Start paused in the debugger, F12 pauses again while running
+  --gdb <PORT>             Wait for a GDB remote protocol client to connect on localhost
+                           before running
+  --max-cycles <N>         Stop after executing N instructions
+  --screenshot <FILE>      Save the last frame as a PNG, or a PBM or PGM when the file ends in
+                           .pbm or .pgm
+  --screenshot-scale <N>   Size in pixels of each CHIP-8 pixel on the screenshot (default: 8)
+  --palette <COLOURS>      Up to 4 colours in hex for the screenshot, background first
+                           (default: 000000,FFFFFF,555555,AAAAAA)
+  --wav <FILE>             Record the buzzer to a WAV file
+  --tone <HZ>              Frequency of the buzzer (default: 440)
+  --volume <VOLUME>        Volume of the buzzer from 0.0 to 1.0 (default: 0.25)
+  --waveform <WAVEFORM>    square, sine, triangle or sawtooth (default: square)
+  -h, --help               Print this help";
+
+pub enum Command {
+    Run(Box<Options>),
+    Disassemble {
+        rom_path: String,
+        quirks: Quirks,
+        source: bool,
+    },
+    Assemble {
+        source_path: String,
+        rom_path: Option<String>,
+        symbols_path: Option<String>,
+    },
+    Help,
+}
+
+pub struct Options {
+    pub rom_path: String,
+    pub instructions_per_second: u32,
+    pub speed: Speed,
+    pub quirks: Quirks,
+    pub display_backend: BackendKind,
+    pub scale: usize,
+    pub key_map_path: Option<String>,
+    pub seed: Option<u64>,
+    pub flags_path: Option<String>,
+    pub rewind_interval: u32,
+    // In bytes
+    pub rewind_budget: usize,
+    pub record_path: Option<String>,
+    pub replay_path: Option<String>,
+    pub headless: bool,
+    pub debug: bool,
+    pub gdb_port: Option<u16>,
+    pub max_cycles: Option<u64>,
+    pub screenshot_path: Option<String>,
+    pub screenshot_scale: usize,
+    pub palette: Palette,
+    pub wav_path: Option<String>,
+    pub audio: AudioConfig,
+}
+
+pub fn parse_args<I>(args: I) -> Result<Command, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter().peekable();
+    if args.peek().is_some_and(|arg| arg == "disassemble") {
+        args.next();
+        return parse_disassemble_args(args);
+    }
+    if args.peek().is_some_and(|arg| arg == "assemble") {
+        args.next();
+        return parse_assemble_args(args);
+    }
+
+    let mut rom_path = None;
+    let mut instructions_per_second = DEFAULT_INSTRUCTIONS_PER_SECOND;
+    let mut speed = None;
+    let mut quirks = Quirks::default();
+    let mut display_backend = BackendKind::Terminal;
+    let mut scale = 1;
+    let mut key_map_path = None;
+    let mut seed = None;
+    let mut flags_path = None;
+    let mut rewind_interval = DEFAULT_REWIND_INTERVAL;
+    let mut rewind_budget = DEFAULT_REWIND_BUDGET;
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut headless = false;
+    let mut debug = false;
+    let mut gdb_port = None;
+    let mut max_cycles = None;
+    let mut screenshot_path = None;
+    let mut screenshot_scale = 8;
+    let mut palette = Palette::default();
+    let mut wav_path = None;
+    let mut audio = AudioConfig::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--clock" => {
+                instructions_per_second = parse_value(&arg, args.next())?;
+                if instructions_per_second == 0 {
+                    return Err("--clock must be above 0".to_string());
+                }
+            }
+            "--speed" => {
+                let multiplier: f64 = parse_value(&arg, args.next())?;
+                if multiplier <= 0.0 || !multiplier.is_finite() {
+                    return Err("--speed must be above 0".to_string());
+                }
+                speed = Some(Speed::Multiplier(multiplier));
+            }
+            "--unthrottled" => speed = Some(Speed::Unthrottled),
+            "--quirks" => {
+                let name = required_value(&arg, args.next())?;
+                quirks = Quirks::from_name(&name)
+                    .ok_or_else(|| format!("Unknown quirk profile: {}", name))?;
+            }
+            "--display" => {
+                let name = required_value(&arg, args.next())?;
+                display_backend = BackendKind::from_name(&name)
+                    .ok_or_else(|| format!("Unknown display backend: {}", name))?;
+            }
+            "--scale" => {
+                scale = parse_value(&arg, args.next())?;
+                if scale == 0 {
+                    return Err("--scale must be above 0".to_string());
+                }
+            }
+            "--keymap" => key_map_path = Some(required_value(&arg, args.next())?),
+            "--seed" => seed = Some(parse_value(&arg, args.next())?),
+            "--flags" => flags_path = Some(required_value(&arg, args.next())?),
+            "--rewind-interval" => {
+                rewind_interval = parse_value(&arg, args.next())?;
+                if rewind_interval == 0 {
+                    return Err("--rewind-interval must be above 0".to_string());
+                }
+            }
+            "--rewind-budget" => {
+                let mebibytes: usize = parse_value(&arg, args.next())?;
+                rewind_budget = mebibytes
+                    .checked_mul(1024 * 1024)
+                    .ok_or_else(|| "--rewind-budget is too big".to_string())?;
+            }
+            "--record" => record_path = Some(required_value(&arg, args.next())?),
+            "--replay" => replay_path = Some(required_value(&arg, args.next())?),
+            "--headless" => headless = true,
+            "--debug" => debug = true,
+            "--gdb" => gdb_port = Some(parse_value(&arg, args.next())?),
+            "--max-cycles" => max_cycles = Some(parse_value(&arg, args.next())?),
+            "--screenshot" => screenshot_path = Some(required_value(&arg, args.next())?),
+            "--screenshot-scale" => {
+                screenshot_scale = parse_value(&arg, args.next())?;
+                if screenshot_scale == 0 {
+                    return Err("--screenshot-scale must be above 0".to_string());
+                }
+            }
+            "--palette" => palette = Palette::parse(&required_value(&arg, args.next())?)?,
+            "--wav" => wav_path = Some(required_value(&arg, args.next())?),
+            "--tone" => {
+                audio.frequency = parse_value(&arg, args.next())?;
+                if audio.frequency <= 0.0 || !audio.frequency.is_finite() {
+                    return Err("--tone must be above 0".to_string());
+                }
+            }
+            "--volume" => {
+                audio.volume = parse_value(&arg, args.next())?;
+                if !(0.0..=1.0).contains(&audio.volume) {
+                    return Err("--volume must be between 0.0 and 1.0".to_string());
+                }
+            }
+            "--waveform" => {
+                let name = required_value(&arg, args.next())?;
+                audio.waveform = Waveform::from_name(&name)
+                    .ok_or_else(|| format!("Unknown waveform: {}", name))?;
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ => {
+                if rom_path.is_some() {
+                    return Err(format!("Unexpected argument: {}", arg));
+                }
+                rom_path = Some(arg);
+            }
+        }
+    }
+
+    let rom_path = rom_path.ok_or_else(|| "Missing the ROM path".to_string())?;
+    if record_path.is_some() && replay_path.is_some() {
+        return Err("--record and --replay can't be used together".to_string());
+    }
+    // The debugger reads its commands from the terminal
+    if debug && headless {
+        return Err("--debug and --headless can't be used together".to_string());
+    }
+    // Both would pause the machine on their own
+    if debug && gdb_port.is_some() {
+        return Err("--debug and --gdb can't be used together".to_string());
+    }
+    // Nobody is watching a headless run, so it goes as fast as possible unless asked otherwise
+    let speed = speed.unwrap_or(if headless {
+        Speed::Unthrottled
+    } else {
+        Speed::Multiplier(1.0)
+    });
+
+    Ok(Command::Run(Box::new(Options {
+        rom_path,
+        instructions_per_second,
+        speed,
+        quirks,
+        display_backend,
+        scale,
+        key_map_path,
+        seed,
+        flags_path,
+        rewind_interval,
+        rewind_budget,
+        record_path,
+        replay_path,
+        headless,
+        debug,
+        gdb_port,
+        max_cycles,
+        screenshot_path,
+        screenshot_scale,
+        palette,
+        wav_path,
+        audio,
+    })))
+}
+
+fn parse_disassemble_args<I>(mut args: I) -> Result<Command, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut rom_path = None;
+    let mut quirks = Quirks::default();
+    let mut source = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--source" => source = true,
+            "--quirks" => {
+                let name = required_value(&arg, args.next())?;
+                quirks = Quirks::from_name(&name)
+                    .ok_or_else(|| format!("Unknown quirk profile: {}", name))?;
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ => {
+                if rom_path.is_some() {
+                    return Err(format!("Unexpected argument: {}", arg));
+                }
+                rom_path = Some(arg);
+            }
+        }
+    }
+
+    let rom_path = rom_path.ok_or_else(|| "Missing the ROM path".to_string())?;
+    Ok(Command::Disassemble {
+        rom_path,
+        quirks,
+        source,
+    })
+}
+
+fn parse_assemble_args<I>(mut args: I) -> Result<Command, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut source_path = None;
+    let mut rom_path = None;
+    let mut symbols_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-o" => rom_path = Some(required_value(&arg, args.next())?),
+            "--symbols" => symbols_path = Some(required_value(&arg, args.next())?),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ => {
+                if source_path.is_some() {
+                    return Err(format!("Unexpected argument: {}", arg));
+                }
+                source_path = Some(arg);
+            }
+        }
+    }
+
+    let source_path = source_path.ok_or_else(|| "Missing the source path".to_string())?;
+    Ok(Command::Assemble {
+        source_path,
+        rom_path,
+        symbols_path,
+    })
+}
+
+fn required_value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("Missing value for {}", option))
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = required_value(option, value)?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", option, value))
+}
@@ -0,0 +1,233 @@
+use crate::cpu::Cpu;
+use crate::memory::MemoryTrait;
+use crate::timers::TimersTrait;
+use std::fmt;
+
+// From the lowest precedence to the highest, like in Rust the comparisons are below the bitwise
+// operators: `V0 & 0x80 == 0x80` is `(V0 & 0x80) == 0x80`
+const BINARY_LEVELS: [&[&str]; 7] = [
+    &["||"],
+    &["&&"],
+    &["==", "!=", "<=", ">=", "<", ">"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["+", "-"],
+];
+// The longest first, so `<=` isn't read as `<`
+const OPERATORS: [&str; 18] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "|", "^", "&", "+", "-", "!", "(", ")", "[", "]",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expression {
+    Number(i64),
+    Register(usize),
+    I,
+    Pc,
+    // The depth of the stack
+    Sp,
+    DelayTimer,
+    SoundTimer,
+    // `[address]`, the byte in the memory
+    Memory(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(&'static str, Box<Expression>, Box<Expression>),
+}
+
+// An expression on the state of the machine, used by the conditional breakpoints:
+//  V3 == 0x10 && I > 0x300
+//  [I + 1] != 0 || SP > 4
+// The values are V0 to VF, I, PC, SP, DT, ST, numbers in decimal or hex with 0x and the bytes of
+// the memory between brackets. True is anything but 0
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    text: String,
+    expression: Expression,
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+        };
+        let expression = parser.binary(0)?;
+        if let Some(token) = tokens.get(parser.position) {
+            return Err(format!("Unexpected {} in the condition", token));
+        }
+        Ok(Condition {
+            text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+            expression,
+        })
+    }
+
+    pub fn evaluate(&self, cpu: &Cpu) -> i64 {
+        evaluate(&self.expression, cpu)
+    }
+
+    pub fn is_true(&self, cpu: &Cpu) -> bool {
+        self.evaluate(cpu) != 0
+    }
+}
+
+fn evaluate(expression: &Expression, cpu: &Cpu) -> i64 {
+    match expression {
+        Expression::Number(value) => *value,
+        Expression::Register(index) => cpu.registers()[*index] as i64,
+        Expression::I => cpu.i_register() as i64,
+        Expression::Pc => cpu.program_counter() as i64,
+        Expression::Sp => cpu.stack().addresses().len() as i64,
+        Expression::DelayTimer => cpu.timers().delay_timer() as i64,
+        Expression::SoundTimer => cpu.timers().sound_timer() as i64,
+        Expression::Memory(address) => {
+            let address = evaluate(address, cpu);
+            match usize::try_from(address) {
+                Ok(address) => cpu.memory().borrow().peek(address) as i64,
+                Err(_) => 0,
+            }
+        }
+        Expression::Not(operand) => (evaluate(operand, cpu) == 0) as i64,
+        Expression::Binary(operator, left, right) => {
+            let left = evaluate(left, cpu);
+            // Like in most languages the right side of && and || isn't always needed
+            match *operator {
+                "&&" if left == 0 => return 0,
+                "||" if left != 0 => return 1,
+                _ => {}
+            }
+            let right = evaluate(right, cpu);
+            match *operator {
+                "&&" | "||" => (right != 0) as i64,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "+" => left.wrapping_add(right),
+                _ => left.wrapping_sub(right),
+            }
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        if let Some(operator) = OPERATORS
+            .iter()
+            .find(|operator| rest.starts_with(**operator))
+        {
+            tokens.push(operator.to_string());
+            rest = &rest[operator.len()..];
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("Unexpected character in {}", text));
+            }
+            tokens.push(rest[..end].to_string());
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn binary(&mut self, level: usize) -> Result<Expression, String> {
+        if level == BINARY_LEVELS.len() {
+            return self.unary();
+        }
+        let mut expression = self.binary(level + 1)?;
+        while let Some(operator) = self.tokens.get(self.position).and_then(|token| {
+            BINARY_LEVELS[level]
+                .iter()
+                .find(|operator| *operator == token)
+        }) {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            expression = Expression::Binary(operator, Box::new(expression), Box::new(right));
+        }
+        Ok(expression)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or("Missing a value in the condition")?;
+        self.position += 1;
+        let expression = match token.as_str() {
+            "!" => Expression::Not(Box::new(self.unary()?)),
+            "-" => Expression::Binary(
+                "-",
+                Box::new(Expression::Number(0)),
+                Box::new(self.unary()?),
+            ),
+            "(" => {
+                let expression = self.binary(0)?;
+                self.close(")")?;
+                expression
+            }
+            "[" => {
+                let address = self.binary(0)?;
+                self.close("]")?;
+                Expression::Memory(Box::new(address))
+            }
+            name => value(name)?,
+        };
+        Ok(expression)
+    }
+
+    fn close(&mut self, bracket: &str) -> Result<(), String> {
+        if self.tokens.get(self.position).map(String::as_str) != Some(bracket) {
+            return Err(format!("Missing {}", bracket));
+        }
+        self.position += 1;
+        Ok(())
+    }
+}
+
+fn value(name: &str) -> Result<Expression, String> {
+    let uppercase = name.to_ascii_uppercase();
+    let expression = match uppercase.as_str() {
+        "I" => Expression::I,
+        "PC" => Expression::Pc,
+        "SP" => Expression::Sp,
+        "DT" => Expression::DelayTimer,
+        "ST" => Expression::SoundTimer,
+        _ => {
+            if let Some(digit) = uppercase.strip_prefix('V')
+                && digit.len() == 1
+                && let Ok(index) = usize::from_str_radix(digit, 16)
+            {
+                return Ok(Expression::Register(index));
+            }
+            let parsed = match uppercase.strip_prefix("0X") {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None => uppercase.parse(),
+            };
+            Expression::Number(parsed.map_err(|_| format!("Unknown value: {}", name))?)
+        }
+    };
+    Ok(expression)
+}
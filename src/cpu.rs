@@ -0,0 +1,722 @@
+use crate::audio::{AUDIO_PATTERN_SIZE, AudioPattern, DEFAULT_PITCH};
+use crate::display::{Display, DisplayTrait};
+use crate::instruction::Instruction;
+use crate::keyboard::{Keyboard, KeyboardTrait};
+use crate::memory::{
+    BIG_FONT_CHARACTER_SIZE, BIG_FONT_START_ADDRESS, FONT_CHARACTER_SIZE, FONT_START_ADDRESS,
+    Memory, MemoryTrait,
+};
+use crate::quirks::{IndexIncrement, Platform, Quirks};
+use crate::random::{RandomSource, SeededRandom};
+use crate::save_state::{
+    SAVE_STATE_MAGIC, SAVE_STATE_VERSION, StateError, StateReader, StateWriter,
+};
+use crate::stack::{Stack, StackTrait};
+use crate::timers::{Timers, TimersTrait};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+pub const PROGRAM_START: usize = 0x200;
+// VF doubles as the carry, borrow, shift and collision flag
+const FLAG_REGISTER: usize = 0xF;
+// The HP-48 calculators running the SUPER-CHIP had 8 of them, the XO-CHIP uses 16
+pub const RPL_FLAGS_SIZE: usize = 16;
+
+// What happened on a single call to `Cpu::step`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    // The instruction was fetched and executed
+    Executed(u16),
+    // FX0A is blocking the program counter until a key is pressed, the instruction will be
+    // retried on the next step
+    WaitingForKey,
+    // With the display wait quirk nothing else runs after a draw until the frame ends
+    WaitingForVerticalBlank,
+    // The program counter walked past the end of the memory, nothing else can be executed
+    Halted,
+    // The program asked to exit with 00FD
+    Exited,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    StackOverflow { program_counter: usize },
+    StackUnderflow { program_counter: usize },
+    UnknownOpcode { opcode: u16, program_counter: usize },
+    // FX75 or FX85 past the RPL flags of the platform
+    RplFlagsOutOfRange { x: usize, program_counter: usize },
+}
+
+impl CpuError {
+    // The address of the instruction that failed
+    pub fn program_counter(&self) -> usize {
+        match *self {
+            CpuError::StackOverflow { program_counter }
+            | CpuError::StackUnderflow { program_counter }
+            | CpuError::UnknownOpcode {
+                program_counter, ..
+            }
+            | CpuError::RplFlagsOutOfRange {
+                program_counter, ..
+            } => program_counter,
+        }
+    }
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::StackOverflow { program_counter } => {
+                write!(f, "Stack overflow at 0x{:03X}", program_counter)
+            }
+            CpuError::StackUnderflow { program_counter } => {
+                write!(f, "Return with an empty stack at 0x{:03X}", program_counter)
+            }
+            CpuError::UnknownOpcode {
+                opcode,
+                program_counter,
+            } => write!(
+                f,
+                "Unknown opcode 0x{:04X} at 0x{:03X}",
+                opcode, program_counter
+            ),
+            CpuError::RplFlagsOutOfRange { x, program_counter } => {
+                write!(f, "No RPL flag for V{:X} at 0x{:03X}", x, program_counter)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+pub struct Cpu<M = Memory> {
+    program_counter: usize,
+    registers: [u8; 16],
+    i_register: usize,
+    timers: Timers,
+    stack: Stack,
+    memory: Rc<RefCell<M>>,
+    display: Display<M>,
+    keyboard: Keyboard,
+    rng: Box<dyn RandomSource>,
+    quirks: Quirks,
+    waiting_for_vertical_blank: bool,
+    // The SUPER-CHIP saves them in the calculator, so they survive between runs of a program
+    rpl_flags: [u8; RPL_FLAGS_SIZE],
+    exited: bool,
+    // Loaded by the XO-CHIP F002, the buzzer plays the tone until then
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    pitch: u8,
+    // Hash of the ROM being run, save states of other ROMs are rejected
+    rom_hash: u32,
+}
+
+impl<M> fmt::Display for Cpu<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "CPU")?;
+        writeln!(f, "====================")?;
+        writeln!(
+            f,
+            "PC: 0x{:03X}  I: 0x{:03X}",
+            self.program_counter, self.i_register
+        )?;
+        writeln!(
+            f,
+            "Delay timer: {}  Sound timer: {}",
+            self.timers.delay_timer(),
+            self.timers.sound_timer()
+        )?;
+        for (index, value) in self.registers.iter().enumerate() {
+            write!(f, "V{:X}: {:02X} ", index, value)?;
+            if index % 8 == 7 {
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<M: MemoryTrait> Cpu<M> {
+    pub fn new(memory: Rc<RefCell<M>>) -> Cpu<M> {
+        let display = Display::new(Rc::clone(&memory));
+        Cpu {
+            program_counter: PROGRAM_START,
+            registers: [0x00; 16],
+            i_register: 0x00,
+            timers: Timers::new(),
+            stack: Stack::new(),
+            memory,
+            display,
+            keyboard: Keyboard::new(),
+            rng: Box::new(SeededRandom::from_entropy()),
+            quirks: Quirks::default(),
+            waiting_for_vertical_blank: false,
+            rpl_flags: [0x00; RPL_FLAGS_SIZE],
+            exited: false,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            rom_hash: 0,
+        }
+    }
+
+    pub fn program_counter(&self) -> usize {
+        self.program_counter
+    }
+
+    pub fn set_program_counter(&mut self, program_counter: usize) {
+        self.program_counter = program_counter;
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn set_register(&mut self, index: usize, value: u8) {
+        self.registers[index] = value;
+    }
+
+    pub fn i_register(&self) -> usize {
+        self.i_register
+    }
+
+    pub fn set_i_register(&mut self, i_register: usize) {
+        self.i_register = i_register;
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    pub fn stack_mut(&mut self) -> &mut Stack {
+        &mut self.stack
+    }
+
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
+    }
+
+    pub fn keyboard_mut(&mut self) -> &mut Keyboard {
+        &mut self.keyboard
+    }
+
+    // Makes the CXNN results the same on every run with the same seed
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Box::new(SeededRandom::new(seed));
+    }
+
+    // Replaces the generator used by CXNN, like a scripted one in tests
+    pub fn set_random_source(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng = rng;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.display.set_clip_sprites(quirks.clip_sprites);
+    }
+
+    // Called when a frame ends, releases a program blocked by the display wait quirk
+    pub fn vertical_blank(&mut self) {
+        self.waiting_for_vertical_blank = false;
+    }
+
+    pub fn rpl_flags(&self) -> &[u8; RPL_FLAGS_SIZE] {
+        &self.rpl_flags
+    }
+
+    pub fn set_rpl_flags(&mut self, rpl_flags: [u8; RPL_FLAGS_SIZE]) {
+        self.rpl_flags = rpl_flags;
+    }
+
+    pub fn audio_pattern(&self) -> Option<AudioPattern> {
+        self.audio_pattern.map(|buffer| AudioPattern {
+            buffer,
+            pitch: self.pitch,
+        })
+    }
+
+    pub fn rom_hash(&self) -> u32 {
+        self.rom_hash
+    }
+
+    // Set by the frontend after loading the ROM, see `rom::rom_hash`
+    pub fn set_rom_hash(&mut self, rom_hash: u32) {
+        self.rom_hash = rom_hash;
+    }
+
+    pub fn memory(&self) -> &Rc<RefCell<M>> {
+        &self.memory
+    }
+
+    pub fn display(&self) -> &Display<M> {
+        &self.display
+    }
+
+    pub fn display_mut(&mut self) -> &mut Display<M> {
+        &mut self.display
+    }
+
+    pub fn timers(&self) -> &Timers {
+        &self.timers
+    }
+
+    pub fn timers_mut(&mut self) -> &mut Timers {
+        &mut self.timers
+    }
+
+    // Fetches the instruction pointed by the program counter and executes it
+    pub fn step(&mut self) -> Result<StepOutcome, CpuError> {
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+        if self.program_counter + 1 >= self.memory.borrow().size() {
+            return Ok(StepOutcome::Halted);
+        }
+        if self.waiting_for_vertical_blank {
+            return Ok(StepOutcome::WaitingForVerticalBlank);
+        }
+
+        // Fetching isn't a read of the program, the debugger doesn't see it
+        let first_byte = self.memory.borrow().peek(self.program_counter) as u16;
+        let second_byte = self.memory.borrow().peek(self.program_counter + 1) as u16;
+        let opcode = (first_byte << 8) + second_byte;
+        // The instructions of newer platforms are unknown, apart from the 00NN ones that become
+        // machine code routines and are ignored like every other one
+        let instruction =
+            Instruction::decode_for_platform(opcode, self.quirks.platform).map_err(|_| {
+                CpuError::UnknownOpcode {
+                    opcode,
+                    program_counter: self.program_counter,
+                }
+            })?;
+        self.program_counter += 2;
+
+        self.execute(opcode, instruction)
+    }
+
+    fn execute(&mut self, opcode: u16, instruction: Instruction) -> Result<StepOutcome, CpuError> {
+        let registers = &mut self.registers;
+        match instruction {
+            Instruction::MachineCodeRoutine { .. } => {
+                // Calls to the native code of the COSMAC VIP, ignored by modern interpreters
+            }
+            Instruction::Clear => {
+                self.display.clear();
+            }
+            Instruction::Return => {
+                if self.stack.size() == 0 {
+                    return Err(CpuError::StackUnderflow {
+                        program_counter: self.program_counter - 2,
+                    });
+                }
+                self.program_counter = self.stack.pop() as usize;
+            }
+            Instruction::Call { nnn } => {
+                if !self.stack.push(self.program_counter as u16) {
+                    return Err(CpuError::StackOverflow {
+                        program_counter: self.program_counter - 2,
+                    });
+                }
+                self.program_counter = nnn as usize;
+            }
+            Instruction::SkipIfXEqualsNn { x, nn } => {
+                if registers[x] == nn {
+                    self.skip_next_instruction();
+                }
+            }
+            Instruction::SkipIfXDiffersNn { x, nn } => {
+                if registers[x] != nn {
+                    self.skip_next_instruction();
+                }
+            }
+            Instruction::SkipIfXEqualsY { x, y } => {
+                if registers[x] == registers[y] {
+                    self.skip_next_instruction();
+                }
+            }
+            Instruction::SkipIfXDiffersY { x, y } => {
+                if registers[x] != registers[y] {
+                    self.skip_next_instruction();
+                }
+            }
+            Instruction::StoreYToX { x, y } => {
+                registers[x] = registers[y];
+            }
+            Instruction::AndYToX { x, y } => {
+                registers[x] &= registers[y];
+                if self.quirks.logic_resets_vf {
+                    registers[FLAG_REGISTER] = 0;
+                }
+            }
+            Instruction::XorYToX { x, y } => {
+                registers[x] ^= registers[y];
+                if self.quirks.logic_resets_vf {
+                    registers[FLAG_REGISTER] = 0;
+                }
+            }
+            Instruction::OrYToX { x, y } => {
+                registers[x] |= registers[y];
+                if self.quirks.logic_resets_vf {
+                    registers[FLAG_REGISTER] = 0;
+                }
+            }
+            Instruction::SkipIfKeyOnXPressed { x } => {
+                if self.keyboard.is_key_pressed(registers[x]) {
+                    self.skip_next_instruction();
+                }
+            }
+            Instruction::SkipIfKeyOnXNotPressed { x } => {
+                if !self.keyboard.is_key_pressed(registers[x]) {
+                    self.skip_next_instruction();
+                }
+            }
+            Instruction::SumYToX { x, y } => {
+                let sum = registers[x] as u16 + registers[y] as u16;
+
+                // The flag is written after the result, so with X being F the carry wins
+                registers[x] = (sum & 0xFF) as u8; // Store lower 8 bits
+                registers[FLAG_REGISTER] = (sum > 255) as u8;
+            }
+            Instruction::ShiftRightX { x, y } => {
+                if self.quirks.shift_uses_vy {
+                    registers[x] = registers[y];
+                }
+                // 0x01 represents 0000 0001 so we are checking if the last bit is 1
+                // this mask only validate the last bit ignoring the rest transforming them in 0
+                let flag = registers[x] & 0x01;
+
+                registers[x] >>= 1;
+                registers[FLAG_REGISTER] = flag;
+            }
+            Instruction::ShiftLeftX { x, y } => {
+                if self.quirks.shift_uses_vy {
+                    registers[x] = registers[y];
+                }
+                // 0x80 represents 1000 0000 so we are checking if the first bit is 1
+                // this mask only validate the first bit ignoring the rest transforming them in 0
+                let flag = registers[x] >> 7;
+
+                registers[x] <<= 1;
+                registers[FLAG_REGISTER] = flag;
+            }
+            Instruction::SubtractYFromX { x, y } => {
+                // 1 when there is no borrow
+                let flag = (registers[x] >= registers[y]) as u8;
+
+                registers[x] = registers[x].wrapping_sub(registers[y]);
+                registers[FLAG_REGISTER] = flag;
+            }
+            Instruction::SubtractXFromY { x, y } => {
+                let flag = (registers[y] >= registers[x]) as u8;
+
+                registers[x] = registers[y].wrapping_sub(registers[x]);
+                registers[FLAG_REGISTER] = flag;
+            }
+            Instruction::SumNnToX { x, nn } => {
+                // There is no carry, the register wraps around
+                registers[x] = registers[x].wrapping_add(nn);
+            }
+            Instruction::SetNnnToI { nnn } => {
+                self.i_register = nnn as usize;
+            }
+            Instruction::SetNnToX { x, nn } => {
+                registers[x] = nn;
+            }
+            Instruction::RandomAndNnToX { x, nn } => {
+                registers[x] = self.rng.next_byte() & nn;
+            }
+            Instruction::JumpToNnnPlusV0 { nnn } => {
+                // With the quirk the highest nibble of NNN is also the register, BXNN
+                let register = if self.quirks.jump_uses_vx {
+                    (nnn >> 8) as usize
+                } else {
+                    0
+                };
+                self.program_counter = nnn as usize + registers[register] as usize;
+            }
+            Instruction::Jump { nnn } => {
+                self.program_counter = nnn as usize;
+            }
+            Instruction::Draw { x, y, n } => {
+                let collision = if n == 0 && self.quirks.platform >= Platform::SuperChip {
+                    self.display.draw_large(
+                        registers[x] as usize,
+                        registers[y] as usize,
+                        self.i_register,
+                    )
+                } else {
+                    self.display.draw(
+                        registers[x] as usize,
+                        registers[y] as usize,
+                        n as usize,
+                        self.i_register,
+                    )
+                };
+                registers[FLAG_REGISTER] = collision as u8;
+                self.waiting_for_vertical_blank = self.quirks.display_wait;
+            }
+            Instruction::SetDelayTimerToX { x } => {
+                registers[x] = self.timers.delay_timer();
+            }
+            Instruction::WaitKeyToX { x } => match self.keyboard.get_key_pressed() {
+                Some(key) => registers[x] = key,
+                None => {
+                    // Nothing pressed yet, go back to this same instruction so the next step
+                    // checks the keyboard again
+                    self.program_counter -= 2;
+                    return Ok(StepOutcome::WaitingForKey);
+                }
+            },
+            Instruction::SetXToDelayTimer { x } => {
+                self.timers.set_delay_timer(registers[x]);
+            }
+            Instruction::SetXToSoundTimer { x } => {
+                self.timers.set_sound_timer(registers[x]);
+            }
+            Instruction::SumXToI { x } => {
+                // I is a 12 bits address, anything above it wraps back to the start of the memory
+                self.i_register = (self.i_register + registers[x] as usize) & self.address_mask();
+            }
+            Instruction::SetIToFontOfX { x } => {
+                // Only the lowest nibble is used as there are only 16 characters (0 to F) loaded
+                let character = (registers[x] & 0x0F) as usize;
+                self.i_register = FONT_START_ADDRESS + character * FONT_CHARACTER_SIZE;
+            }
+            Instruction::StoreBcdOfXOnI { x } => {
+                // 254 is stored as 2, 5 and 4
+                let value = registers[x];
+                let mut memory = self.memory.borrow_mut();
+                memory.write(self.i_register, value / 100);
+                memory.write(self.i_register + 1, (value / 10) % 10);
+                memory.write(self.i_register + 2, value % 10);
+            }
+            Instruction::StoreV0ToXOnI { x } => {
+                {
+                    let mut memory = self.memory.borrow_mut();
+                    for (index, value) in registers.iter().enumerate().take(x + 1) {
+                        memory.write(self.i_register + index, *value);
+                    }
+                }
+                self.increment_i_after_load_or_store(x);
+            }
+            Instruction::LoadV0ToXFromI { x } => {
+                {
+                    let memory = self.memory.borrow();
+                    for (index, register) in registers.iter_mut().enumerate().take(x + 1) {
+                        *register = memory.retrieve(self.i_register + index);
+                    }
+                }
+                self.increment_i_after_load_or_store(x);
+            }
+            Instruction::ScrollDown { n } => self.display.scroll_down(n as usize),
+            Instruction::ScrollRight => self.display.scroll_right(4),
+            Instruction::ScrollLeft => self.display.scroll_left(4),
+            Instruction::Exit => {
+                self.exited = true;
+                return Ok(StepOutcome::Exited);
+            }
+            Instruction::LowResolution => self.display.set_hires(false),
+            Instruction::HighResolution => self.display.set_hires(true),
+            Instruction::SetIToBigFontOfX { x } => {
+                let character = (registers[x] & 0x0F) as usize;
+                self.i_register = BIG_FONT_START_ADDRESS + character * BIG_FONT_CHARACTER_SIZE;
+            }
+            Instruction::StoreV0ToXOnFlags { x } => {
+                check_rpl_flags(self.quirks.platform, x, self.program_counter)?;
+                self.rpl_flags[..=x].copy_from_slice(&registers[..=x]);
+            }
+            Instruction::LoadV0ToXFromFlags { x } => {
+                check_rpl_flags(self.quirks.platform, x, self.program_counter)?;
+                registers[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+            }
+            Instruction::ScrollUp { n } => self.display.scroll_up(n as usize),
+            Instruction::StoreXToYOnI { x, y } => {
+                // Unlike FX55 the range can go backwards and I is never changed
+                let mut memory = self.memory.borrow_mut();
+                for (offset, register) in register_range(x, y).enumerate() {
+                    memory.write(self.i_register + offset, registers[register]);
+                }
+            }
+            Instruction::LoadXToYFromI { x, y } => {
+                let memory = self.memory.borrow();
+                for (offset, register) in register_range(x, y).enumerate() {
+                    registers[register] = memory.retrieve(self.i_register + offset);
+                }
+            }
+            Instruction::SetNextWordToI => {
+                let memory = self.memory.borrow();
+                let first_byte = memory.peek(self.program_counter) as usize;
+                let second_byte = memory.peek(self.program_counter + 1) as usize;
+                self.i_register = (first_byte << 8) + second_byte;
+                self.program_counter += 2;
+            }
+            Instruction::SelectPlanes { n } => self.display.select_planes(n),
+            Instruction::LoadAudioPattern => {
+                let memory = self.memory.borrow();
+                let mut buffer = [0x00; AUDIO_PATTERN_SIZE];
+                for (index, value) in buffer.iter_mut().enumerate() {
+                    *value = memory.retrieve(self.i_register + index);
+                }
+                self.audio_pattern = Some(buffer);
+            }
+            Instruction::SetPitchToX { x } => {
+                self.pitch = registers[x];
+            }
+        }
+
+        Ok(StepOutcome::Executed(opcode))
+    }
+
+    // I can point anywhere in the 64 KiB of the XO-CHIP, the others are limited to 12 bits
+    fn address_mask(&self) -> usize {
+        if self.quirks.platform >= Platform::XoChip {
+            0xFFFF
+        } else {
+            0x0FFF
+        }
+    }
+
+    // The XO-CHIP F000 NNNN is 4 bytes long, skipping it has to jump over the address too
+    fn skip_next_instruction(&mut self) {
+        if self.quirks.platform >= Platform::XoChip {
+            let memory = self.memory.borrow();
+            let first_byte = memory.peek(self.program_counter) as u16;
+            let second_byte = memory.peek(self.program_counter + 1) as u16;
+            if (first_byte << 8) + second_byte == 0xF000 {
+                self.program_counter += 2;
+            }
+        }
+        self.program_counter += 2;
+    }
+
+    fn increment_i_after_load_or_store(&mut self, x: usize) {
+        match self.quirks.index_increment {
+            IndexIncrement::Unchanged => {}
+            IndexIncrement::ByX => self.i_register += x,
+            IndexIncrement::ByXPlusOne => self.i_register += x + 1,
+        }
+    }
+}
+
+// The save states hold the whole memory, so they need the real one
+impl Cpu {
+    // A snapshot of the whole machine, the keyboard is left out as it belongs to the host
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.write_bytes(SAVE_STATE_MAGIC);
+        writer.write_u16(SAVE_STATE_VERSION);
+        writer.write_u32(self.rom_hash);
+
+        self.quirks.save_state(&mut writer);
+        writer.write_u32(self.program_counter as u32);
+        writer.write_u32(self.i_register as u32);
+        writer.write_bytes(&self.registers);
+        writer.write_bool(self.waiting_for_vertical_blank);
+        writer.write_bool(self.exited);
+        writer.write_bytes(&self.rpl_flags);
+        writer.write_bool(self.audio_pattern.is_some());
+        writer.write_bytes(&self.audio_pattern.unwrap_or_default());
+        writer.write_u8(self.pitch);
+        self.stack.save_state(&mut writer);
+        self.timers.save_state(&mut writer);
+        self.memory.borrow().save_state(&mut writer);
+        self.display.save_state(&mut writer);
+        // The size goes first so the state can be checked before the generator is touched
+        let mut rng_writer = StateWriter::new();
+        self.rng.save_state(&mut rng_writer);
+        let rng_state = rng_writer.into_bytes();
+        writer.write_u32(rng_state.len() as u32);
+        writer.write_bytes(&rng_state);
+        writer.into_bytes()
+    }
+
+    // Everything is read before the machine is touched, so a broken state leaves it as it was
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader::new(data);
+        if reader.read_bytes(SAVE_STATE_MAGIC.len())? != SAVE_STATE_MAGIC {
+            return Err(StateError::InvalidFormat);
+        }
+        let version = reader.read_u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let rom_hash = reader.read_u32()?;
+        if rom_hash != self.rom_hash {
+            return Err(StateError::WrongRom {
+                expected: self.rom_hash,
+                found: rom_hash,
+            });
+        }
+
+        let quirks = Quirks::load_state(&mut reader)?;
+        let program_counter = reader.read_u32()? as usize;
+        let i_register = reader.read_u32()? as usize;
+        let registers = reader.read_array()?;
+        let waiting_for_vertical_blank = reader.read_bool()?;
+        let exited = reader.read_bool()?;
+        let rpl_flags = reader.read_array()?;
+        let has_audio_pattern = reader.read_bool()?;
+        let audio_pattern = reader.read_array()?;
+        let pitch = reader.read_u8()?;
+        let stack = Stack::load_state(&mut reader)?;
+        let timers = Timers::load_state(&mut reader)?;
+        let memory = Memory::load_state(&mut reader)?;
+        let display = Display::load_state(&mut reader, Rc::clone(&self.memory))?;
+        let rng_state_size = reader.read_u32()? as usize;
+        let rng_state = reader.read_bytes(rng_state_size)?;
+        reader.finish()?;
+        // The generator is behind a trait and can only be loaded in place, so it is put back as
+        // it was when its part of the state is broken. Nothing else has been changed yet
+        let mut backup = StateWriter::new();
+        self.rng.save_state(&mut backup);
+        let mut rng_reader = StateReader::new(rng_state);
+        let loaded = self
+            .rng
+            .load_state(&mut rng_reader)
+            .and_then(|()| rng_reader.finish());
+        if let Err(e) = loaded {
+            self.rng
+                .load_state(&mut StateReader::new(&backup.into_bytes()))?;
+            return Err(e);
+        }
+
+        *self.memory.borrow_mut() = memory;
+        self.program_counter = program_counter;
+        self.i_register = i_register;
+        self.registers = registers;
+        self.waiting_for_vertical_blank = waiting_for_vertical_blank;
+        self.exited = exited;
+        self.rpl_flags = rpl_flags;
+        self.audio_pattern = has_audio_pattern.then_some(audio_pattern);
+        self.pitch = pitch;
+        self.stack = stack;
+        self.timers = timers;
+        self.display = display;
+        self.set_quirks(quirks);
+        Ok(())
+    }
+}
+
+// The registers from X to Y, going backwards when X is bigger
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
+
+// The program counter is already on the next instruction
+fn check_rpl_flags(platform: Platform, x: usize, program_counter: usize) -> Result<(), CpuError> {
+    if x >= platform.rpl_flags() {
+        return Err(CpuError::RplFlagsOutOfRange {
+            x,
+            program_counter: program_counter - 2,
+        });
+    }
+    Ok(())
+}
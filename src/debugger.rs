@@ -0,0 +1,513 @@
+use crate::condition::Condition;
+use crate::cpu::{Cpu, PROGRAM_START};
+use crate::instruction::Instruction;
+use crate::memory::{Access, AccessKind, MemoryTrait};
+use crate::timers::TimersTrait;
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub const HELP: &str = "break <ADDR> [if <EXPR>], delete <ADDR>, \
+                        watch read|write|change <ADDR>[-<ADDR>], watch if <EXPR>, unwatch <N>, \
+                        step, next, finish, continue, quit \
+                        (addresses in hex, an empty line repeats the last command)";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Break(usize),
+    // Only pauses at the address when the condition is true
+    BreakIf(usize, Condition),
+    Delete(usize),
+    Watch(Watchpoint),
+    // Removes a watchpoint by its number
+    Unwatch(usize),
+    // Runs one instruction
+    Step,
+    // Runs one instruction, a whole subroutine for a CALL
+    Next,
+    // Runs until the current subroutine returns
+    Finish,
+    Continue,
+    Help,
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    // Only the writes of a different value
+    Change,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Watchpoint {
+    // Both ends are included
+    Memory {
+        kind: WatchKind,
+        start: usize,
+        end: usize,
+    },
+    // Pauses when the condition becomes true, wherever PC is
+    Condition(Condition),
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watchpoint::Memory { kind, start, end } => {
+                let kind = match kind {
+                    WatchKind::Read => "read",
+                    WatchKind::Write => "write",
+                    WatchKind::Change => "change",
+                };
+                write!(f, "{} 0x{:03X}", kind, start)?;
+                if end != start {
+                    write!(f, "-0x{:03X}", end)?;
+                }
+                Ok(())
+            }
+            Watchpoint::Condition(condition) => write!(f, "if {}", condition),
+        }
+    }
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let line = line.trim();
+        let (name, arguments) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let arguments = arguments.trim();
+        let mut words = arguments.split_whitespace();
+        let mut address = || -> Result<usize, String> {
+            let word = words
+                .next()
+                .ok_or_else(|| format!("{} needs an address", name))?;
+            parse_address(word)
+        };
+        let command = match name {
+            "b" | "break" => {
+                let address = address()?;
+                match words.next() {
+                    Some("if") => {
+                        let expression = arguments.split_once(" if ").unwrap_or_default().1;
+                        return Ok(Command::BreakIf(address, Condition::parse(expression)?));
+                    }
+                    Some(_) => return Err(format!("Too many arguments: {}", line)),
+                    None => Command::Break(address),
+                }
+            }
+            "d" | "delete" => Command::Delete(address()?),
+            "w" | "watch" => {
+                let kind = match words.next() {
+                    Some("if") => {
+                        let expression = arguments.strip_prefix("if").unwrap_or_default();
+                        let condition = Condition::parse(expression)?;
+                        return Ok(Command::Watch(Watchpoint::Condition(condition)));
+                    }
+                    Some("read") => WatchKind::Read,
+                    Some("write") => WatchKind::Write,
+                    Some("change") => WatchKind::Change,
+                    _ => return Err("watch needs read, write, change or if".to_string()),
+                };
+                let range = words
+                    .next()
+                    .ok_or_else(|| "watch needs an address".to_string())?;
+                let (start, end) = match range.split_once('-') {
+                    Some((start, end)) => (parse_address(start)?, parse_address(end)?),
+                    None => (parse_address(range)?, parse_address(range)?),
+                };
+                if end < start {
+                    return Err(format!("Invalid range: {}", range));
+                }
+                // Nothing writes to the interpreter area, a range reaching past it only watches
+                // the part that can be written
+                let start = match kind {
+                    WatchKind::Read => start,
+                    _ if end < PROGRAM_START => {
+                        return Err("The interpreter area below 0x200 can't be written".to_string());
+                    }
+                    _ => start.max(PROGRAM_START),
+                };
+                Command::Watch(Watchpoint::Memory { kind, start, end })
+            }
+            "u" | "unwatch" => {
+                let word = words
+                    .next()
+                    .ok_or_else(|| "unwatch needs a number".to_string())?;
+                Command::Unwatch(
+                    word.parse()
+                        .map_err(|_| format!("Invalid watchpoint: {}", word))?,
+                )
+            }
+            "s" | "step" => Command::Step,
+            "n" | "next" => Command::Next,
+            "f" | "finish" => Command::Finish,
+            "c" | "continue" => Command::Continue,
+            "h" | "help" => Command::Help,
+            "q" | "quit" => Command::Quit,
+            _ => return Err(format!("Unknown command: {}", line)),
+        };
+        if words.next().is_some() {
+            return Err(format!("Too many arguments: {}", line));
+        }
+        Ok(command)
+    }
+}
+
+fn parse_address(word: &str) -> Result<usize, String> {
+    let digits = word.strip_prefix("0x").unwrap_or(word);
+    usize::from_str_radix(digits, 16).map_err(|_| format!("Invalid address: {}", word))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    Continue,
+    Step,
+    // Until the stack is back to this depth, after a CALL or to leave the subroutine
+    UntilDepth(usize),
+}
+
+struct Watch {
+    watchpoint: Watchpoint,
+    // The conditions only pause when they become true, not on every instruction after
+    was_true: bool,
+}
+
+// Decides before every instruction whether the machine stops, the frontend asks it through
+// `Scheduler::run_frame_until` and reads the commands while it is paused
+pub struct Debugger {
+    breakpoints: BTreeMap<usize, Option<Condition>>,
+    watches: BTreeMap<usize, Watch>,
+    next_watch: usize,
+    // The address and the mnemonic of the last instruction that ran, the watchpoints are hit
+    // after it
+    previous: Option<(usize, String)>,
+    // None while paused
+    resume: Option<Resume>,
+    // The instruction the machine was paused on runs without checking, so a breakpoint can be
+    // left
+    resuming: bool,
+    stop_reason: String,
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    // Starts paused, before the first instruction
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: BTreeMap::new(),
+            watches: BTreeMap::new(),
+            next_watch: 1,
+            previous: None,
+            resume: None,
+            resuming: false,
+            stop_reason: "Paused".to_string(),
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.resume.is_none()
+    }
+
+    pub fn pause(&mut self, reason: &str) {
+        self.resume = None;
+        self.stop_reason = reason.to_string();
+    }
+
+    // Why the machine is paused, like the breakpoint that was hit
+    pub fn stop_reason(&self) -> &str {
+        &self.stop_reason
+    }
+
+    pub fn breakpoints(&self) -> &BTreeMap<usize, Option<Condition>> {
+        &self.breakpoints
+    }
+
+    // By their number, which `unwatch` takes
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.watches
+            .iter()
+            .map(|(number, watch)| (*number, &watch.watchpoint))
+    }
+
+    // Returns the message shown to the user, quitting is left to the frontend
+    pub fn run_command(&mut self, command: Command, cpu: &Cpu) -> Result<String, String> {
+        let depth = cpu.stack().addresses().len();
+        let resume = match command {
+            Command::Break(address) => {
+                self.breakpoints.insert(address, None);
+                return Ok(format!("Breakpoint at 0x{:03X}", address));
+            }
+            Command::BreakIf(address, condition) => {
+                let message = format!("Breakpoint at 0x{:03X} if {}", address, condition);
+                self.breakpoints.insert(address, Some(condition));
+                return Ok(message);
+            }
+            Command::Delete(address) => {
+                if self.breakpoints.remove(&address).is_none() {
+                    return Err(format!("No breakpoint at 0x{:03X}", address));
+                }
+                return Ok(format!("Deleted the breakpoint at 0x{:03X}", address));
+            }
+            Command::Watch(watchpoint) => {
+                let number = self.next_watch;
+                self.next_watch += 1;
+                let message = format!("Watchpoint {}: {}", number, watchpoint);
+                let was_true = match &watchpoint {
+                    Watchpoint::Condition(condition) => condition.is_true(cpu),
+                    Watchpoint::Memory { .. } => false,
+                };
+                self.watches.insert(
+                    number,
+                    Watch {
+                        watchpoint,
+                        was_true,
+                    },
+                );
+                return Ok(message);
+            }
+            Command::Unwatch(number) => {
+                if self.watches.remove(&number).is_none() {
+                    return Err(format!("No watchpoint {}", number));
+                }
+                return Ok(format!("Deleted the watchpoint {}", number));
+            }
+            Command::Step => Resume::Step,
+            Command::Next => match current_instruction(cpu) {
+                Some(Instruction::Call { .. }) => Resume::UntilDepth(depth),
+                _ => Resume::Step,
+            },
+            Command::Finish => {
+                if depth == 0 {
+                    return Err("Not in a subroutine".to_string());
+                }
+                Resume::UntilDepth(depth - 1)
+            }
+            Command::Continue => Resume::Continue,
+            Command::Help => return Ok(HELP.to_string()),
+            Command::Quit => return Ok(String::new()),
+        };
+        self.resume = Some(resume);
+        self.resuming = true;
+        Ok(String::new())
+    }
+
+    // Asked before every instruction while running
+    pub fn should_pause(&mut self, cpu: &Cpu) -> bool {
+        let Some(resume) = self.resume else {
+            return true;
+        };
+        // Loading a state replaces the memory, so the recording is turned on again every time
+        let accesses = {
+            let mut memory = cpu.memory().borrow_mut();
+            let accesses = memory.take_accesses();
+            memory.record_accesses(self.watches_memory());
+            accesses
+        };
+        let previous = self.previous.replace((
+            cpu.program_counter(),
+            current_instruction(cpu)
+                .map(|instruction| instruction.mnemonic(cpu.quirks()))
+                .unwrap_or_else(|| "???".to_string()),
+        ));
+        if self.resuming {
+            // Whatever was read while paused isn't from the program
+            self.resuming = false;
+            self.update_conditions(cpu);
+            return false;
+        }
+
+        let program_counter = cpu.program_counter();
+        let reason = if let Some(reason) = self.check_watches(cpu, &accesses, previous) {
+            reason
+        } else if let Some(condition) = self.breakpoints.get(&program_counter)
+            && condition
+                .as_ref()
+                .is_none_or(|condition| condition.is_true(cpu))
+        {
+            format!("Breakpoint at 0x{:03X}", program_counter)
+        } else {
+            match resume {
+                Resume::Continue => return false,
+                Resume::Step => "Stepped".to_string(),
+                Resume::UntilDepth(depth) if cpu.stack().addresses().len() <= depth => {
+                    "Returned".to_string()
+                }
+                Resume::UntilDepth(_) => return false,
+            }
+        };
+        self.pause(&reason);
+        true
+    }
+
+    fn watches_memory(&self) -> bool {
+        self.watches
+            .values()
+            .any(|watch| matches!(watch.watchpoint, Watchpoint::Memory { .. }))
+    }
+
+    fn update_conditions(&mut self, cpu: &Cpu) {
+        for watch in self.watches.values_mut() {
+            if let Watchpoint::Condition(condition) = &watch.watchpoint {
+                watch.was_true = condition.is_true(cpu);
+            }
+        }
+    }
+
+    // The first watchpoint hit by the previous instruction
+    fn check_watches(
+        &mut self,
+        cpu: &Cpu,
+        accesses: &[Access],
+        previous: Option<(usize, String)>,
+    ) -> Option<String> {
+        let (address, mnemonic) = previous?;
+        let by = format!("0x{:03X}: {}", address, mnemonic);
+        let mut hit = None;
+        for access in accesses {
+            hit = self
+                .watches
+                .values()
+                .find_map(|watch| match watch.watchpoint {
+                    Watchpoint::Memory { kind, start, end }
+                        if (start..=end).contains(&access.address)
+                            && memory_watch_hit(kind, access) =>
+                    {
+                        Some(describe_access(access, &by))
+                    }
+                    _ => None,
+                });
+            if hit.is_some() {
+                break;
+            }
+        }
+        for watch in self.watches.values_mut() {
+            if let Watchpoint::Condition(condition) = &watch.watchpoint {
+                let is_true = condition.is_true(cpu);
+                if is_true && !watch.was_true && hit.is_none() {
+                    hit = Some(format!("{} became true after {}", condition, by));
+                }
+                watch.was_true = is_true;
+            }
+        }
+        hit
+    }
+}
+
+fn memory_watch_hit(kind: WatchKind, access: &Access) -> bool {
+    match (kind, access.kind) {
+        (WatchKind::Read, AccessKind::Read) | (WatchKind::Write, AccessKind::Write) => true,
+        (WatchKind::Change, AccessKind::Write) => access.old_value != access.value,
+        _ => false,
+    }
+}
+
+fn describe_access(access: &Access, by: &str) -> String {
+    match access.kind {
+        AccessKind::Read => format!(
+            "Read of 0x{:02X} at 0x{:03X} by {}",
+            access.value, access.address, by
+        ),
+        AccessKind::Write => format!(
+            "Write of 0x{:02X} at 0x{:03X} (was 0x{:02X}) by {}",
+            access.value, access.address, access.old_value, by
+        ),
+    }
+}
+
+fn current_instruction(cpu: &Cpu) -> Option<Instruction> {
+    let opcode = read_word(cpu, cpu.program_counter());
+    Instruction::decode_for_platform(opcode, cpu.quirks().platform).ok()
+}
+
+fn read_word(cpu: &Cpu, address: usize) -> u16 {
+    // Peeked, the watchpoints would see the reads otherwise
+    let memory = cpu.memory().borrow();
+    u16::from_be_bytes([memory.peek(address), memory.peek(address + 1)])
+}
+
+// The V registers, I, PC, the timers and the stack from the bottom
+pub fn registers_pane(cpu: &Cpu) -> Vec<String> {
+    let mut lines: Vec<String> = cpu
+        .registers()
+        .chunks(8)
+        .enumerate()
+        .map(|(row, registers)| {
+            let cells: Vec<String> = registers
+                .iter()
+                .enumerate()
+                .map(|(column, value)| format!("V{:X} {:02X}", row * 8 + column, value))
+                .collect();
+            cells.join("  ")
+        })
+        .collect();
+    lines.push(format!(
+        "PC {:03X}  I {:03X}  DT {:02X}  ST {:02X}",
+        cpu.program_counter(),
+        cpu.i_register(),
+        cpu.timers().delay_timer(),
+        cpu.timers().sound_timer()
+    ));
+    let stack: Vec<String> = cpu
+        .stack()
+        .addresses()
+        .iter()
+        .map(|address| format!("{:03X}", address))
+        .collect();
+    lines.push(if stack.is_empty() {
+        "Stack: empty".to_string()
+    } else {
+        format!("Stack: {}", stack.join(" "))
+    });
+    lines
+}
+
+// The instructions around PC, marked with `>`, the breakpoints are marked with `*`. Everything
+// is decoded as an instruction, sprite data included
+pub fn disassembly_pane(cpu: &Cpu, debugger: &Debugger, around: usize) -> Vec<String> {
+    let program_counter = cpu.program_counter();
+    let start = program_counter.saturating_sub(around * 2);
+    (0..around * 2 + 1)
+        .map(|index| {
+            let address = start + index * 2;
+            let opcode = read_word(cpu, address);
+            let mnemonic = match Instruction::decode_for_platform(opcode, cpu.quirks().platform) {
+                Ok(Instruction::SetNextWordToI) => {
+                    format!("LD I, LONG 0x{:04X}", read_word(cpu, address + 2))
+                }
+                Ok(instruction) => instruction.mnemonic(cpu.quirks()),
+                Err(_) => "???".to_string(),
+            };
+            let breakpoint = if debugger.breakpoints.contains_key(&address) {
+                '*'
+            } else {
+                ' '
+            };
+            let current = if address == program_counter { '>' } else { ' ' };
+            format!(
+                "{}{} {:03X}  {:04X}  {}",
+                breakpoint, current, address, opcode, mnemonic
+            )
+        })
+        .collect()
+}
+
+// The conditional breakpoints and the watchpoints, the plain breakpoints are already marked in
+// the disassembly
+pub fn watchpoints_pane(debugger: &Debugger) -> Vec<String> {
+    let breakpoints = debugger
+        .breakpoints
+        .iter()
+        .filter_map(|(address, condition)| {
+            let condition = condition.as_ref()?;
+            Some(format!("Break 0x{:03X} if {}", address, condition))
+        });
+    let watchpoints = debugger
+        .watchpoints()
+        .map(|(number, watchpoint)| format!("Watch {}: {}", number, watchpoint));
+    breakpoints.chain(watchpoints).collect()
+}
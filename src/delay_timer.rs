@@ -1,7 +0,0 @@
-struct DelayTimer {
-    registers: u8;
-}
-
-trait DelayTimerTrait {
-
-}
@@ -0,0 +1,219 @@
+use crate::cpu::PROGRAM_START;
+use crate::instruction::Instruction;
+use crate::quirks::Quirks;
+use std::collections::BTreeSet;
+use std::fmt;
+
+// One line of the disassembly, either an instruction reached by the control flow or a byte
+// that never was, usually sprite data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Item {
+    Instruction {
+        address: usize,
+        opcode: u16,
+        instruction: Instruction,
+        // The address loaded by the XO-CHIP F000, stored on the 2 bytes after the opcode
+        long_address: Option<u16>,
+    },
+    Data {
+        address: usize,
+        byte: u8,
+    },
+}
+
+impl Item {
+    pub fn address(&self) -> usize {
+        match *self {
+            Item::Instruction { address, .. } | Item::Data { address, .. } => address,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match *self {
+            Item::Instruction {
+                long_address: Some(_),
+                ..
+            } => 4,
+            Item::Instruction { .. } => 2,
+            Item::Data { .. } => 1,
+        }
+    }
+}
+
+pub struct Disassembly {
+    items: Vec<Item>,
+    // Addresses reached by a jump or a call, printed with a label
+    targets: BTreeSet<usize>,
+    // BNNN is printed differently when it jumps relative to VX
+    quirks: Quirks,
+}
+
+// Follows the control flow from the start of the program, everything that can't be reached by
+// a jump, a call, a skip or the next instruction is data. BNNN and the code after it can't be
+// followed, as the address depends on a register
+pub fn disassemble(rom: &[u8], quirks: Quirks) -> Disassembly {
+    let end = PROGRAM_START + rom.len();
+    let read_word = |address: usize| -> Option<u16> {
+        if address < PROGRAM_START || address + 1 >= end {
+            return None;
+        }
+        let offset = address - PROGRAM_START;
+        Some(((rom[offset] as u16) << 8) + rom[offset + 1] as u16)
+    };
+    let decode = |address: usize| -> Option<(u16, Instruction, usize)> {
+        let opcode = read_word(address)?;
+        let instruction = Instruction::decode_for_platform(opcode, quirks.platform).ok()?;
+        if instruction == Instruction::SetNextWordToI {
+            read_word(address + 2)?;
+            return Some((opcode, instruction, 4));
+        }
+        Some((opcode, instruction, 2))
+    };
+
+    let mut starts = BTreeSet::new();
+    let mut targets = BTreeSet::new();
+    let mut pending = vec![PROGRAM_START];
+    while let Some(address) = pending.pop() {
+        if starts.contains(&address) {
+            continue;
+        }
+        let Some((_, instruction, size)) = decode(address) else {
+            continue;
+        };
+        starts.insert(address);
+
+        let next = address + size;
+        match instruction {
+            Instruction::Jump { nnn } => {
+                targets.insert(nnn as usize);
+                pending.push(nnn as usize);
+            }
+            Instruction::Call { nnn } => {
+                targets.insert(nnn as usize);
+                pending.push(nnn as usize);
+                pending.push(next);
+            }
+            Instruction::Return | Instruction::Exit | Instruction::JumpToNnnPlusV0 { .. } => {}
+            _ if instruction.is_skip() => {
+                pending.push(next);
+                // The XO-CHIP skips the whole F000 NNNN
+                let skipped_size = match decode(next) {
+                    Some((_, Instruction::SetNextWordToI, size)) => size,
+                    _ => 2,
+                };
+                pending.push(next + skipped_size);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    // Jumping to the middle of another instruction is possible, but only one of them can be
+    // printed, the first one wins
+    let mut items = Vec::new();
+    let mut address = PROGRAM_START;
+    while address < end {
+        let item = match decode(address) {
+            Some((opcode, instruction, size)) if starts.contains(&address) => Item::Instruction {
+                address,
+                opcode,
+                instruction,
+                long_address: (size == 4).then(|| read_word(address + 2).unwrap_or_default()),
+            },
+            _ => Item::Data {
+                address,
+                byte: rom[address - PROGRAM_START],
+            },
+        };
+        address += item.size();
+        items.push(item);
+    }
+
+    Disassembly {
+        items,
+        targets,
+        quirks,
+    }
+}
+
+impl Disassembly {
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    pub fn is_target(&self, address: usize) -> bool {
+        self.targets.contains(&address)
+    }
+
+    // The same items without the addresses and raw bytes, `assemble` turns it back into the ROM
+    pub fn source(&self) -> String {
+        let mut source = String::new();
+        for item in &self.items {
+            if self.is_target(item.address()) {
+                source.push_str(&format!("L{:03X}:\n", item.address()));
+            }
+            let line = match *item {
+                Item::Instruction {
+                    instruction,
+                    long_address: Some(long_address),
+                    ..
+                } => format!("{} 0x{:04X}", instruction, long_address),
+                Item::Instruction { instruction, .. } => instruction.mnemonic(self.quirks),
+                Item::Data { byte, .. } => format!("DB 0x{:02X}  ; {}", byte, sprite_bits(byte)),
+            };
+            source.push_str(&format!("    {}\n", line));
+        }
+        source
+    }
+}
+
+// The bits of the byte as a row of a sprite
+fn sprite_bits(byte: u8) -> String {
+    (0..8)
+        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+        .collect()
+}
+
+// A listing with the address, the raw bytes and the mnemonic of every item. Data bytes show
+// their bits, so the sprites can be recognised
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.items {
+            if self.is_target(item.address()) {
+                writeln!(f, "L{:03X}:", item.address())?;
+            }
+            match *item {
+                Item::Instruction {
+                    address,
+                    opcode,
+                    instruction,
+                    long_address: Some(long_address),
+                } => writeln!(
+                    f,
+                    "  {:03X}  {:04X} {:04X}  {} 0x{:04X}",
+                    address, opcode, long_address, instruction, long_address
+                )?,
+                Item::Instruction {
+                    address,
+                    opcode,
+                    instruction,
+                    long_address: None,
+                } => writeln!(
+                    f,
+                    "  {:03X}  {:04X}       {}",
+                    address,
+                    opcode,
+                    instruction.mnemonic(self.quirks)
+                )?,
+                Item::Data { address, byte } => writeln!(
+                    f,
+                    "  {:03X}  {:02X}         DB 0x{:02X}  ; {}",
+                    address,
+                    byte,
+                    byte,
+                    sprite_bits(byte)
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,107 +1,318 @@
-use crate::memory::{MemoryTrait, SharedMemory};
-use crossterm::{
-    ExecutableCommand,
-    cursor::{Hide, MoveTo, Show},
-    terminal::{Clear, ClearType},
-};
-use std::io::{Write, stdout};
-
-pub struct Display {
-    pixels: [[bool; 64]; 32],
-    memory: SharedMemory,
+use crate::memory::{Memory, MemoryTrait};
+use crate::save_state::{StateError, StateReader, StateWriter};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+// The SUPER-CHIP hires mode doubles both sides of the screen
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+// The XO-CHIP has two planes, each pixel is a colour from 0 to 3 made of one bit of each
+pub const PLANES: usize = 2;
+
+// The sprites are read from the memory of the machine, `Memory` unless it runs on another bus
+pub struct Display<M = Memory> {
+    // Always big enough for the hires mode, in lores only the top left corner is used. Bit 0 is
+    // the first plane and bit 1 the second
+    pixels: [[u8; HIRES_WIDTH]; HIRES_HEIGHT],
+    hires: bool,
+    // Mask of the planes affected by drawing, clearing and scrolling, FN01 changes it
+    selected_planes: u8,
+    memory: Rc<RefCell<M>>,
+    // Set when the pixels changed since the last refresh
+    dirty: bool,
+    // Sprites going past the edges are cut instead of wrapping to the other side
+    clip_sprites: bool,
 }
 
 pub trait DisplayTrait {
     fn refresh(&mut self) -> bool;
-    fn draw(&mut self, x: usize, y: usize, num_bytes: usize, i_register: usize);
+    // Returns if any pixel that was on got turned off
+    fn draw(&mut self, x: usize, y: usize, num_bytes: usize, i_register: usize) -> bool;
+    // The SUPER-CHIP DXY0, a 16x16 sprite made of 32 bytes, two for each row
+    fn draw_large(&mut self, x: usize, y: usize, i_register: usize) -> bool;
     fn clear(&mut self) -> bool;
+    fn scroll_down(&mut self, rows: usize);
+    // XO-CHIP 00DN
+    fn scroll_up(&mut self, rows: usize);
+    fn scroll_right(&mut self, columns: usize);
+    fn scroll_left(&mut self, columns: usize);
 }
 
-impl DisplayTrait for Display {
+impl<M: MemoryTrait> DisplayTrait for Display<M> {
+    // Returns if anything changed since the last refresh, so the frontend knows when the screen
+    // has to be presented again
     fn refresh(&mut self) -> bool {
-        false
+        let changed = self.dirty;
+        self.dirty = false;
+        changed
     }
 
     fn clear(&mut self) -> bool {
-        for (_, row) in self.pixels.iter_mut().enumerate() {
-            for j in 0..row.len() {
-                row[j] = false;
+        for row in self.pixels.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel &= !self.selected_planes;
             }
         }
+        self.dirty = true;
 
-        return true;
+        true
     }
 
-    fn draw(&mut self, x: usize, y: usize, num_bytes: usize, i_register: usize) {
+    fn draw(&mut self, x: usize, y: usize, num_bytes: usize, i_register: usize) -> bool {
         // The num_bytes is the total of values that will take from the memory from the index presented
         // inside of the i_register
+        self.draw_planes(x, y, num_bytes, 1, i_register)
+    }
 
-        // Loop through each row of the sprite (each byte represents one 8-pixel row)
-        for row in 0..num_bytes {
-            // Get one byte of sprite data from memory (8 pixels worth)
-            let sprite_byte = self.memory.borrow().retrieve(i_register + row);
-
-            // Calculate which screen row to draw on, wrapping if it goes past bottom (32 rows total)
-            let screen_row = (y + row) % 32;
+    fn draw_large(&mut self, x: usize, y: usize, i_register: usize) -> bool {
+        self.draw_planes(x, y, 16, 2, i_register)
+    }
 
-            // Process each of the 8 bits in this sprite byte (each bit = one pixel)
-            for bit_position in 0..8 {
-                // Extract the specific bit from the sprite byte (leftmost bit first)
-                let sprite_pixel = (sprite_byte >> (7 - bit_position)) & 1;
+    fn scroll_down(&mut self, rows: usize) {
+        let height = self.height();
+        for row in (0..height).rev() {
+            for column in 0..HIRES_WIDTH {
+                let source = if row >= rows {
+                    self.pixels[row - rows][column]
+                } else {
+                    0
+                };
+                self.move_selected_planes(row, column, source);
+            }
+        }
+        self.dirty = true;
+    }
 
-                // Calculate which screen column to draw on, wrapping if it goes past right edge (64 columns total)
-                let screen_column = (x + bit_position) % 64;
+    fn scroll_up(&mut self, rows: usize) {
+        let height = self.height();
+        for row in 0..height {
+            for column in 0..HIRES_WIDTH {
+                let source = if row + rows < height {
+                    self.pixels[row + rows][column]
+                } else {
+                    0
+                };
+                self.move_selected_planes(row, column, source);
+            }
+        }
+        self.dirty = true;
+    }
 
-                // XOR the sprite pixel with the screen pixel (CHIP-8's drawing method)
-                // If sprite bit is 1, flip the screen pixel; if sprite bit is 0, leave unchanged
-                self.pixels[screen_row][screen_column] ^= sprite_pixel == 1;
+    fn scroll_right(&mut self, columns: usize) {
+        let width = self.width();
+        for row in 0..HIRES_HEIGHT {
+            for column in (0..width).rev() {
+                let source = if column >= columns {
+                    self.pixels[row][column - columns]
+                } else {
+                    0
+                };
+                self.move_selected_planes(row, column, source);
             }
         }
+        self.dirty = true;
+    }
 
-        // Display the updated screen
-        self.print_with_crossterm();
+    fn scroll_left(&mut self, columns: usize) {
+        let width = self.width();
+        for row in 0..HIRES_HEIGHT {
+            for column in 0..width {
+                let source = if column + columns < width {
+                    self.pixels[row][column + columns]
+                } else {
+                    0
+                };
+                self.move_selected_planes(row, column, source);
+            }
+        }
+        self.dirty = true;
     }
 }
 
-impl Display {
-    pub fn new(memory: SharedMemory) -> Display {
-        let pixels = [[false; 64]; 32];
-        let display = Display { pixels, memory };
-        display
+impl<M: MemoryTrait> Display<M> {
+    pub fn new(memory: Rc<RefCell<M>>) -> Display<M> {
+        let pixels = [[0; HIRES_WIDTH]; HIRES_HEIGHT];
+        Display {
+            pixels,
+            hires: false,
+            selected_planes: 1,
+            memory,
+            dirty: true,
+            clip_sprites: false,
+        }
+    }
+
+    pub fn set_clip_sprites(&mut self, clip_sprites: bool) {
+        self.clip_sprites = clip_sprites;
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // Switching the resolution clears every plane, the old pixels wouldn't make sense in the
+    // new one
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = [[0; HIRES_WIDTH]; HIRES_HEIGHT];
+        self.dirty = true;
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    // Only the lowest 2 bits are used, 0 makes drawing do nothing
+    pub fn select_planes(&mut self, planes: u8) {
+        self.selected_planes = planes & 0b11;
     }
 
-    // print without lib
-    pub fn print(&self) {
-        println!("  ");
-        println!("////");
-        println!("  ");
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { LORES_WIDTH }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            LORES_HEIGHT
+        }
+    }
+
+    // If the pixel is on in any plane
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels[y][x] != 0
+    }
+
+    // The colour from 0 to 3 of the pixel, one bit for each plane
+    pub fn color(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y][x]
+    }
+
+    // The colours of the visible rows in the current resolution
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        let width = self.width();
+        self.pixels[..self.height()]
+            .iter()
+            .map(move |row| &row[..width])
+    }
+
+    // The clipping is not written as it comes from the quirks
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.hires);
+        writer.write_u8(self.selected_planes);
         for row in self.pixels.iter() {
-            for &pixel in row.iter() {
-                let symbol = if pixel { '█' } else { '_' };
-                print!("{}", symbol);
+            writer.write_bytes(row);
+        }
+    }
+
+    pub(crate) fn load_state(
+        reader: &mut StateReader,
+        memory: Rc<RefCell<M>>,
+    ) -> Result<Display<M>, StateError> {
+        let mut display = Display::new(memory);
+        display.hires = reader.read_bool()?;
+        display.selected_planes = reader.read_u8()?;
+        for row in display.pixels.iter_mut() {
+            *row = reader.read_array()?;
+            if row.iter().any(|&color| color > 0b11) {
+                return Err(StateError::InvalidFormat);
+            }
+        }
+        Ok(display)
+    }
+
+    // Replaces the bits of the selected planes with the ones from the source pixel
+    fn move_selected_planes(&mut self, row: usize, column: usize, source: u8) {
+        let pixel = &mut self.pixels[row][column];
+        *pixel = (*pixel & !self.selected_planes) | (source & self.selected_planes);
+    }
+
+    // With both planes selected the sprite of the second plane comes right after the first one
+    fn draw_planes(
+        &mut self,
+        x: usize,
+        y: usize,
+        num_rows: usize,
+        width_bytes: usize,
+        i_register: usize,
+    ) -> bool {
+        let mut collision = false;
+        let mut address = i_register;
+        for plane in 0..PLANES {
+            let plane_mask = 1 << plane;
+            if self.selected_planes & plane_mask == 0 {
+                continue;
             }
-            println!();
+            collision |= self.draw_sprite(x, y, num_rows, width_bytes, address, plane_mask);
+            address += num_rows * width_bytes;
         }
+
+        // The frontend only presents the screen on the next refresh
+        self.dirty = true;
+        collision
     }
 
-    fn print_with_crossterm(&self) {
-        let mut stdout = stdout();
+    // Sprites are `width_bytes` bytes wide (8 pixels each) and `num_rows` tall
+    fn draw_sprite(
+        &mut self,
+        x: usize,
+        y: usize,
+        num_rows: usize,
+        width_bytes: usize,
+        i_register: usize,
+        plane_mask: u8,
+    ) -> bool {
+        let width = self.width();
+        let height = self.height();
+        // The starting position always wraps, even when the sprites are clipped
+        let x = x % width;
+        let y = y % height;
+        let mut collision = false;
+
+        // Loop through each row of the sprite
+        for row in 0..num_rows {
+            if self.clip_sprites && y + row >= height {
+                break;
+            }
+            // Calculate which screen row to draw on, wrapping if it goes past bottom
+            let screen_row = (y + row) % height;
+
+            for byte in 0..width_bytes {
+                // Get one byte of sprite data from memory (8 pixels worth)
+                let sprite_byte = self
+                    .memory
+                    .borrow()
+                    .retrieve(i_register + row * width_bytes + byte);
 
-        stdout.execute(Clear(ClearType::All)).unwrap();
-        stdout.execute(Hide).unwrap();
+                // Process each of the 8 bits in this sprite byte (each bit = one pixel)
+                for bit_position in 0..8 {
+                    // Extract the specific bit from the sprite byte (leftmost bit first)
+                    let sprite_pixel = (sprite_byte >> (7 - bit_position)) & 1;
+                    let column = x + byte * 8 + bit_position;
 
-        for (row_idx, row) in self.pixels.iter().enumerate() {
-            stdout.execute(MoveTo(0, row_idx as u16)).unwrap();
+                    if self.clip_sprites && column >= width {
+                        break;
+                    }
 
-            let row_string: String = row
-                .iter()
-                .map(|&pixel| if pixel { '█' } else { '·' })
-                .collect();
+                    // Calculate which screen column to draw on, wrapping if it goes past right edge
+                    let screen_column = column % width;
 
-            print!("{}", row_string);
+                    // XOR the sprite pixel with the screen pixel (CHIP-8's drawing method)
+                    // If sprite bit is 1, flip the screen pixel; if sprite bit is 0, leave unchanged
+                    if sprite_pixel == 1 {
+                        let pixel = &mut self.pixels[screen_row][screen_column];
+                        if *pixel & plane_mask != 0 {
+                            collision = true;
+                        }
+                        *pixel ^= plane_mask;
+                    }
+                }
+            }
         }
 
-        stdout.execute(Show).unwrap();
-        stdout.flush().unwrap();
+        collision
     }
 }
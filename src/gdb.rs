@@ -0,0 +1,487 @@
+use crate::cpu::{Cpu, CpuError, PROGRAM_START};
+use crate::memory::MemoryTrait;
+use crate::stack::StackTrait;
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// The signals of the stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+// V0 to VF, I, PC and the depth of the stack as SP
+const REGISTER_COUNT: usize = 19;
+const I_REGISTER: usize = 16;
+const PROGRAM_COUNTER: usize = 17;
+const STACK_POINTER: usize = 18;
+// Ctrl+C in gdb is sent outside of a packet
+const INTERRUPT: u8 = 0x03;
+
+// What the frontend does once gdb lets the machine go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Served {
+    Resumed,
+    // gdb is gone, the machine runs on its own
+    Detached,
+    Killed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    Continue,
+    Step,
+}
+
+enum Action {
+    Reply(String),
+    Resume(Resume),
+    Detach,
+    Kill,
+}
+
+// A server of the GDB remote serial protocol for the clients that take the registers from the
+// target description, as gdb has no CHIP-8 architecture of its own. Like the debugger it is
+// asked before every instruction through `Scheduler::run_frame_until`, and the frontend calls
+// `serve` while it is paused. The registers are V0 to VF, I, PC and SP, which is the depth of
+// the stack, the breakpoints are kept here instead of being written in the memory
+pub struct GdbStub {
+    stream: TcpStream,
+    // Bytes received but not handled yet
+    received: VecDeque<u8>,
+    closed: bool,
+    breakpoints: BTreeSet<usize>,
+    // None while paused
+    resume: Option<Resume>,
+    // The instruction the machine was paused on runs without checking, so a breakpoint can be
+    // left
+    resuming: bool,
+    signal: u8,
+    // After a continue or a step gdb waits for the stop reply
+    waiting: bool,
+    // Turned off by QStartNoAckMode
+    acknowledge: bool,
+}
+
+impl GdbStub {
+    // Waits for gdb to connect, the machine starts paused
+    pub fn accept(listener: &TcpListener) -> io::Result<GdbStub> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        Ok(GdbStub::new(stream))
+    }
+
+    pub fn new(stream: TcpStream) -> GdbStub {
+        GdbStub {
+            stream,
+            received: VecDeque::new(),
+            closed: false,
+            breakpoints: BTreeSet::new(),
+            resume: None,
+            resuming: false,
+            signal: SIGTRAP,
+            waiting: false,
+            acknowledge: true,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.resume.is_none()
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    // Asked before every instruction while running
+    pub fn should_pause(&mut self, cpu: &Cpu) -> bool {
+        let Some(resume) = self.resume else {
+            return true;
+        };
+        if self.resuming {
+            self.resuming = false;
+            return false;
+        }
+        if resume == Resume::Step || self.breakpoints.contains(&cpu.program_counter()) {
+            self.pause(SIGTRAP);
+            return true;
+        }
+        false
+    }
+
+    // Checks without blocking whether gdb asked to stop while running, between two frames
+    pub fn poll(&mut self) -> io::Result<()> {
+        self.stream.set_nonblocking(true)?;
+        let result = self.receive();
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        if let Some(position) = self.received.iter().position(|&byte| byte == INTERRUPT) {
+            self.received.remove(position);
+            self.pause(SIGINT);
+        }
+        // Serving finds out that gdb is gone
+        if self.closed {
+            self.pause(SIGTRAP);
+        }
+        Ok(())
+    }
+
+    // Answers the packets until gdb resumes the machine, detaches or kills it
+    pub fn serve(&mut self, cpu: &mut Cpu) -> io::Result<Served> {
+        if self.waiting {
+            self.waiting = false;
+            let reply = self.stop_reply();
+            self.send(&reply)?;
+        }
+        loop {
+            let Some(packet) = self.read_packet()? else {
+                return Ok(Served::Detached);
+            };
+            match self.handle(&packet, cpu) {
+                Action::Reply(reply) => {
+                    self.send(&reply)?;
+                    if packet == "QStartNoAckMode" {
+                        self.acknowledge = false;
+                    }
+                }
+                Action::Resume(resume) => {
+                    self.resume = Some(resume);
+                    self.resuming = true;
+                    self.waiting = true;
+                    return Ok(Served::Resumed);
+                }
+                Action::Detach => {
+                    self.send("OK")?;
+                    return Ok(Served::Detached);
+                }
+                Action::Kill => return Ok(Served::Killed),
+            }
+        }
+    }
+
+    // Tells gdb the program is over when it waits for a stop reply
+    pub fn report_exit(&mut self) -> io::Result<()> {
+        if self.waiting {
+            self.waiting = false;
+            self.send("W00")?;
+        }
+        Ok(())
+    }
+
+    // Pauses on an error of the CPU instead of ending the session, so gdb can look at the machine.
+    // The failed instruction changed nothing but the program counter, which goes back to it
+    pub fn report_error(&mut self, cpu: &mut Cpu, error: &CpuError) {
+        cpu.set_program_counter(error.program_counter());
+        self.pause(SIGILL);
+    }
+
+    fn pause(&mut self, signal: u8) {
+        self.resume = None;
+        self.signal = signal;
+    }
+
+    fn stop_reply(&self) -> String {
+        format!("S{:02x}", self.signal)
+    }
+
+    fn handle(&mut self, packet: &str, cpu: &mut Cpu) -> Action {
+        let reply =
+            |result: Option<String>| Action::Reply(result.unwrap_or_else(|| "E01".to_string()));
+        let Some(kind) = packet.chars().next() else {
+            return Action::Reply(String::new());
+        };
+        let arguments = &packet[kind.len_utf8()..];
+        match kind {
+            '?' => Action::Reply(self.stop_reply()),
+            'g' => Action::Reply(
+                (0..REGISTER_COUNT)
+                    .map(|index| encode_hex(&read_register(cpu, index)))
+                    .collect(),
+            ),
+            'G' => reply(write_registers(cpu, arguments)),
+            'p' => reply(
+                parse_number(arguments)
+                    .filter(|&index| index < REGISTER_COUNT)
+                    .map(|index| encode_hex(&read_register(cpu, index))),
+            ),
+            'P' => reply(arguments.split_once('=').and_then(|(index, value)| {
+                let index = parse_number(index)?;
+                write_register(cpu, index, &decode_hex(value)?)
+            })),
+            'm' => reply(read_memory(cpu, arguments)),
+            'M' => reply(write_memory(cpu, arguments)),
+            'c' | 's' => {
+                if !arguments.is_empty() {
+                    match parse_number(arguments) {
+                        Some(address) => cpu.set_program_counter(address),
+                        None => return Action::Reply("E01".to_string()),
+                    }
+                }
+                Action::Resume(if kind == 'c' {
+                    Resume::Continue
+                } else {
+                    Resume::Step
+                })
+            }
+            // Software and hardware breakpoints are the same here
+            'Z' | 'z' => {
+                let mut fields = arguments.split(',');
+                let (Some("0" | "1"), Some(address)) = (fields.next(), fields.next()) else {
+                    return Action::Reply(String::new());
+                };
+                reply(parse_number(address).map(|address| {
+                    if kind == 'Z' {
+                        self.breakpoints.insert(address);
+                    } else {
+                        self.breakpoints.remove(&address);
+                    }
+                    "OK".to_string()
+                }))
+            }
+            'D' => Action::Detach,
+            'k' => Action::Kill,
+            // There is a single thread, whichever gdb picks
+            'H' | 'T' => Action::Reply("OK".to_string()),
+            _ => Action::Reply(query(packet)),
+        }
+    }
+
+    // The data of the next packet, None once gdb is gone
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Anything before the start of a packet is an acknowledgement or an interrupt, which
+            // doesn't matter while paused
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'$') => break,
+                    Some(_) => {}
+                }
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            for digit in &mut checksum {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(byte) => *digit = byte,
+                }
+            }
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(checksum_of(&data));
+            if self.acknowledge {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.received.is_empty() && !self.closed {
+            self.receive()?;
+        }
+        Ok(self.received.pop_front())
+    }
+
+    fn receive(&mut self) -> io::Result<()> {
+        let mut buffer = [0; 1024];
+        let read = self.stream.read(&mut buffer)?;
+        if read == 0 {
+            self.closed = true;
+        }
+        self.received.extend(&buffer[..read]);
+        Ok(())
+    }
+
+    // The acknowledgement from gdb is not waited for, it is skipped with the next packet
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+}
+
+fn query(packet: &str) -> String {
+    if packet.starts_with("qSupported") {
+        return "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".to_string();
+    }
+    if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+        return read_target_description(range).unwrap_or("E01".to_string());
+    }
+    match packet {
+        "QStartNoAckMode" => "OK",
+        // Quitting gdb detaches instead of killing the emulator
+        "qAttached" => "1",
+        "qC" => "QC1",
+        "qfThreadInfo" => "m1",
+        "qsThreadInfo" => "l",
+        _ => "",
+    }
+    .to_string()
+}
+
+// The registers gdb doesn't know about without a CHIP-8 architecture
+fn target_description() -> String {
+    let mut registers: Vec<String> = (0..16)
+        .map(|index| format!("<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>", index))
+        .collect();
+    registers.push("<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>".to_string());
+    registers.push("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>".to_string());
+    registers.push("<reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>".to_string());
+    format!(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><feature name=\"org.chip8.core\">{}</feature></target>",
+        registers.join("")
+    )
+}
+
+// `offset,length`, the reply starts with m when there is more to read and l for the last part
+fn read_target_description(range: &str) -> Option<String> {
+    let (offset, length) = range.split_once(',')?;
+    let (offset, length) = (parse_number(offset)?, parse_number(length)?);
+    let description = target_description();
+    let start = offset.min(description.len());
+    let end = offset.saturating_add(length).min(description.len());
+    let more = if end < description.len() { 'm' } else { 'l' };
+    Some(format!("{}{}", more, &description[start..end]))
+}
+
+// Little endian, like every register sent to gdb
+fn read_register(cpu: &Cpu, index: usize) -> Vec<u8> {
+    match index {
+        I_REGISTER => (cpu.i_register() as u16).to_le_bytes().to_vec(),
+        PROGRAM_COUNTER => (cpu.program_counter() as u16).to_le_bytes().to_vec(),
+        STACK_POINTER => vec![cpu.stack().addresses().len() as u8],
+        _ => vec![cpu.registers()[index]],
+    }
+}
+
+fn register_size(index: usize) -> usize {
+    match index {
+        I_REGISTER | PROGRAM_COUNTER => 2,
+        _ => 1,
+    }
+}
+
+// The value to write, None if it doesn't fit the register
+fn decode_register(cpu: &Cpu, index: usize, bytes: &[u8]) -> Option<usize> {
+    if index >= REGISTER_COUNT || bytes.len() != register_size(index) {
+        return None;
+    }
+    let value = bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| (value << 8) | byte as usize);
+    // The stack can only be unwound, there is nothing to push
+    if index == STACK_POINTER && value > cpu.stack().addresses().len() {
+        return None;
+    }
+    Some(value)
+}
+
+fn set_register(cpu: &mut Cpu, index: usize, value: usize) {
+    match index {
+        I_REGISTER => cpu.set_i_register(value),
+        PROGRAM_COUNTER => cpu.set_program_counter(value),
+        STACK_POINTER => {
+            for _ in value..cpu.stack().addresses().len() {
+                cpu.stack_mut().pop();
+            }
+        }
+        _ => cpu.set_register(index, value as u8),
+    }
+}
+
+fn write_register(cpu: &mut Cpu, index: usize, bytes: &[u8]) -> Option<String> {
+    let value = decode_register(cpu, index, bytes)?;
+    set_register(cpu, index, value);
+    Some("OK".to_string())
+}
+
+// Nothing is written unless every register is valid
+fn write_registers(cpu: &mut Cpu, hex: &str) -> Option<String> {
+    let mut bytes = decode_hex(hex)?;
+    if bytes.len() != (0..REGISTER_COUNT).map(register_size).sum::<usize>() {
+        return None;
+    }
+    let mut values = Vec::with_capacity(REGISTER_COUNT);
+    for index in 0..REGISTER_COUNT {
+        let rest = bytes.split_off(register_size(index));
+        values.push(decode_register(cpu, index, &bytes)?);
+        bytes = rest;
+    }
+    for (index, value) in values.into_iter().enumerate() {
+        set_register(cpu, index, value);
+    }
+    Some("OK".to_string())
+}
+
+// `address,length`
+fn read_memory(cpu: &Cpu, arguments: &str) -> Option<String> {
+    let (address, length) = arguments.split_once(',')?;
+    let (address, length) = (parse_number(address)?, parse_number(length)?);
+    let memory = cpu.memory().borrow();
+    if address.saturating_add(length) > memory.size() {
+        return None;
+    }
+    // gdb looking at the memory isn't a read of the program
+    let bytes: Vec<u8> = (address..address + length)
+        .map(|address| memory.peek(address))
+        .collect();
+    Some(encode_hex(&bytes))
+}
+
+// `address,length:bytes`, the interpreter area below 0x200 can't be written
+fn write_memory(cpu: &Cpu, arguments: &str) -> Option<String> {
+    let (range, hex) = arguments.split_once(':')?;
+    let (address, length) = range.split_once(',')?;
+    let (address, length) = (parse_number(address)?, parse_number(length)?);
+    let bytes = decode_hex(hex)?;
+    if bytes.len() != length {
+        return None;
+    }
+    let mut memory = cpu.memory().borrow_mut();
+    // Checked before writing anything, gdb expects all the bytes or none
+    if address < PROGRAM_START || address.saturating_add(length) > memory.size() {
+        return None;
+    }
+    for (offset, &byte) in bytes.iter().enumerate() {
+        memory.write(address + offset, byte);
+    }
+    Some("OK".to_string())
+}
+
+fn parse_number(hex: &str) -> Option<usize> {
+    usize::from_str_radix(hex, 16).ok()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter()
+        .fold(0, |checksum: u8, &byte| checksum.wrapping_add(byte))
+}
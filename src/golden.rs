@@ -0,0 +1,155 @@
+use crate::backend::Framebuffer;
+use crate::cpu::Cpu;
+use crate::memory::{Memory, SharedMemory};
+use crate::quirks::Quirks;
+use crate::rom::load_rom_bytes;
+use crate::scheduler::{DEFAULT_INSTRUCTIONS_PER_SECOND, FrameOutcome, Scheduler, SchedulerTrait};
+use crate::screenshot::{Palette, decode_pgm, encode_pgm, encode_png};
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+// Set to 1 to write the golden images again instead of comparing with them
+pub const UPDATE_GOLDEN_VARIABLE: &str = "UPDATE_GOLDEN";
+
+// Colours of the diff image
+const DIFF_PALETTE: Palette = Palette {
+    colors: [
+        // Off on both
+        [0x00, 0x00, 0x00],
+        // On on both
+        [0x60, 0x60, 0x60],
+        // Only on the golden image, missing from the frame
+        [0xFF, 0x30, 0x30],
+        // Only on the frame
+        [0x30, 0xFF, 0x30],
+    ],
+};
+
+// A ROM run without a terminal for a fixed amount of frames
+pub struct RomTest {
+    pub rom: Vec<u8>,
+    pub frames: u32,
+    pub quirks: Quirks,
+    pub seed: u64,
+    pub instructions_per_second: u32,
+    // The keys pressed from a frame on, until the next entry. Each key is a bit like in
+    // `Keyboard::state`
+    pub inputs: Vec<(u32, u16)>,
+}
+
+impl RomTest {
+    pub fn new(rom: Vec<u8>, frames: u32) -> RomTest {
+        RomTest {
+            rom,
+            frames,
+            quirks: Quirks::default(),
+            seed: 0,
+            instructions_per_second: DEFAULT_INSTRUCTIONS_PER_SECOND,
+            inputs: Vec::new(),
+        }
+    }
+
+    // Runs the frames without a terminal, or less if the ROM halts, and returns the last one
+    pub fn run(&self) -> Result<Framebuffer, String> {
+        let memory: SharedMemory = Rc::new(RefCell::new(Memory::with_size(
+            self.quirks.platform.memory_size(),
+        )));
+        load_rom_bytes(&self.rom, Rc::clone(&memory))?;
+        let mut cpu = Cpu::new(memory);
+        cpu.set_quirks(self.quirks);
+        cpu.set_seed(self.seed);
+
+        let mut scheduler = Scheduler::new(self.instructions_per_second);
+        for frame in 0..self.frames {
+            if let Some(&(_, keys)) = self.inputs.iter().rev().find(|(from, _)| *from <= frame) {
+                cpu.keyboard_mut().set_state(keys);
+            }
+            let outcome = scheduler.run_frame(&mut cpu).map_err(|e| e.to_string())?;
+            if outcome != FrameOutcome::Completed {
+                break;
+            }
+        }
+
+        Ok(Framebuffer::from_display(cpu.display()))
+    }
+}
+
+// Compares the frame with the golden image (a PGM written by `encode_pgm`). On a mismatch a PNG
+// with the differences is written to `diff_path`. With UPDATE_GOLDEN=1 the golden image is
+// written instead
+pub fn check_golden(
+    frame: &Framebuffer,
+    golden_path: &Path,
+    diff_path: &Path,
+) -> Result<(), String> {
+    if std::env::var(UPDATE_GOLDEN_VARIABLE).is_ok_and(|value| value == "1") {
+        return fs::write(golden_path, encode_pgm(frame))
+            .map_err(|e| format!("Failed to write {}: {}", golden_path.display(), e));
+    }
+
+    let data = fs::read(golden_path).map_err(|e| {
+        format!(
+            "Failed to read the golden image {} ({}), run with {}=1 to create it",
+            golden_path.display(),
+            e,
+            UPDATE_GOLDEN_VARIABLE
+        )
+    })?;
+    let golden = decode_pgm(&data)?;
+    if golden.width != frame.width || golden.height != frame.height {
+        return Err(format!(
+            "The frame is {}x{} but the golden image {} is {}x{}",
+            frame.width,
+            frame.height,
+            golden_path.display(),
+            golden.width,
+            golden.height
+        ));
+    }
+
+    let differences = golden
+        .pixels
+        .iter()
+        .zip(&frame.pixels)
+        .filter(|(expected, found)| expected != found)
+        .count();
+    if differences == 0 {
+        return Ok(());
+    }
+
+    fs::write(
+        diff_path,
+        encode_png(&diff_frame(&golden, frame), 4, &DIFF_PALETTE),
+    )
+    .map_err(|e| format!("Failed to write {}: {}", diff_path.display(), e))?;
+    Err(format!(
+        "{} pixels differ from {}, see {}",
+        differences,
+        golden_path.display(),
+        diff_path.display()
+    ))
+}
+
+// Each pixel becomes one of the colours of the diff palette
+fn diff_frame(golden: &Framebuffer, frame: &Framebuffer) -> Framebuffer {
+    let pixels = golden
+        .pixels
+        .iter()
+        .zip(&frame.pixels)
+        .map(|(&expected, &found)| match (expected != 0, found != 0) {
+            (false, false) => 0,
+            (true, true) if expected == found => 1,
+            (true, false) => 2,
+            (false, true) => 3,
+            // Both on but with different colours of the XO-CHIP
+            (true, true) => 2,
+        })
+        .collect();
+    Framebuffer {
+        width: frame.width,
+        height: frame.height,
+        pixels,
+    }
+}
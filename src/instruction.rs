@@ -0,0 +1,482 @@
+use crate::quirks::{Platform, Quirks};
+use std::fmt;
+
+// The first nibble of every instruction tells the group it belongs to, some groups have only one
+// instruction and others need a second mask to know which one of the group it is
+const SYSTEM: u16 = 0x0000; // 0NNN, 00E0 and 00EE
+const JUMP_TO_NNN: u16 = 0x1000; // 1NNN: Jump to address NNN
+const SET_PROGRAM_COUNTER_TO_THE_TOP_OF_THE_STACK_AND_GO_TO_NNN: u16 = 0x2000; // 2NNN: Call the subroutine at NNN
+const SKIP_NEXT_INSTRUCTION_IF_X_IS_EQUAL_TO_KK: u16 = 0x3000; // 3XNN: Skip if VX == NN
+const SKIP_NEXT_INSTRUCTION_IF_X_IS_DIFFERENT_OF_KK: u16 = 0x4000; // 4XNN: Skip if VX != NN
+const SKIP_NEXT_INSTRUCTION_IF_X_IS_EQUAL_TO_Y: u16 = 0x5000; // 5XY0: Skip if VX == VY
+const SET_NN_TO_VX: u16 = 0x6000; // 6XNN: Set VX to NN
+const SUM_NN_TO_VX: u16 = 0x7000; // 7XNN: Add NN to VX
+const ARITHMETIC: u16 = 0x8000; // 8XYN
+const SKIP_NEXT_INSTRUCTION_IF_X_IS_DIFFERENT_OF_Y: u16 = 0x9000; // 9XY0: Skip if VX != VY
+const SET_NNN_TO_I: u16 = 0xA000; // ANNN: Set I to NNN
+const JUMP_TO_NNN_PLUS_V0: u16 = 0xB000; // BNNN: Jump to address NNN + V0
+const RANDOM_AND_AND_WITH_KK: u16 = 0xC000; // CXNN: Set VX to a random byte AND NN
+const DRAW: u16 = 0xD000; // DXYN: Draw sprite at (VX, VY) with height N
+const KEYBOARD: u16 = 0xE000; // EX9E and EXA1
+const TIMERS_MEMORY_AND_KEYBOARD: u16 = 0xF000; // FXNN
+
+// System group, exact match as they don't send args
+const CLEAR: u16 = 0x00E0; // 00E0: Clear screen
+const POP_THE_TOP_OF_THE_STACK_AS_THE_CURRENT_PROGRAM_COUNTER: u16 = 0x00EE; // 00EE: Return from a subroutine
+const SCROLL_RIGHT_4_PIXELS: u16 = 0x00FB; // 00FB: Scroll the screen 4 pixels to the right (SUPER-CHIP)
+const SCROLL_LEFT_4_PIXELS: u16 = 0x00FC; // 00FC: Scroll the screen 4 pixels to the left (SUPER-CHIP)
+const EXIT_THE_INTERPRETER: u16 = 0x00FD; // 00FD: Exit the interpreter (SUPER-CHIP)
+const DISABLE_HIGH_RESOLUTION: u16 = 0x00FE; // 00FE: Switch to the 64x32 mode (SUPER-CHIP)
+const ENABLE_HIGH_RESOLUTION: u16 = 0x00FF; // 00FF: Switch to the 128x64 mode (SUPER-CHIP)
+// Matched with the 0xFFF0 mask
+const SCROLL_DOWN_N_PIXELS: u16 = 0x00C0; // 00CN: Scroll the screen N pixels down (SUPER-CHIP)
+const SCROLL_UP_N_PIXELS: u16 = 0x00D0; // 00DN: Scroll the screen N pixels up (XO-CHIP)
+
+// Register range group, matched with the 0xF00F mask
+const STORE_VX_TO_VY_ON_I: u16 = 0x5002; // 5XY2: Store VX to VY on the memory starting at I (XO-CHIP)
+const LOAD_VX_TO_VY_FROM_I: u16 = 0x5003; // 5XY3: Load VX to VY from the memory starting at I (XO-CHIP)
+
+// Arithmetic group, matched with the 0xF00F mask
+const STORE_THE_VALUE_OF_REGISTER_X_TO_REGISTER_Y: u16 = 0x8000; // 8XY0: Set VX to VY
+const OR_TO_X_AND_Y: u16 = 0x8001; // 8XY1: Set VX to VX OR VY
+const AND_TO_X_AND_Y: u16 = 0x8002; // 8XY2: Set VX to VX AND VY
+const XOR_TO_X_AND_Y: u16 = 0x8003; // 8XY3: Set VX to VX XOR VY
+const ADD_VX_WITH_VY_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS: u16 = 0x8004; // 8XY4: Add VY to VX, VF is the carry
+const SUBTRACT_VX_WITH_VY_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS: u16 = 0x8005; // 8XY5: Set VX to VX - VY, VF is NOT borrow
+const SHIFT_RIGHT_X: u16 = 0x8006; // 8XY6: Shift VX right, VF is the bit shifted out
+const SUBTRACT_VY_WITH_VX_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS: u16 = 0x8007; // 8XY7: Set VX to VY - VX, VF is NOT borrow
+const SHIFT_LEFT_X: u16 = 0x800E; // 8XYE: Shift VX left, VF is the bit shifted out
+
+// Keyboard group, matched with the 0xF0FF mask
+const SKIP_NEXT_INSTRUCTION_IF_X_KEY_WAS_PRESSED: u16 = 0xE09E; // EX9E: Skip if the key on VX is pressed
+const SKIP_NEXT_INSTRUCTION_IF_X_KEY_WAS_NOT_PRESSED: u16 = 0xE0A1; // EXA1: Skip if the key on VX is not pressed
+
+// Timers, memory and keyboard group, matched with the 0xF0FF mask
+const SET_DELAY_TIMER_TO_VX: u16 = 0xF007; // FX07: Set VX to the delay timer
+const WAIT_TO_A_KEY_TO_BE_PRESSED_AND_STORE_IT_ON_THE_VX: u16 = 0xF00A; // FX0A: Wait a key and store it on VX
+const SET_VX_TO_DELAY_TIMER: u16 = 0xF015; // FX15: Set the delay timer to VX
+const SET_VX_TO_SOUND_TIMER: u16 = 0xF018; // FX18: Set the sound timer to VX
+const SUM_VX_TO_I: u16 = 0xF01E; // FX1E: Add VX to I
+const SET_I_TO_THE_FONT_OF_VX: u16 = 0xF029; // FX29: Set I to the font sprite of the digit on VX
+const STORE_BCD_OF_VX_ON_I: u16 = 0xF033; // FX33: Store the decimal digits of VX on I, I+1 and I+2
+const STORE_V0_TO_VX_ON_I: u16 = 0xF055; // FX55: Store V0 to VX on the memory starting at I
+const LOAD_V0_TO_VX_FROM_I: u16 = 0xF065; // FX65: Load V0 to VX from the memory starting at I
+const SET_I_TO_THE_BIG_FONT_OF_VX: u16 = 0xF030; // FX30: Set I to the big font sprite of the digit on VX (SUPER-CHIP)
+const STORE_V0_TO_VX_ON_THE_FLAGS: u16 = 0xF075; // FX75: Store V0 to VX on the RPL flags (SUPER-CHIP)
+const LOAD_V0_TO_VX_FROM_THE_FLAGS: u16 = 0xF085; // FX85: Load V0 to VX from the RPL flags (SUPER-CHIP)
+const SET_THE_NEXT_16_BITS_TO_I: u16 = 0xF000; // F000 NNNN: Set I to the address on the next 2 bytes (XO-CHIP)
+const SELECT_THE_PLANES_N: u16 = 0xF001; // FN01: Select the planes on the mask N (XO-CHIP)
+const LOAD_THE_AUDIO_PATTERN_FROM_I: u16 = 0xF002; // F002: Load the 16 bytes audio pattern from I (XO-CHIP)
+const SET_VX_TO_PITCH: u16 = 0xF03A; // FX3A: Set the pitch of the audio pattern to VX (XO-CHIP)
+
+// A decoded instruction with its arguments already extracted from the opcode, `x` and `y` are
+// register indexes, `nn` is a byte, `n` is a nibble and `nnn` is an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    MachineCodeRoutine { nnn: u16 },
+    Clear,
+    Return,
+    Jump { nnn: u16 },
+    Call { nnn: u16 },
+    SkipIfXEqualsNn { x: usize, nn: u8 },
+    SkipIfXDiffersNn { x: usize, nn: u8 },
+    SkipIfXEqualsY { x: usize, y: usize },
+    SetNnToX { x: usize, nn: u8 },
+    SumNnToX { x: usize, nn: u8 },
+    StoreYToX { x: usize, y: usize },
+    OrYToX { x: usize, y: usize },
+    AndYToX { x: usize, y: usize },
+    XorYToX { x: usize, y: usize },
+    SumYToX { x: usize, y: usize },
+    SubtractYFromX { x: usize, y: usize },
+    ShiftRightX { x: usize, y: usize },
+    SubtractXFromY { x: usize, y: usize },
+    ShiftLeftX { x: usize, y: usize },
+    SkipIfXDiffersY { x: usize, y: usize },
+    SetNnnToI { nnn: u16 },
+    JumpToNnnPlusV0 { nnn: u16 },
+    RandomAndNnToX { x: usize, nn: u8 },
+    Draw { x: usize, y: usize, n: u8 },
+    SkipIfKeyOnXPressed { x: usize },
+    SkipIfKeyOnXNotPressed { x: usize },
+    SetDelayTimerToX { x: usize },
+    WaitKeyToX { x: usize },
+    SetXToDelayTimer { x: usize },
+    SetXToSoundTimer { x: usize },
+    SumXToI { x: usize },
+    SetIToFontOfX { x: usize },
+    StoreBcdOfXOnI { x: usize },
+    StoreV0ToXOnI { x: usize },
+    LoadV0ToXFromI { x: usize },
+    ScrollDown { n: u8 },
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowResolution,
+    HighResolution,
+    SetIToBigFontOfX { x: usize },
+    StoreV0ToXOnFlags { x: usize },
+    LoadV0ToXFromFlags { x: usize },
+    ScrollUp { n: u8 },
+    StoreXToYOnI { x: usize, y: usize },
+    LoadXToYFromI { x: usize, y: usize },
+    // The address is on the 2 bytes after the opcode, the CPU reads them
+    SetNextWordToI,
+    SelectPlanes { n: u8 },
+    LoadAudioPattern,
+    SetPitchToX { x: usize },
+}
+
+// The opcode doesn't match any instruction known by the decoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownOpcode(pub u16);
+
+impl fmt::Display for UnknownOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown opcode 0x{:04X}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownOpcode {}
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Result<Instruction, UnknownOpcode> {
+        // Instruction: 0110 0010 0011 0111 (0x6237)
+        // Mask 0x0F00: 0000 1111 0000 0000
+        // ─────────────────────
+        // Result:      0000 0010 0000 0000 = 0x0200 that is not the value expected, we
+        // have to shift it 8 bits to the right to ensure that we are getting the real
+        // argument:
+        // 0x0200 = 0000 0010 0000 0000
+        // >> 8   = 0000 0000 0000 0010 = 0x0002
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        let n = (opcode & 0x000F) as u8;
+        // Instruction: 0110 0010 0011 0111
+        // Mask 0x00FF: 0000 0000 1111 1111
+        // ─────────────────────
+        // Result:      0000 0000 0011 0111 = 0x0037
+        let nn = (opcode & 0x00FF) as u8;
+        // Instruction: 0110 0010 0011 0111
+        // Mask 0x0FFF: 0000 1111 1111 1111
+        // ─────────────────────
+        // Result:      0000 0010 0011 0111 = 0x0237
+        let nnn = opcode & 0x0FFF;
+
+        // THIS IS GETTING ONLY THE TYPE OF THE INSTRUCTION AS EACH CALL FOR THE CPU WILL HAVE ARGS
+        // WITH IT SO IT MUST ISOLATE ONLY THE TYPE OF THE CALL AND PROCESS THE RANDOM ARGS INSIDE
+        // OF THE BLOCK THIS MASK IS ISOLATING ONLY THE FIRST 4 BITS OF THE INSTRUCTION
+        // MASK: 1111 0000 0000 0000
+        let instruction = match opcode & 0xF000 {
+            SYSTEM => match opcode {
+                CLEAR => Instruction::Clear,
+                POP_THE_TOP_OF_THE_STACK_AS_THE_CURRENT_PROGRAM_COUNTER => Instruction::Return,
+                SCROLL_RIGHT_4_PIXELS => Instruction::ScrollRight,
+                SCROLL_LEFT_4_PIXELS => Instruction::ScrollLeft,
+                EXIT_THE_INTERPRETER => Instruction::Exit,
+                DISABLE_HIGH_RESOLUTION => Instruction::LowResolution,
+                ENABLE_HIGH_RESOLUTION => Instruction::HighResolution,
+                _ if opcode & 0xFFF0 == SCROLL_DOWN_N_PIXELS => Instruction::ScrollDown { n },
+                _ if opcode & 0xFFF0 == SCROLL_UP_N_PIXELS => Instruction::ScrollUp { n },
+                _ => Instruction::MachineCodeRoutine { nnn },
+            },
+            JUMP_TO_NNN => Instruction::Jump { nnn },
+            SET_PROGRAM_COUNTER_TO_THE_TOP_OF_THE_STACK_AND_GO_TO_NNN => Instruction::Call { nnn },
+            SKIP_NEXT_INSTRUCTION_IF_X_IS_EQUAL_TO_KK => Instruction::SkipIfXEqualsNn { x, nn },
+            SKIP_NEXT_INSTRUCTION_IF_X_IS_DIFFERENT_OF_KK => {
+                Instruction::SkipIfXDiffersNn { x, nn }
+            }
+            SKIP_NEXT_INSTRUCTION_IF_X_IS_EQUAL_TO_Y => match opcode & 0xF00F {
+                SKIP_NEXT_INSTRUCTION_IF_X_IS_EQUAL_TO_Y => Instruction::SkipIfXEqualsY { x, y },
+                STORE_VX_TO_VY_ON_I => Instruction::StoreXToYOnI { x, y },
+                LOAD_VX_TO_VY_FROM_I => Instruction::LoadXToYFromI { x, y },
+                _ => return Err(UnknownOpcode(opcode)),
+            },
+            SET_NN_TO_VX => Instruction::SetNnToX { x, nn },
+            SUM_NN_TO_VX => Instruction::SumNnToX { x, nn },
+            // MASK: 1111 0000 0000 1111, the last nibble tells which operation it is
+            ARITHMETIC => match opcode & 0xF00F {
+                STORE_THE_VALUE_OF_REGISTER_X_TO_REGISTER_Y => Instruction::StoreYToX { x, y },
+                OR_TO_X_AND_Y => Instruction::OrYToX { x, y },
+                AND_TO_X_AND_Y => Instruction::AndYToX { x, y },
+                XOR_TO_X_AND_Y => Instruction::XorYToX { x, y },
+                ADD_VX_WITH_VY_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS => {
+                    Instruction::SumYToX { x, y }
+                }
+                SUBTRACT_VX_WITH_VY_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS => {
+                    Instruction::SubtractYFromX { x, y }
+                }
+                SHIFT_RIGHT_X => Instruction::ShiftRightX { x, y },
+                SUBTRACT_VY_WITH_VX_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS => {
+                    Instruction::SubtractXFromY { x, y }
+                }
+                SHIFT_LEFT_X => Instruction::ShiftLeftX { x, y },
+                _ => return Err(UnknownOpcode(opcode)),
+            },
+            SKIP_NEXT_INSTRUCTION_IF_X_IS_DIFFERENT_OF_Y if n == 0 => {
+                Instruction::SkipIfXDiffersY { x, y }
+            }
+            SET_NNN_TO_I => Instruction::SetNnnToI { nnn },
+            JUMP_TO_NNN_PLUS_V0 => Instruction::JumpToNnnPlusV0 { nnn },
+            RANDOM_AND_AND_WITH_KK => Instruction::RandomAndNnToX { x, nn },
+            DRAW => Instruction::Draw { x, y, n },
+            // MASK: 1111 0000 1111 1111, removes the X register leaving only the type of the call
+            KEYBOARD => match opcode & 0xF0FF {
+                SKIP_NEXT_INSTRUCTION_IF_X_KEY_WAS_PRESSED => {
+                    Instruction::SkipIfKeyOnXPressed { x }
+                }
+                SKIP_NEXT_INSTRUCTION_IF_X_KEY_WAS_NOT_PRESSED => {
+                    Instruction::SkipIfKeyOnXNotPressed { x }
+                }
+                _ => return Err(UnknownOpcode(opcode)),
+            },
+            // These two use the whole opcode, so they have to be matched before the mask
+            TIMERS_MEMORY_AND_KEYBOARD if opcode == SET_THE_NEXT_16_BITS_TO_I => {
+                Instruction::SetNextWordToI
+            }
+            TIMERS_MEMORY_AND_KEYBOARD if opcode == LOAD_THE_AUDIO_PATTERN_FROM_I => {
+                Instruction::LoadAudioPattern
+            }
+            TIMERS_MEMORY_AND_KEYBOARD => match opcode & 0xF0FF {
+                SET_DELAY_TIMER_TO_VX => Instruction::SetDelayTimerToX { x },
+                WAIT_TO_A_KEY_TO_BE_PRESSED_AND_STORE_IT_ON_THE_VX => Instruction::WaitKeyToX { x },
+                SET_VX_TO_DELAY_TIMER => Instruction::SetXToDelayTimer { x },
+                SET_VX_TO_SOUND_TIMER => Instruction::SetXToSoundTimer { x },
+                SUM_VX_TO_I => Instruction::SumXToI { x },
+                SET_I_TO_THE_FONT_OF_VX => Instruction::SetIToFontOfX { x },
+                STORE_BCD_OF_VX_ON_I => Instruction::StoreBcdOfXOnI { x },
+                STORE_V0_TO_VX_ON_I => Instruction::StoreV0ToXOnI { x },
+                LOAD_V0_TO_VX_FROM_I => Instruction::LoadV0ToXFromI { x },
+                SET_I_TO_THE_BIG_FONT_OF_VX => Instruction::SetIToBigFontOfX { x },
+                STORE_V0_TO_VX_ON_THE_FLAGS => Instruction::StoreV0ToXOnFlags { x },
+                LOAD_V0_TO_VX_FROM_THE_FLAGS => Instruction::LoadV0ToXFromFlags { x },
+                // The planes go where the register usually is
+                SELECT_THE_PLANES_N => Instruction::SelectPlanes { n: x as u8 },
+                SET_VX_TO_PITCH => Instruction::SetPitchToX { x },
+                _ => return Err(UnknownOpcode(opcode)),
+            },
+            _ => return Err(UnknownOpcode(opcode)),
+        };
+
+        Ok(instruction)
+    }
+
+    // The opcode of the instruction, `decode` of it gives the same instruction back. The address
+    // of F000 is not part of it and goes on the next 2 bytes
+    pub fn encode(&self) -> u16 {
+        let xy = |x: usize, y: usize| ((x as u16) << 8) | ((y as u16) << 4);
+        let xnn = |x: usize, nn: u8| ((x as u16) << 8) | nn as u16;
+        let vx = |x: usize| (x as u16) << 8;
+        match *self {
+            Instruction::MachineCodeRoutine { nnn } => SYSTEM | nnn,
+            Instruction::Clear => CLEAR,
+            Instruction::Return => POP_THE_TOP_OF_THE_STACK_AS_THE_CURRENT_PROGRAM_COUNTER,
+            Instruction::Jump { nnn } => JUMP_TO_NNN | nnn,
+            Instruction::Call { nnn } => {
+                SET_PROGRAM_COUNTER_TO_THE_TOP_OF_THE_STACK_AND_GO_TO_NNN | nnn
+            }
+            Instruction::SkipIfXEqualsNn { x, nn } => {
+                SKIP_NEXT_INSTRUCTION_IF_X_IS_EQUAL_TO_KK | xnn(x, nn)
+            }
+            Instruction::SkipIfXDiffersNn { x, nn } => {
+                SKIP_NEXT_INSTRUCTION_IF_X_IS_DIFFERENT_OF_KK | xnn(x, nn)
+            }
+            Instruction::SkipIfXEqualsY { x, y } => {
+                SKIP_NEXT_INSTRUCTION_IF_X_IS_EQUAL_TO_Y | xy(x, y)
+            }
+            Instruction::SetNnToX { x, nn } => SET_NN_TO_VX | xnn(x, nn),
+            Instruction::SumNnToX { x, nn } => SUM_NN_TO_VX | xnn(x, nn),
+            Instruction::StoreYToX { x, y } => {
+                STORE_THE_VALUE_OF_REGISTER_X_TO_REGISTER_Y | xy(x, y)
+            }
+            Instruction::OrYToX { x, y } => OR_TO_X_AND_Y | xy(x, y),
+            Instruction::AndYToX { x, y } => AND_TO_X_AND_Y | xy(x, y),
+            Instruction::XorYToX { x, y } => XOR_TO_X_AND_Y | xy(x, y),
+            Instruction::SumYToX { x, y } => {
+                ADD_VX_WITH_VY_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS | xy(x, y)
+            }
+            Instruction::SubtractYFromX { x, y } => {
+                SUBTRACT_VX_WITH_VY_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS | xy(x, y)
+            }
+            Instruction::ShiftRightX { x, y } => SHIFT_RIGHT_X | xy(x, y),
+            Instruction::SubtractXFromY { x, y } => {
+                SUBTRACT_VY_WITH_VX_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS | xy(x, y)
+            }
+            Instruction::ShiftLeftX { x, y } => SHIFT_LEFT_X | xy(x, y),
+            Instruction::SkipIfXDiffersY { x, y } => {
+                SKIP_NEXT_INSTRUCTION_IF_X_IS_DIFFERENT_OF_Y | xy(x, y)
+            }
+            Instruction::SetNnnToI { nnn } => SET_NNN_TO_I | nnn,
+            Instruction::JumpToNnnPlusV0 { nnn } => JUMP_TO_NNN_PLUS_V0 | nnn,
+            Instruction::RandomAndNnToX { x, nn } => RANDOM_AND_AND_WITH_KK | xnn(x, nn),
+            Instruction::Draw { x, y, n } => DRAW | xy(x, y) | n as u16,
+            Instruction::SkipIfKeyOnXPressed { x } => {
+                SKIP_NEXT_INSTRUCTION_IF_X_KEY_WAS_PRESSED | vx(x)
+            }
+            Instruction::SkipIfKeyOnXNotPressed { x } => {
+                SKIP_NEXT_INSTRUCTION_IF_X_KEY_WAS_NOT_PRESSED | vx(x)
+            }
+            Instruction::SetDelayTimerToX { x } => SET_DELAY_TIMER_TO_VX | vx(x),
+            Instruction::WaitKeyToX { x } => {
+                WAIT_TO_A_KEY_TO_BE_PRESSED_AND_STORE_IT_ON_THE_VX | vx(x)
+            }
+            Instruction::SetXToDelayTimer { x } => SET_VX_TO_DELAY_TIMER | vx(x),
+            Instruction::SetXToSoundTimer { x } => SET_VX_TO_SOUND_TIMER | vx(x),
+            Instruction::SumXToI { x } => SUM_VX_TO_I | vx(x),
+            Instruction::SetIToFontOfX { x } => SET_I_TO_THE_FONT_OF_VX | vx(x),
+            Instruction::StoreBcdOfXOnI { x } => STORE_BCD_OF_VX_ON_I | vx(x),
+            Instruction::StoreV0ToXOnI { x } => STORE_V0_TO_VX_ON_I | vx(x),
+            Instruction::LoadV0ToXFromI { x } => LOAD_V0_TO_VX_FROM_I | vx(x),
+            Instruction::ScrollDown { n } => SCROLL_DOWN_N_PIXELS | n as u16,
+            Instruction::ScrollRight => SCROLL_RIGHT_4_PIXELS,
+            Instruction::ScrollLeft => SCROLL_LEFT_4_PIXELS,
+            Instruction::Exit => EXIT_THE_INTERPRETER,
+            Instruction::LowResolution => DISABLE_HIGH_RESOLUTION,
+            Instruction::HighResolution => ENABLE_HIGH_RESOLUTION,
+            Instruction::SetIToBigFontOfX { x } => SET_I_TO_THE_BIG_FONT_OF_VX | vx(x),
+            Instruction::StoreV0ToXOnFlags { x } => STORE_V0_TO_VX_ON_THE_FLAGS | vx(x),
+            Instruction::LoadV0ToXFromFlags { x } => LOAD_V0_TO_VX_FROM_THE_FLAGS | vx(x),
+            Instruction::ScrollUp { n } => SCROLL_UP_N_PIXELS | n as u16,
+            Instruction::StoreXToYOnI { x, y } => STORE_VX_TO_VY_ON_I | xy(x, y),
+            Instruction::LoadXToYFromI { x, y } => LOAD_VX_TO_VY_FROM_I | xy(x, y),
+            Instruction::SetNextWordToI => SET_THE_NEXT_16_BITS_TO_I,
+            Instruction::SelectPlanes { n } => SELECT_THE_PLANES_N | vx(n as usize),
+            Instruction::LoadAudioPattern => LOAD_THE_AUDIO_PATTERN_FROM_I,
+            Instruction::SetPitchToX { x } => SET_VX_TO_PITCH | vx(x),
+        }
+    }
+
+    // Like `decode`, but the instructions of newer platforms are unknown. The ones on the 00NN
+    // range were machine code routines for the older platforms, so they are decoded as one
+    pub fn decode_for_platform(
+        opcode: u16,
+        platform: Platform,
+    ) -> Result<Instruction, UnknownOpcode> {
+        let instruction = Instruction::decode(opcode)?;
+        if (instruction.is_super_chip() && platform < Platform::SuperChip)
+            || (instruction.is_xo_chip() && platform < Platform::XoChip)
+        {
+            if opcode & 0xF000 == 0 {
+                return Ok(Instruction::MachineCodeRoutine {
+                    nnn: opcode & 0x0FFF,
+                });
+            }
+            return Err(UnknownOpcode(opcode));
+        }
+        Ok(instruction)
+    }
+
+    // Instructions that can skip the next one, the program can continue on any of both
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SkipIfXEqualsNn { .. }
+                | Instruction::SkipIfXDiffersNn { .. }
+                | Instruction::SkipIfXEqualsY { .. }
+                | Instruction::SkipIfXDiffersY { .. }
+                | Instruction::SkipIfKeyOnXPressed { .. }
+                | Instruction::SkipIfKeyOnXNotPressed { .. }
+        )
+    }
+
+    // The instructions added by the SUPER-CHIP, DXY0 is not one of them as it is a valid CHIP-8
+    // draw of 0 rows
+    pub fn is_super_chip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::ScrollDown { .. }
+                | Instruction::ScrollRight
+                | Instruction::ScrollLeft
+                | Instruction::Exit
+                | Instruction::LowResolution
+                | Instruction::HighResolution
+                | Instruction::SetIToBigFontOfX { .. }
+                | Instruction::StoreV0ToXOnFlags { .. }
+                | Instruction::LoadV0ToXFromFlags { .. }
+        )
+    }
+
+    // The mnemonic for the quirks it runs with, BNNN jumps relative to VX on the CHIP-48 and the
+    // SUPER-CHIP, where X is the first digit of the address
+    pub fn mnemonic(&self, quirks: Quirks) -> String {
+        match *self {
+            Instruction::JumpToNnnPlusV0 { nnn } if quirks.jump_uses_vx => {
+                format!("JP V{:X}, 0x{:03X}", nnn >> 8, nnn)
+            }
+            _ => self.to_string(),
+        }
+    }
+
+    pub fn is_xo_chip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::ScrollUp { .. }
+                | Instruction::StoreXToYOnI { .. }
+                | Instruction::LoadXToYFromI { .. }
+                | Instruction::SetNextWordToI
+                | Instruction::SelectPlanes { .. }
+                | Instruction::LoadAudioPattern
+                | Instruction::SetPitchToX { .. }
+        )
+    }
+}
+
+// The mnemonics of Cowgod's reference, extended with the ones commonly used for the SUPER-CHIP
+// and XO-CHIP. F000 is printed without its address, as it is not part of the opcode
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::MachineCodeRoutine { nnn } => write!(f, "SYS 0x{:03X}", nnn),
+            Instruction::Clear => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::Jump { nnn } => write!(f, "JP 0x{:03X}", nnn),
+            Instruction::Call { nnn } => write!(f, "CALL 0x{:03X}", nnn),
+            Instruction::SkipIfXEqualsNn { x, nn } => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipIfXDiffersNn { x, nn } => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipIfXEqualsY { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SetNnToX { x, nn } => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            Instruction::SumNnToX { x, nn } => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            Instruction::StoreYToX { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::OrYToX { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::AndYToX { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::XorYToX { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::SumYToX { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::SubtractYFromX { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRightX { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubtractXFromY { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeftX { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipIfXDiffersY { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::SetNnnToI { nnn } => write!(f, "LD I, 0x{:03X}", nnn),
+            Instruction::JumpToNnnPlusV0 { nnn } => write!(f, "JP V0, 0x{:03X}", nnn),
+            Instruction::RandomAndNnToX { x, nn } => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipIfKeyOnXPressed { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipIfKeyOnXNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::SetDelayTimerToX { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKeyToX { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetXToDelayTimer { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetXToSoundTimer { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::SumXToI { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::SetIToFontOfX { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::StoreBcdOfXOnI { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::StoreV0ToXOnI { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadV0ToXFromI { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::ScrollDown { n } => write!(f, "SCD {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowResolution => write!(f, "LOW"),
+            Instruction::HighResolution => write!(f, "HIGH"),
+            Instruction::SetIToBigFontOfX { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::StoreV0ToXOnFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadV0ToXFromFlags { x } => write!(f, "LD V{:X}, R", x),
+            Instruction::ScrollUp { n } => write!(f, "SCU {}", n),
+            Instruction::StoreXToYOnI { x, y } => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadXToYFromI { x, y } => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Instruction::SetNextWordToI => write!(f, "LD I, LONG"),
+            Instruction::SelectPlanes { n } => write!(f, "PLANE {}", n),
+            Instruction::LoadAudioPattern => write!(f, "AUDIO"),
+            Instruction::SetPitchToX { x } => write!(f, "PITCH V{:X}", x),
+        }
+    }
+}
@@ -1,11 +1,8 @@
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, poll, read};
 use std::collections::HashMap;
 use std::fmt::{self, Display, Formatter};
-use std::io::{self, Read};
-use std::process::exit;
-use std::time::Duration;
+use std::fs;
 
-#[derive(Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
 pub enum CosmacVIPKey {
     Key1,
     Key2,
@@ -26,31 +23,79 @@ pub enum CosmacVIPKey {
 }
 
 impl CosmacVIPKey {
+    // The registers hold the key as its hexadecimal value (0x0 to 0xF)
     pub fn from_u8(byte: u8) -> Option<Self> {
         match byte {
-            b'1' => Some(Self::Key1),
-            b'2' => Some(Self::Key2),
-            b'3' => Some(Self::Key3),
-            b'c' | b'C' => Some(Self::KeyC),
-            b'4' => Some(Self::Key4),
-            b'5' => Some(Self::Key5),
-            b'6' => Some(Self::Key6),
-            b'd' | b'D' => Some(Self::KeyD),
-            b'7' => Some(Self::Key7),
-            b'8' => Some(Self::Key8),
-            b'9' => Some(Self::Key9),
-            b'e' | b'E' => Some(Self::KeyE),
-            b'a' | b'A' => Some(Self::KeyA),
-            b'0' => Some(Self::Key0),
-            b'b' | b'B' => Some(Self::KeyB),
-            b'f' | b'F' => Some(Self::KeyF),
+            0x1 => Some(Self::Key1),
+            0x2 => Some(Self::Key2),
+            0x3 => Some(Self::Key3),
+            0xC => Some(Self::KeyC),
+            0x4 => Some(Self::Key4),
+            0x5 => Some(Self::Key5),
+            0x6 => Some(Self::Key6),
+            0xD => Some(Self::KeyD),
+            0x7 => Some(Self::Key7),
+            0x8 => Some(Self::Key8),
+            0x9 => Some(Self::Key9),
+            0xE => Some(Self::KeyE),
+            0xA => Some(Self::KeyA),
+            0x0 => Some(Self::Key0),
+            0xB => Some(Self::KeyB),
+            0xF => Some(Self::KeyF),
             _ => None,
         }
     }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Self::Key1 => 0x1,
+            Self::Key2 => 0x2,
+            Self::Key3 => 0x3,
+            Self::KeyC => 0xC,
+            Self::Key4 => 0x4,
+            Self::Key5 => 0x5,
+            Self::Key6 => 0x6,
+            Self::KeyD => 0xD,
+            Self::Key7 => 0x7,
+            Self::Key8 => 0x8,
+            Self::Key9 => 0x9,
+            Self::KeyE => 0xE,
+            Self::KeyA => 0xA,
+            Self::Key0 => 0x0,
+            Self::KeyB => 0xB,
+            Self::KeyF => 0xF,
+        }
+    }
 }
 
+// The default layout uses the left side of a QWERTY keyboard as the COSMAC VIP keypad
+//  1 2 3 4      1 2 3 C
+//  Q W E R  ->  4 5 6 D
+//  A S D F      7 8 9 E
+//  Z X C V      A 0 B F
+const DEFAULT_KEY_MAP: [(char, CosmacVIPKey); 16] = [
+    ('1', CosmacVIPKey::Key1),
+    ('2', CosmacVIPKey::Key2),
+    ('3', CosmacVIPKey::Key3),
+    ('4', CosmacVIPKey::KeyC),
+    ('q', CosmacVIPKey::Key4),
+    ('w', CosmacVIPKey::Key5),
+    ('e', CosmacVIPKey::Key6),
+    ('r', CosmacVIPKey::KeyD),
+    ('a', CosmacVIPKey::Key7),
+    ('s', CosmacVIPKey::Key8),
+    ('d', CosmacVIPKey::Key9),
+    ('f', CosmacVIPKey::KeyE),
+    ('z', CosmacVIPKey::KeyA),
+    ('x', CosmacVIPKey::Key0),
+    ('c', CosmacVIPKey::KeyB),
+    ('v', CosmacVIPKey::KeyF),
+];
+
 pub struct Keyboard {
     keys: HashMap<CosmacVIPKey, bool>,
+    // Host character to the CHIP-8 key it presses
+    key_map: HashMap<char, CosmacVIPKey>,
 }
 
 impl Display for CosmacVIPKey {
@@ -78,94 +123,112 @@ impl Display for CosmacVIPKey {
 }
 
 pub trait KeyboardTrait {
-    fn process_any_input(&mut self);
-    fn is_key_pressed(&mut self, byte: u8) -> bool;
-    fn map_key_to_chip8(&self, key: KeyCode) -> Option<CosmacVIPKey>;
-    fn get_key_pressed(&self) -> u8;
+    // Presses the key and releases every other one, as a terminal only reports the last key
+    // pressed
+    fn press_only(&mut self, key: CosmacVIPKey);
+    fn press(&mut self, key: CosmacVIPKey);
+    fn release(&mut self, key: CosmacVIPKey);
+    fn is_key_pressed(&self, byte: u8) -> bool;
+    fn map_key_to_chip8(&self, key: char) -> Option<CosmacVIPKey>;
+    fn get_key_pressed(&self) -> Option<u8>;
 }
 
 impl KeyboardTrait for Keyboard {
-    fn process_any_input(&mut self) {
-        let mut ctrl_c_pressed = false;
-
-        while poll(Duration::from_millis(0)).unwrap() {
-            if let Ok(Event::Key(KeyEvent {
-                kind,
-                state,
-                code,
-                modifiers,
-            })) = read()
-            {
-                if modifiers.contains(KeyModifiers::CONTROL) {
-                    match code {
-                        KeyCode::Char('c') => {
-                            ctrl_c_pressed = true;
-                        }
-                        _ => {}
-                    }
-
-                    if ctrl_c_pressed {
-                        println!("Ctrl+C pressed. Exiting...");
-                        exit(0);
-                    }
-                }
-
-                if let Some(chip8_key) = self.map_key_to_chip8(code) {
-                    for (key, value) in self.keys.iter_mut() {
-                        *value = key == &chip8_key
-                    }
-                }
-            }
+    fn press_only(&mut self, key: CosmacVIPKey) {
+        for (other_key, value) in self.keys.iter_mut() {
+            *value = *other_key == key
         }
+        self.keys.insert(key, true);
     }
 
-    fn is_key_pressed(&mut self, byte: u8) -> bool {
-        if let Some(key) = CosmacVIPKey::from_u8(byte) {
-            return self.keys.get(&key).is_none();
-        } else {
-            return false;
-        }
+    fn press(&mut self, key: CosmacVIPKey) {
+        self.keys.insert(key, true);
     }
 
-    fn map_key_to_chip8(&self, key: KeyCode) -> Option<CosmacVIPKey> {
-        match key {
-            KeyCode::Char(c) => match c.to_ascii_lowercase() {
-                '1' => Some(CosmacVIPKey::Key1),
-                '2' => Some(CosmacVIPKey::Key2),
-                '3' => Some(CosmacVIPKey::Key3),
-                '4' => Some(CosmacVIPKey::KeyC),
-                'q' => Some(CosmacVIPKey::Key4),
-                'w' => Some(CosmacVIPKey::Key5),
-                'e' => Some(CosmacVIPKey::Key6),
-                'r' => Some(CosmacVIPKey::KeyD),
-                'a' => Some(CosmacVIPKey::Key7),
-                's' => Some(CosmacVIPKey::Key8),
-                'd' => Some(CosmacVIPKey::Key9),
-                'f' => Some(CosmacVIPKey::KeyE),
-                'z' => Some(CosmacVIPKey::KeyA),
-                'x' => Some(CosmacVIPKey::Key0),
-                'c' => Some(CosmacVIPKey::KeyB),
-                'v' => Some(CosmacVIPKey::KeyF),
-                _ => None,
-            },
-            _ => None,
-        }
+    fn release(&mut self, key: CosmacVIPKey) {
+        self.keys.insert(key, false);
     }
 
-    fn get_key_pressed(&self) -> Option<CosmacVIPKey> {
-        for (key, value) in self.keys.iter_mut() {
-            match value {
-                true => return Some(key),
-                false => return None,
-            }
+    fn is_key_pressed(&self, byte: u8) -> bool {
+        match CosmacVIPKey::from_u8(byte) {
+            Some(key) => self.keys.get(&key).copied().unwrap_or(false),
+            None => false,
         }
     }
+
+    fn map_key_to_chip8(&self, key: char) -> Option<CosmacVIPKey> {
+        self.key_map.get(&key.to_ascii_lowercase()).copied()
+    }
+
+    // The lowest key wins when several are pressed, going through the map would depend on the
+    // order of the hashes and change between runs
+    fn get_key_pressed(&self) -> Option<u8> {
+        (0x0..=0xF).find(|&byte| self.is_key_pressed(byte))
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        Keyboard::new()
+    }
 }
 
 impl Keyboard {
     pub fn new() -> Keyboard {
         let keys = HashMap::new();
-        let keyboard = Keyboard { keys };
-        keyboard
+        let key_map = DEFAULT_KEY_MAP.into_iter().collect();
+        Keyboard { keys, key_map }
+    }
+
+    // Every key as one bit, bit 0 is the key 0 and bit 15 the key F
+    pub fn state(&self) -> u16 {
+        (0x0..=0xF)
+            .filter(|&byte| self.is_key_pressed(byte))
+            .fold(0, |state, byte| state | (1 << byte))
+    }
+
+    pub fn set_state(&mut self, state: u16) {
+        for byte in 0x0..=0xF {
+            if let Some(key) = CosmacVIPKey::from_u8(byte) {
+                self.keys.insert(key, state & (1 << byte) != 0);
+            }
+        }
+    }
+
+    // Replaces the layout with the one in the file. Each line has the host key followed by the
+    // CHIP-8 key in hexadecimal, `#` starts a comment:
+    //  # host chip-8
+    //  x      0
+    //  1      1
+    // Keys missing from the file are not mapped
+    pub fn load_key_map(&mut self, file_path: &str) -> Result<(), String> {
+        let content = fs::read_to_string(file_path)
+            .map_err(|e| format!("Failed to read key map {}: {}", file_path, e))?;
+
+        let mut key_map = HashMap::new();
+        for (line_number, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid_line = || format!("Invalid key map line {}: {}", line_number + 1, line);
+            let mut parts = line.split_whitespace();
+            let (Some(host), Some(chip8), None) = (parts.next(), parts.next(), parts.next()) else {
+                return Err(invalid_line());
+            };
+            let mut host_chars = host.chars();
+            let (Some(host), None) = (host_chars.next(), host_chars.next()) else {
+                return Err(invalid_line());
+            };
+            let chip8 = u8::from_str_radix(chip8, 16)
+                .ok()
+                .and_then(CosmacVIPKey::from_u8)
+                .ok_or_else(invalid_line)?;
+            key_map.insert(host.to_ascii_lowercase(), chip8);
+        }
+
+        self.key_map = key_map;
+        Ok(())
     }
 }
@@ -0,0 +1,48 @@
+pub mod assembler;
+pub mod audio;
+pub mod backend;
+pub mod checksum;
+pub mod condition;
+pub mod cpu;
+pub mod debugger;
+pub mod disassembler;
+pub mod display;
+pub mod gdb;
+pub mod golden;
+pub mod instruction;
+pub mod keyboard;
+pub mod memory;
+pub mod movie;
+pub mod octo;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod rom;
+pub mod save_state;
+pub mod scheduler;
+pub mod screenshot;
+pub mod stack;
+pub mod timers;
+
+pub use assembler::{Assembly, AssemblyError, assemble, assemble_file};
+pub use backend::{DisplayBackend, Framebuffer, HeadlessBackend};
+pub use condition::Condition;
+pub use cpu::{Cpu, CpuError, StepOutcome};
+pub use debugger::Debugger;
+pub use disassembler::{Disassembly, disassemble};
+pub use display::Display;
+pub use gdb::GdbStub;
+pub use golden::{RomTest, check_golden};
+pub use instruction::Instruction;
+pub use keyboard::Keyboard;
+pub use memory::{Memory, SharedMemory};
+pub use movie::Movie;
+pub use quirks::Quirks;
+pub use random::{RandomSource, ScriptedRandom, SeededRandom};
+pub use rewind::Rewind;
+pub use rom::{load_rom, load_rom_bytes, rom_hash};
+pub use save_state::StateError;
+pub use scheduler::Scheduler;
+pub use screenshot::{Palette, decode_pgm, encode_pbm, encode_pgm, encode_png};
+pub use stack::Stack;
+pub use timers::Timers;
@@ -1,379 +1,493 @@
-mod display;
-mod keyboard;
-mod memory;
-mod stack;
+mod cli;
+mod terminal;
+use chip8_core::assembler::assemble_file;
+use chip8_core::audio::{Buzzer, BuzzerTrait, WavSink};
+use chip8_core::backend::{DisplayBackend, Framebuffer};
+use chip8_core::cpu::{Cpu, RPL_FLAGS_SIZE};
+use chip8_core::debugger::{self, Debugger};
+use chip8_core::disassembler::disassemble;
+use chip8_core::display::DisplayTrait;
+use chip8_core::gdb::{GdbStub, Served};
+use chip8_core::memory::{Memory, SharedMemory};
+use chip8_core::movie::Movie;
+use chip8_core::octo::compile_file;
+use chip8_core::quirks::Quirks;
+use chip8_core::rewind::{Rewind, RewindTrait};
+use chip8_core::rom::{is_octo_source, load_rom, rom_hash};
+use chip8_core::scheduler::{FRAMES_PER_SECOND, FrameOutcome, Scheduler, SchedulerTrait};
+use chip8_core::screenshot::{encode_pbm, encode_pgm, encode_png};
+use chip8_core::timers::TimersTrait;
+use cli::{Command, Options};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
-use display::{Display, DisplayTrait};
-use keyboard::{Keyboard, KeyboardTrait};
-use memory::MemoryTrait;
-use memory::{MEMORY_SIZE, Memory, SharedMemory};
-use rand::Rng;
-use stack::{Stack, StackTrait};
 use std::cell::RefCell;
+use std::env;
 use std::fs;
 use std::io::{self, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::ExitCode;
 use std::rc::Rc;
+use std::time::Duration;
+use terminal::{BackendKind, Hotkey, Renderer};
 
-const PROGRAM_START: usize = 0x200;
-const SKIP_NEXT_INSTRUCTION_IF_X_IS_EQUAL_TO_Y: u16 = 0x5000;
-const SKIP_NEXT_INSTRUCTION_IF_X_IS_DIFFERENT_OF_Y: u16 = 0x9000;
-const SKIP_NEXT_INSTRUCTION_IF_X_IS_EQUAL_TO_KK: u16 = 0x3000;
-const SKIP_NEXT_INSTRUCTION_IF_X_IS_DIFFERENT_OF_KK: u16 = 0x4000;
-const MAX_ROM_SIZE: usize = MEMORY_SIZE - PROGRAM_START;
-const CLEAR: u16 = 0x00E0; // Clear screen (exact match, no mask needed because it doesn't send
-const POP_THE_TOP_OF_THE_STACK_AS_THE_CURRENT_PROGRAM_COUNTER: u16 = 0x00EE; // Clear screen (exact match, no mask needed because it doesn't send
-// args)
-const JUMP_TO_NNN: u16 = 0x1000; // 1NNN: Jump to address NNN 
-const JUMP_TO_NNN_PLUS_V0: u16 = 0xB000; // 1NNN: Jump to address NNN 
-const RANDOM_AND_AND_WITH_KK: u16 = 0xC000; // 1NNN: Jump to address NNN 
-const SET_PROGRAM_COUNTER_TO_THE_TOP_OF_THE_STACK_AND_GO_TO_NNN: u16 = 0x2000; // 1NNN: Jump to address NNN 
-const SET_NN_TO_VX: u16 = 0x6000; // 6XNN: Set VX to NN 
-const SUM_NN_TO_VX: u16 = 0x7000; // 7XNN: Add NN to VX 
-const STORE_THE_VALUE_OF_REGISTER_X_TO_REGISTER_Y: u16 = 0x8000; // 7XNN: Add NN to VX 
-const OR_TO_X_AND_Y: u16 = 0x8001; // 7XNN: Add NN to VX 
-const XOR_TO_X_AND_Y: u16 = 0x8003; // 7XNN: Add NN to VX 
-const AND_TO_X_AND_Y: u16 = 0x8002; // 7XNN: Add NN to VX 
-const ADD_VX_WITH_VY_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS: u16 = 0x8004; // 7XNN: Add NN to VX 
-const SUBTRACT_VX_WITH_VY_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS: u16 = 0x8005; // 7XNN: Add NN to VX 
-const SUBTRACT_VY_WITH_VX_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS: u16 = 0x8007; // 7XNN: Add NN to VX 
-const SHIFT_RIGHT_X: u16 = 0x8006; // 7XNN: Add NN to VX 
-const SKIP_NEXT_INSTRUCTION_IF_X_KEY_WAS_PRESSED: u16 = 0xE09E; // 7XNN: Add NN to VX 
-const SKIP_NEXT_INSTRUCTION_IF_X_KEY_WAS_NOT_PRESSED: u16 = 0xE0A1; // 7XNN: Add NN to VX 
-const SET_DELAY_TIMER_TO_VX: u16 = 0xF007; // 7XNN: Add NN to VX 
-const WAIT_TO_A_KEY_TO_BE_PRESSED_AND_STORE_IT_ON_THE_VX: u16 = 0xF00A; // 7XNN: Add NN to VX 
-const SHIFT_LEFT_X: u16 = 0x800E; // 7XNN: Add NN to VX 
-const SET_NNN_TO_I: u16 = 0xA000; // ANNN: Set I to NNN 
-const DRAW: u16 = 0xD000; // DXYN: Draw sprite at (VX, VY) with height N 
-
-pub fn load_rom(file_path: &str, memory: SharedMemory) -> Result<Vec<u8>, String> {
-    let rom_data = fs::read(file_path).map_err(|e| match e.kind() {
-        io::ErrorKind::NotFound => format!("ROM file not found: {}", file_path),
-        io::ErrorKind::PermissionDenied => format!("Permission denied reading: {}", file_path),
-        _ => format!("Failed to read ROM file: {}", e),
-    })?;
-
-    if rom_data.len() > MAX_ROM_SIZE {
-        return Err(format!(
-            "ROM too large: {} bytes (max: {} bytes)",
-            rom_data.len(),
-            MAX_ROM_SIZE
-        ));
-    }
-
-    if rom_data.is_empty() {
-        return Err("ROM file is empty".to_string());
-    }
+// What happens with the keys of every frame
+enum MovieMode {
+    Off,
+    Recording(Movie),
+    // The keys come from the movie instead of the keyboard
+    Replaying(Movie),
+}
 
-    for (i, &byte) in rom_data.iter().enumerate() {
-        memory.borrow_mut().write(PROGRAM_START + i, byte);
+fn process_instructions(
+    cpu: &mut Cpu,
+    scheduler: &mut Scheduler,
+    renderer: &mut Renderer,
+    buzzer: Option<&mut Buzzer>,
+    movie_mode: &mut MovieMode,
+    options: &Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if options.headless {
+        let result = run_frames(cpu, scheduler, renderer, buzzer, movie_mode, options);
+        // Nothing was printed while running, the last frame is the only output
+        renderer.print(cpu.display());
+        println!("{}", cpu);
+        return result;
     }
 
-    println!("Loaded ROM: {} ({} bytes)", file_path, rom_data.len());
-    println!("Memory: {}", memory.borrow());
-    Ok(rom_data)
+    enable_raw_mode()?;
+    let result = run_frames(cpu, scheduler, renderer, buzzer, movie_mode, options);
+    disable_raw_mode()?;
+    result
 }
 
-fn process_instructions(
-    memory: SharedMemory,
-    display: &mut Display,
-    stack: &mut Stack,
-    keyboard: &mut Keyboard,
+fn run_frames(
+    cpu: &mut Cpu,
+    scheduler: &mut Scheduler,
+    renderer: &mut Renderer,
+    mut buzzer: Option<&mut Buzzer>,
+    movie_mode: &mut MovieMode,
+    options: &Options,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let mut program_counter = PROGRAM_START;
-    let mut registers: [u8; 16] = [0x00; 16];
-    let mut i_register = 0x00;
-    let mut f_register: u8 = 0x00;
-    let mut program_counter_sum_value = 2;
-    loop {
-        enable_raw_mode()?;
-        keyboard.process_any_input();
-        disable_raw_mode()?;
-        // display.print();
-        let first_byte = memory.borrow().retrieve(program_counter) as u16;
-        let second_byte = memory.borrow().retrieve(program_counter + 1) as u16;
-        let instruction = (first_byte << 8) + second_byte;
-        program_counter = program_counter + program_counter_sum_value;
-        if program_counter >= MEMORY_SIZE {
-            break;
+    let headless = options.headless;
+    let frame_duration = Duration::from_secs(1) / FRAMES_PER_SECOND;
+    let mut was_buzzing = false;
+    let mut rewind = Rewind::new(options.rewind_interval, options.rewind_budget);
+    let mut frame = 0;
+    let mut debugger = options.debug.then(Debugger::new);
+    let mut last_command = None;
+    let mut gdb = match options.gdb_port {
+        Some(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port))?;
+            // The terminal may be in raw mode already
+            eprint!("Waiting for gdb on 127.0.0.1:{}\r\n", port);
+            Some(GdbStub::accept(&listener)?)
         }
-        // THIS IS GETTING ONLY THE TYPE OF THE INSTRUCTION AS EACH CALL FOR THE CPU WILL HAVE ARGS
-        // WITH IT SO IT MUST ISOLATE ONLY THE TYPE OF THE CALL AND PROCESS THE RANDOM ARGS INSIDE
-        // OF THE BLOCK THIS MASK IS ISOLATING ONLY THE FIRST 4 BITS OF THE INSTRUCTION
-        // MASK: 1111 0000 0000 0000
-        match instruction & 0xF000 {
-            CLEAR => {
-                println!("Clearing the display");
-                display.clear();
-            }
-            POP_THE_TOP_OF_THE_STACK_AS_THE_CURRENT_PROGRAM_COUNTER => {
-                let value = stack.pop() as usize;
-                println!("Stack popping the value {} to program_counter", value);
-                program_counter = value;
-            }
-            SET_PROGRAM_COUNTER_TO_THE_TOP_OF_THE_STACK_AND_GO_TO_NNN => {
-                // Instruction: 0110 0010 0011 0111
-                // Mask 0x0FFF: 0000 1111 1111 1111
-                // _______________________________________________________________
-                // Result: 0000 0000 0011 0111 = 0x0037
-                let value = (instruction & 0x0FFF) as usize;
-                println!(
-                    "Stack pushing {} and program_counter navigating to {}",
-                    program_counter, value
-                );
-                stack.push(program_counter as u16);
-                program_counter = value;
-            }
-            SKIP_NEXT_INSTRUCTION_IF_X_IS_EQUAL_TO_KK => {
-                let register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let k_arg = (instruction & 0x00FF) as u8;
-                if registers[register_index] == k_arg {
-                    program_counter += 2;
+        None => None,
+    };
+    // A frame paused by the debugger is finished before starting a new one
+    let mut frame_in_progress = false;
+    'frames: loop {
+        if !headless {
+            for hotkey in terminal::process_any_input(cpu.keyboard_mut()) {
+                if hotkey == Hotkey::Quit {
+                    break 'frames;
                 }
-            }
-            STORE_THE_VALUE_OF_REGISTER_X_TO_REGISTER_Y => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let y_register_index = ((instruction & 0x00F0) >> 4) as usize;
-                registers[x_register_index] = registers[y_register_index];
-            }
-            SKIP_NEXT_INSTRUCTION_IF_X_IS_DIFFERENT_OF_Y => {
-                let register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let y_register_index = (instruction & 0x00F0 >> 4) as usize;
-                if registers[register_index] != registers[y_register_index] {
-                    program_counter += 2;
+                if hotkey == Hotkey::Break
+                    && let Some(debugger) = debugger.as_mut()
+                {
+                    debugger.pause("Paused");
+                    continue;
                 }
+                let message = handle_hotkey(cpu, &mut rewind, movie_mode, hotkey, options);
+                renderer.present(cpu.display());
+                renderer.status(cpu.display(), &message);
             }
-            SKIP_NEXT_INSTRUCTION_IF_X_IS_DIFFERENT_OF_KK => {
-                let register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let k_arg = (instruction & 0x00FF) as u8;
-                if registers[register_index] != k_arg {
-                    program_counter += 2;
+        }
+        if let Some(debugger) = debugger.as_mut()
+            && debugger.is_paused()
+            && prompt_debugger(cpu, debugger, renderer, &mut last_command)?
+        {
+            break;
+        }
+        if let Some(stub) = gdb.as_mut() {
+            stub.poll()?;
+            if stub.is_paused() {
+                match stub.serve(cpu)? {
+                    Served::Resumed => {}
+                    Served::Detached => gdb = None,
+                    Served::Killed => break,
                 }
             }
-            AND_TO_X_AND_Y => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let y_register_index = ((instruction & 0x00F0) >> 4) as usize;
-                registers[x_register_index] =
-                    registers[x_register_index] & registers[y_register_index]
-            }
-            XOR_TO_X_AND_Y => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let y_register_index = ((instruction & 0x00F0) >> 4) as usize;
-                registers[x_register_index] =
-                    registers[x_register_index] ^ registers[y_register_index];
+        }
+        if !frame_in_progress {
+            match movie_mode {
+                MovieMode::Off => {}
+                MovieMode::Recording(movie) => movie.record_frame(cpu.keyboard().state()),
+                MovieMode::Replaying(movie) => match movie.frame(frame) {
+                    Some(keys) => cpu.keyboard_mut().set_state(keys),
+                    None => break,
+                },
             }
-            OR_TO_X_AND_Y => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let y_register_index = ((instruction & 0x00F0) >> 4) as usize;
-                registers[x_register_index] =
-                    registers[x_register_index] | registers[y_register_index]
+            frame += 1;
+        }
+
+        let outcome = match (debugger.as_mut(), gdb.as_mut()) {
+            (Some(debugger), _) => {
+                scheduler.run_frame_until(cpu, &mut |cpu| debugger.should_pause(cpu))
             }
-            SKIP_NEXT_INSTRUCTION_IF_X_KEY_WAS_PRESSED => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let pressed_key = registers[x_register_index];
-                if keyboard.is_key_pressed(pressed_key) {
-                    program_counter += 2;
+            (None, Some(stub)) => scheduler.run_frame_until(cpu, &mut |cpu| stub.should_pause(cpu)),
+            (None, None) => scheduler.run_frame(cpu),
+        };
+        frame_in_progress = outcome == Ok(FrameOutcome::Paused);
+        match outcome {
+            Ok(FrameOutcome::Halted) | Ok(FrameOutcome::LimitReached) => {
+                if let Some(stub) = gdb.as_mut() {
+                    stub.report_exit()?;
                 }
+                break;
             }
-            SKIP_NEXT_INSTRUCTION_IF_X_KEY_WAS_NOT_PRESSED => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let pressed_key = registers[x_register_index];
-                if !keyboard.is_key_pressed(pressed_key) {
-                    program_counter += 2;
+            // The prompt is shown on the next turn of the loop
+            Ok(FrameOutcome::Paused) => continue,
+            Ok(FrameOutcome::Completed) => {
+                if cpu.display_mut().refresh() {
+                    renderer.present(cpu.display());
                 }
-            }
-            SKIP_NEXT_INSTRUCTION_IF_X_IS_EQUAL_TO_Y => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let y_register_index = ((instruction & 0x00F0) >> 4) as usize;
-                if registers[x_register_index] == registers[y_register_index] {
-                    program_counter += 2;
+                // Nobody can press the rewind key on a headless run
+                if !headless {
+                    rewind.record(cpu);
                 }
             }
-            SET_DELAY_TIMER_TO_VX => {
-                // TODO implement this when we have the delay timer implemented
-            }
-            WAIT_TO_A_KEY_TO_BE_PRESSED_AND_STORE_IT_ON_THE_VX => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                registers[x_register_index] = keyboard.get_key_pressed();
+            Err(e) => {
+                // gdb is told and keeps the session, it can move the program counter past the
+                // instruction
+                if let Some(stub) = gdb.as_mut() {
+                    stub.report_error(cpu, &e);
+                    if !headless {
+                        renderer.status(cpu.display(), &e.to_string());
+                    }
+                    frame_in_progress = true;
+                    continue;
+                }
+                if !headless {
+                    disable_raw_mode()?;
+                    println!("{}", cpu);
+                }
+                return Err(Box::new(e));
             }
-            ADD_VX_WITH_VY_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let y_register_index = ((instruction & 0x00F0) >> 4) as usize;
-                let sum = registers[x_register_index] + registers[y_register_index];
+        }
 
-                if sum > 255 {
-                    f_register = 1;
-                } else {
-                    f_register = 0;
-                }
+        // The audio follows the emulated time, not the wall clock, so a session recorded in
+        // turbo still sounds like it would in real time
+        let is_buzzing = cpu.timers().is_buzzer_active();
+        if let Some(buzzer) = buzzer.as_deref_mut() {
+            buzzer.set_pattern(cpu.audio_pattern());
+            buzzer.generate(is_buzzing, frame_duration)?;
+        }
+        // Without an audio backend the terminal bell is the closest to the buzzer we have
+        if is_buzzing && !was_buzzing && !headless {
+            print!("\x07");
+            io::stdout().flush()?;
+        }
+        was_buzzing = is_buzzing;
 
-                registers[x_register_index] = (sum & 0xFF) as u8; // Store lower 8 bits
-            }
-            SHIFT_RIGHT_X => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                // 0x01 represents 0000 0001 so we are checking if the last bit is 1
-                // this mask only validate the last bit ignoring the rest transforming them in 0
-                if registers[x_register_index] & 0x01 == 0x01 {
-                    f_register = 1;
-                } else {
-                    f_register = 0;
-                }
+        scheduler.wait_for_next_frame();
+    }
+    if let Some(buzzer) = buzzer {
+        buzzer.finish()?;
+    }
+    Ok(())
+}
+
+// Shows the machine and reads commands until one resumes it, returns true to quit
+fn prompt_debugger(
+    cpu: &Cpu,
+    debugger: &mut Debugger,
+    renderer: &mut Renderer,
+    last_command: &mut Option<debugger::Command>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut message = debugger.stop_reason().to_string();
+    loop {
+        let mut lines = debugger::registers_pane(cpu);
+        lines.push(String::new());
+        lines.extend(debugger::disassembly_pane(cpu, debugger, 5));
+        lines.push(String::new());
+        let watchpoints = debugger::watchpoints_pane(debugger);
+        if !watchpoints.is_empty() {
+            lines.extend(watchpoints);
+            lines.push(String::new());
+        }
+        lines.push(message);
+        renderer.present(cpu.display());
+        renderer.panel(cpu.display(), &lines);
 
-                registers[x_register_index] = registers[x_register_index] >> 1;
+        let Some(line) = terminal::read_line("(debug) ")? else {
+            return Ok(true);
+        };
+        let command = match (line.trim(), last_command.as_ref()) {
+            ("", Some(command)) => Ok(command.clone()),
+            ("", None) => Err(debugger::HELP.to_string()),
+            (line, _) => debugger::Command::parse(line),
+        };
+        message = match command {
+            Ok(debugger::Command::Quit) => return Ok(true),
+            Ok(command) => {
+                *last_command = Some(command.clone());
+                debugger.run_command(command, cpu).unwrap_or_else(|e| e)
             }
-            SHIFT_LEFT_X => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
+            Err(e) => e,
+        };
+        if !debugger.is_paused() {
+            return Ok(false);
+        }
+    }
+}
 
-                // 0x80 represents 1000 0000 so we are checking if the first bit is 1
-                // this mask only validate the first bit ignoring the rest transforming them in 0
-                if registers[x_register_index] & 0x80 == 0x80 {
-                    f_register = 1;
-                } else {
-                    f_register = 0;
-                }
+// The slots are files next to the ROM, so they are still there on the next session
+fn save_slot_path(rom_path: &str, slot: u8) -> String {
+    format!("{}.state{}", rom_path, slot)
+}
 
-                registers[x_register_index] = registers[x_register_index] << 1;
+// Returns the message shown to the user, a failed quick-save doesn't stop the emulation
+fn handle_hotkey(
+    cpu: &mut Cpu,
+    rewind: &mut Rewind,
+    movie_mode: &MovieMode,
+    hotkey: Hotkey,
+    options: &Options,
+) -> String {
+    let rom_path = &options.rom_path;
+    // Going back in time would leave frames in the movie that never happened
+    if matches!(hotkey, Hotkey::LoadState(_) | Hotkey::Rewind)
+        && !matches!(movie_mode, MovieMode::Off)
+    {
+        return "Not available with a movie".to_string();
+    }
+    match hotkey {
+        Hotkey::SaveState(slot) => {
+            match fs::write(save_slot_path(rom_path, slot), cpu.save_state()) {
+                Ok(()) => format!("Saved the state on slot {}", slot),
+                Err(e) => format!("Failed to save on slot {}: {}", slot, e),
             }
-            SUBTRACT_VX_WITH_VY_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let y_register_index = ((instruction & 0x00F0) >> 4) as usize;
+        }
+        Hotkey::LoadState(slot) => match fs::read(save_slot_path(rom_path, slot)) {
+            Ok(data) => match cpu.load_state(&data) {
+                Ok(()) => format!("Loaded the state from slot {}", slot),
+                Err(e) => format!("Failed to load slot {}: {}", slot, e),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => format!("Slot {} is empty", slot),
+            Err(e) => format!("Failed to load slot {}: {}", slot, e),
+        },
+        Hotkey::Rewind => {
+            if rewind.rewind(cpu) {
+                format!("Rewound, {} snapshots left", rewind.len())
+            } else {
+                "Nothing left to rewind".to_string()
+            }
+        }
+        Hotkey::Break => "Start with --debug to use the debugger".to_string(),
+        // Handled by the frame loop
+        Hotkey::Quit => String::new(),
+    }
+}
 
-                if registers[x_register_index] >= registers[y_register_index] {
-                    f_register = 1;
-                } else {
-                    f_register = 0;
-                }
+fn run(mut options: Options) -> Result<(), Box<dyn std::error::Error>> {
+    let mut movie_mode = MovieMode::Off;
+    if let Some(replay_path) = &options.replay_path {
+        let data = fs::read(replay_path)
+            .map_err(|e| format!("Failed to read the movie {}: {}", replay_path, e))?;
+        let movie = Movie::from_bytes(&data)?;
+        // Anything else would make the movie go out of sync
+        options.quirks = movie.quirks;
+        options.seed = Some(movie.seed);
+        options.instructions_per_second = movie.instructions_per_second;
+        movie_mode = MovieMode::Replaying(movie);
+    }
 
-                registers[x_register_index] =
-                    registers[x_register_index].wrapping_sub(registers[y_register_index]);
-            }
+    let memory: SharedMemory = Rc::new(RefCell::new(Memory::with_size(
+        options.quirks.platform.memory_size(),
+    )));
+    let rom_data = load_rom(&options.rom_path, Rc::clone(&memory))?;
+    println!(
+        "Loaded ROM: {} ({} bytes)",
+        options.rom_path,
+        rom_data.len()
+    );
+    println!("Memory: {}", memory.borrow());
+
+    let cpu = &mut Cpu::new(memory);
+    cpu.set_rom_hash(rom_hash(&rom_data));
+    cpu.set_quirks(options.quirks);
+    if let MovieMode::Replaying(movie) = &movie_mode {
+        movie.check_rom(cpu.rom_hash())?;
+    }
+    // A recording needs a seed to be replayed, one is picked when none was given
+    if options.record_path.is_some() && options.seed.is_none() {
+        options.seed = Some(rand::random());
+    }
+    if let Some(seed) = options.seed {
+        cpu.set_seed(seed);
+    }
+    if options.record_path.is_some() {
+        movie_mode = MovieMode::Recording(Movie::new(
+            cpu.rom_hash(),
+            options.quirks,
+            options.seed.unwrap_or_default(),
+            options.instructions_per_second,
+        ));
+    }
+    if let Some(key_map_path) = &options.key_map_path {
+        cpu.keyboard_mut().load_key_map(key_map_path)?;
+    }
+    let backend = if options.headless {
+        BackendKind::Headless
+    } else {
+        options.display_backend
+    };
+    let renderer = &mut Renderer::new(backend, options.scale);
 
-            SUBTRACT_VY_WITH_VX_AND_SET_TRUE_TO_VF_IF_ITS_MORE_THAN_8_BITS => {
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let y_register_index = ((instruction & 0x00F0) >> 4) as usize;
+    let scheduler = &mut Scheduler::new(options.instructions_per_second);
+    scheduler.set_speed(options.speed);
+    scheduler.set_max_instructions(options.max_cycles);
 
-                if registers[y_register_index] >= registers[x_register_index] {
-                    f_register = 1;
-                } else {
-                    f_register = 0;
-                }
+    let mut buzzer = match &options.wav_path {
+        Some(wav_path) => Some(Buzzer::new(
+            options.audio,
+            Box::new(WavSink::create(wav_path, options.audio.sample_rate)?),
+        )),
+        None => None,
+    };
 
-                registers[x_register_index] =
-                    registers[y_register_index].wrapping_sub(registers[x_register_index]);
-            }
+    if let Some(flags_path) = &options.flags_path {
+        cpu.set_rpl_flags(load_rpl_flags(flags_path)?);
+    }
 
-            SUM_NN_TO_VX => {
-                // Instruction: 0110 0010 0011 0111 (0x6237)
-                // Mask 0x0F00: 0000 1111 0000 0000
-                // ─────────────────────
-                // Result:      0000 0010 0000 0000 = 0x0200 that is not the value expected, we
-                // have to shift it 8 bits to the right to ensure that we are getting the real
-                // argument:
-                // 0x0200 = 0000 0010 0000 0000
-                // >> 8   = 0000 0000 0000 0010 = 0x0002
-                let register_index = ((instruction & 0x0F00) >> 8) as usize;
-                // Instruction: 0110 0010 0011 0111
-                // Mask 0x00FF: 0000 0000 1111 1111
-                // ─────────────────────
-                // Result: 0000 0000 0011 0111 = 0x0037
-                let value = instruction & 0x00FF;
-                println!(
-                    "Summing the value {} to the register {} that has the value {}",
-                    value, register_index, registers[register_index]
-                );
-                registers[register_index as usize] = registers[register_index] + value as u8;
-            }
-            SET_NNN_TO_I => {
-                // Instruction: 0110 0010 0011 0111
-                // Mask 0x0FFF: 0000 1111 1111 1111
-                // _______________________________________________________________
-                // Result: 0000 0000 0011 0111 = 0x0037
-                let value = (instruction & 0x0FFF) as usize;
-                println!("Setting the value {} to the register I", value);
-                i_register = value;
-            }
-            SET_NN_TO_VX => {
-                // Instruction: 0110 0010 0011 0111 (0x6237)
-                // Mask 0x0F00: 0000 1111 0000 0000
-                // ─────────────────────
-                // Result:      0000 0010 0000 0000 = 0x0200 that is not the value expected, we
-                // have to shift it 8 bits to the right to ensure that we are getting the real
-                // argument:
-                // 0x0200 = 0000 0010 0000 0000
-                // >> 8   = 0000 0000 0000 0010 = 0x0002
-                let register_index = (instruction & 0x0F00) >> 8;
-                // Instruction: 0110 0010 0011 0111
-                // Mask 0x00FF: 0000 0000 1111 1111
-                // ─────────────────────
-                // Result:      0000 0000 0011 0111 = 0x0037
-                let value = instruction & 0x0FFF;
-                println!(
-                    "Setting the value {} to the register {}",
-                    value, register_index
-                );
-                registers[register_index as usize] = value as u8;
-            }
-            RANDOM_AND_AND_WITH_KK => {
-                // Instruction: 0110 0010 0011 0111
-                // Mask 0x0FFF: 0000 1111 1111 1111
-                // _______________________________________________________________
-                // Result: 0000 0000 0011 0111 = 0x0037
-                let mut rng = rand::rng();
-                let kk_mask = (instruction & 0x00FF) as u8;
-                let x_register_index = ((instruction & 0x0F00) >> 8) as usize;
-                let random_byte = rng.random_range(0..256);
-                println!(
-                    "random number provided: {} (must be between 0 to 256)",
-                    random_byte
-                );
-                registers[x_register_index] = random_byte & kk_mask;
-            }
-            JUMP_TO_NNN_PLUS_V0 => {
-                // Instruction: 0110 0010 0011 0111
-                // Mask 0x0FFF: 0000 1111 1111 1111
-                // _______________________________________________________________
-                // Result: 0000 0000 0011 0111 = 0x0037
-                let value = (instruction & 0x0FFF) as usize;
-                // println!("Jumping to the value {}", value);
-                program_counter = value + registers[0] as usize;
-            }
-            JUMP_TO_NNN => {
-                // Instruction: 0110 0010 0011 0111
-                // Mask 0x0FFF: 0000 1111 1111 1111
-                // _______________________________________________________________
-                // Result: 0000 0000 0011 0111 = 0x0037
-                let value = (instruction & 0x0FFF) as usize;
-                // println!("Jumping to the value {}", value);
-                program_counter = value;
-            }
-            DRAW => {
-                let register_index_for_x = ((instruction & 0x0F00) >> 8) as usize;
-                let register_index_for_y = ((instruction & 0x00F0) >> 4) as usize;
+    let result = process_instructions(
+        cpu,
+        scheduler,
+        renderer,
+        buzzer.as_mut(),
+        &mut movie_mode,
+        &options,
+    );
+    if let Some(flags_path) = &options.flags_path {
+        fs::write(flags_path, cpu.rpl_flags())?;
+    }
+    if let Some(screenshot_path) = &options.screenshot_path {
+        save_screenshot(cpu, screenshot_path, &options)?;
+    }
+    // Written even when the ROM failed, that is when the movie is the most useful
+    if let (Some(record_path), MovieMode::Recording(movie)) = (&options.record_path, &movie_mode) {
+        fs::write(record_path, movie.to_bytes())?;
+    }
+    result
+}
+
+fn save_screenshot(cpu: &Cpu, path: &str, options: &Options) -> Result<(), String> {
+    let frame = Framebuffer::from_display(cpu.display());
+    let lowercase_path = path.to_ascii_lowercase();
+    let data = if lowercase_path.ends_with(".pbm") {
+        encode_pbm(&frame, options.screenshot_scale, &options.palette)
+    } else if lowercase_path.ends_with(".pgm") {
+        // The format of the golden images, always one value for each pixel
+        encode_pgm(&frame)
+    } else {
+        encode_png(&frame, options.screenshot_scale, &options.palette)
+    };
+    fs::write(path, data).map_err(|e| format!("Failed to save the screenshot {}: {}", path, e))
+}
+
+fn run_disassemble(
+    rom_path: &str,
+    quirks: Quirks,
+    source: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rom = fs::read(rom_path).map_err(|e| format!("Failed to read ROM {}: {}", rom_path, e))?;
+    let disassembly = disassemble(&rom, quirks);
+    let text = if source {
+        disassembly.source()
+    } else {
+        disassembly.to_string()
+    };
+    // Written at once so a closed pipe is an error instead of a panic
+    io::stdout().lock().write_all(text.as_bytes())?;
+    Ok(())
+}
+
+fn run_assemble(
+    source_path: &str,
+    rom_path: Option<String>,
+    symbols_path: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = Path::new(source_path);
+    let assembly = if is_octo_source(source) {
+        compile_file(source)?
+    } else {
+        assemble_file(source)?
+    };
+    let rom_path = rom_path.unwrap_or_else(|| {
+        Path::new(source_path)
+            .with_extension("ch8")
+            .display()
+            .to_string()
+    });
+    fs::write(&rom_path, &assembly.rom)
+        .map_err(|e| format!("Failed to write the ROM {}: {}", rom_path, e))?;
+    if let Some(symbols_path) = symbols_path {
+        fs::write(&symbols_path, assembly.symbol_map())
+            .map_err(|e| format!("Failed to write the symbols {}: {}", symbols_path, e))?;
+    }
+    println!("Assembled {} ({} bytes)", rom_path, assembly.rom.len());
+    Ok(())
+}
 
-                let number_of_bytes_to_present = (instruction & 0x000F) as usize;
-                let y = registers[register_index_for_y];
-                let x = registers[register_index_for_x];
-                display.draw(
-                    x as usize,
-                    y as usize,
-                    number_of_bytes_to_present,
-                    i_register,
-                );
+// A missing file is the first run of the program, every flag starts as 0
+fn load_rpl_flags(path: &str) -> Result<[u8; RPL_FLAGS_SIZE], String> {
+    let mut rpl_flags = [0x00; RPL_FLAGS_SIZE];
+    match fs::read(path) {
+        Ok(data) => {
+            if data.len() > RPL_FLAGS_SIZE {
+                return Err(format!("The flags file {} is too big", path));
             }
-            default => {} // default => panic!("TODO not implemented yet {}", default),
+            rpl_flags[..data.len()].copy_from_slice(&data);
         }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("Failed to read the flags file {}: {}", path, e)),
     }
-    Ok(())
+    Ok(rpl_flags)
+}
+
+fn main() -> ExitCode {
+    let options = match cli::parse_args(env::args().skip(1)) {
+        Ok(Command::Run(options)) => *options,
+        Ok(Command::Disassemble {
+            rom_path,
+            quirks,
+            source,
+        }) => return exit_code(run_disassemble(&rom_path, quirks, source)),
+        Ok(Command::Assemble {
+            source_path,
+            rom_path,
+            symbols_path,
+        }) => return exit_code(run_assemble(&source_path, rom_path, symbols_path)),
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    exit_code(run(options))
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let stack = &mut Stack::new();
-    let memory: SharedMemory = Rc::new(RefCell::new(Memory::new()));
-    let display = &mut Display::new(Rc::clone(&memory));
-    let result = load_rom("IBM Logo.ch8", Rc::clone(&memory));
-    let keyboard = &mut Keyboard::new();
+fn exit_code(result: Result<(), Box<dyn std::error::Error>>) -> ExitCode {
     match result {
-        Ok(_) => process_instructions(memory, display, stack, keyboard),
-        Err(e) => panic!("Failed to load the ROM: {}", e),
-    }?;
-    Ok(())
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -1,23 +1,56 @@
+use crate::save_state::{StateError, StateReader, StateWriter};
 use std::cell::RefCell;
 use std::fmt;
 use std::rc::Rc;
 
 pub const MEMORY_SIZE: usize = 4096;
-const FONT_START_ADDRESS: usize = 0x50;
+// The XO-CHIP can address 64 KiB with F000 NNNN
+pub const XO_CHIP_MEMORY_SIZE: usize = 0x10000;
+pub const FONT_START_ADDRESS: usize = 0x50;
+// Each character of the font is 4 pixels wide and 5 rows (bytes) tall
+pub const FONT_CHARACTER_SIZE: usize = 5;
+// The SUPER-CHIP big font goes right after the small one
+pub const BIG_FONT_START_ADDRESS: usize = 0xA0;
+// Each character of the big font is 8 pixels wide and 10 rows tall
+pub const BIG_FONT_CHARACTER_SIZE: usize = 10;
 
 pub type SharedMemory = Rc<RefCell<Memory>>;
 pub struct Memory {
-    memory: [u8; MEMORY_SIZE],
+    memory: Vec<u8>,
+    // Only kept while the debugger watches the memory. `retrieve` doesn't borrow the memory
+    // mutably, so the list needs its own RefCell
+    accesses: Option<RefCell<Vec<Access>>>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+// A read or a write done through MemoryTrait, the old value is the same as the value for a read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub address: usize,
+    pub kind: AccessKind,
+    pub old_value: u8,
+    pub value: u8,
+}
+
+// What the CPU and the display need from the memory, so a machine can run on another bus
 pub trait MemoryTrait {
     fn write(&mut self, position: usize, value: u8) -> bool;
     fn retrieve(&self, position: usize) -> u8;
+    // Reads without being recorded, for the debugger looking at the memory and the CPU fetching
+    // its instructions
+    fn peek(&self, position: usize) -> u8;
+    fn size(&self) -> usize;
 }
 
 impl fmt::Display for Memory {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "Memory ({} bytes)", MEMORY_SIZE)?;
+        let size = self.size();
+        writeln!(f, "Memory ({} bytes)", size)?;
         writeln!(f, "====================")?;
 
         // Display interpreter area (0x000 - 0x1FF)
@@ -36,7 +69,7 @@ impl fmt::Display for Memory {
         // Show first few bytes of font data as example
         write!(f, "  First 16 bytes: ")?;
         for i in 0..16 {
-            if i < 80 && FONT_START_ADDRESS + i < MEMORY_SIZE {
+            if i < 80 && FONT_START_ADDRESS + i < size {
                 write!(f, "{:02X} ", self.memory[FONT_START_ADDRESS + i])?;
             }
         }
@@ -46,13 +79,13 @@ impl fmt::Display for Memory {
         writeln!(
             f,
             "Program Area (0x200-0x{:03X} / 512-{}):",
-            MEMORY_SIZE - 1,
-            MEMORY_SIZE - 1
+            size - 1,
+            size - 1
         )?;
 
         // Find the last non-zero byte in program area to avoid showing empty memory
         let mut last_used = 0x200;
-        for i in (0x200..MEMORY_SIZE).rev() {
+        for i in (0x200..size).rev() {
             if self.memory[i] != 0 {
                 last_used = i;
                 break;
@@ -76,7 +109,7 @@ impl fmt::Display for Memory {
             for row in (0x200..=std::cmp::min(0x20F, last_used)).step_by(16) {
                 write!(f, "  {:03X} ({:4}): ", row, row)?;
                 for col in 0..16 {
-                    if row + col <= last_used && row + col < MEMORY_SIZE {
+                    if row + col <= last_used && row + col < size {
                         write!(f, "{:02X} ", self.memory[row + col])?;
                     } else {
                         write!(f, "   ")?;
@@ -92,38 +125,108 @@ impl fmt::Display for Memory {
     }
 }
 impl MemoryTrait for Memory {
+    // Nothing is written outside of the program area, the caller is told with false
     fn write(&mut self, position: usize, value: u8) -> bool {
-        if position >= MEMORY_SIZE {
-            println!("Position not supported on the memory");
+        if position >= self.size() {
             return false;
         }
         // The CHIP used the 0 to 512 indexes to allocate the interpreter
         if position <= 0x1FF {
-            println!("Memory allocated to the interpreter");
             return false;
         }
+        if let Some(accesses) = &self.accesses {
+            accesses.borrow_mut().push(Access {
+                address: position,
+                kind: AccessKind::Write,
+                old_value: self.memory[position],
+                value,
+            });
+        }
         self.memory[position] = value;
-        return true;
+        true
     }
 
+    // Reads past the end of the memory give 0
     fn retrieve(&self, position: usize) -> u8 {
-        if position >= MEMORY_SIZE {
-            println!("Position {} not supported on the memory", position);
+        if position >= self.size() {
             return 0x00;
         }
-        self.memory[position]
+        let value = self.memory[position];
+        if let Some(accesses) = &self.accesses {
+            accesses.borrow_mut().push(Access {
+                address: position,
+                kind: AccessKind::Read,
+                old_value: value,
+                value,
+            });
+        }
+        value
+    }
+
+    fn peek(&self, position: usize) -> u8 {
+        self.memory.get(position).copied().unwrap_or(0x00)
+    }
+
+    fn size(&self) -> usize {
+        self.memory.len()
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
     }
 }
 
 impl Memory {
     pub fn new() -> Memory {
+        Memory::with_size(MEMORY_SIZE)
+    }
+
+    // Bigger memories are for the XO-CHIP, anything below the program area is still reserved
+    pub fn with_size(size: usize) -> Memory {
         let mut memory = Memory {
-            memory: [0; MEMORY_SIZE],
+            memory: vec![0; size.max(MEMORY_SIZE)],
+            accesses: None,
         };
         memory.load_font();
         memory
     }
 
+    // Starts or stops keeping every read and write, the list is emptied when stopping
+    pub fn record_accesses(&mut self, enabled: bool) {
+        if !enabled {
+            self.accesses = None;
+        } else if self.accesses.is_none() {
+            self.accesses = Some(RefCell::new(Vec::new()));
+        }
+    }
+
+    // The accesses since the last call, oldest first
+    pub fn take_accesses(&self) -> Vec<Access> {
+        self.accesses
+            .as_ref()
+            .map(|accesses| accesses.take())
+            .unwrap_or_default()
+    }
+
+    // The whole memory is written, the ROM itself can change while running
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u32(self.memory.len() as u32);
+        writer.write_bytes(&self.memory);
+    }
+
+    pub(crate) fn load_state(reader: &mut StateReader) -> Result<Memory, StateError> {
+        let size = reader.read_u32()? as usize;
+        if !(MEMORY_SIZE..=XO_CHIP_MEMORY_SIZE).contains(&size) {
+            return Err(StateError::InvalidFormat);
+        }
+        Ok(Memory {
+            memory: reader.read_bytes(size)?.to_vec(),
+            accesses: None,
+        })
+    }
+
     fn load_font(&mut self) {
         const FONT_DATA: [u8; 80] = [
             0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -148,5 +251,30 @@ impl Memory {
         for (i, &byte) in FONT_DATA.iter().enumerate() {
             self.memory[FONT_START_ADDRESS + i] = byte;
         }
+
+        // The SUPER-CHIP only had the digits, A to F are drawn in the same style for the ROMs that
+        // print hex numbers
+        const BIG_FONT_DATA: [u8; 160] = [
+            0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+            0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+            0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+            0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+            0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+            0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+            0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+            0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+            0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+            0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+            0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+            0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+            0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+        ];
+
+        for (i, &byte) in BIG_FONT_DATA.iter().enumerate() {
+            self.memory[BIG_FONT_START_ADDRESS + i] = byte;
+        }
     }
 }
@@ -0,0 +1,101 @@
+use crate::quirks::Quirks;
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+// Every movie starts with these bytes, followed by the version
+pub const MOVIE_MAGIC: &[u8; 4] = b"C8MV";
+pub const MOVIE_VERSION: u16 = 1;
+
+// The keypad state of every frame of a session, with everything else needed to run it again
+// exactly the same way. The emulator is deterministic when the ROM, the quirks, the seed, the
+// clock and the keys of each frame are the same
+pub struct Movie {
+    pub rom_hash: u32,
+    pub quirks: Quirks,
+    pub seed: u64,
+    pub instructions_per_second: u32,
+    // One bit for each key, see `Keyboard::state`
+    frames: Vec<u16>,
+}
+
+impl Movie {
+    pub fn new(rom_hash: u32, quirks: Quirks, seed: u64, instructions_per_second: u32) -> Movie {
+        Movie {
+            rom_hash,
+            quirks,
+            seed,
+            instructions_per_second,
+            frames: Vec::new(),
+        }
+    }
+
+    // Called once per frame with the keys pressed while it runs
+    pub fn record_frame(&mut self, keys: u16) {
+        self.frames.push(keys);
+    }
+
+    // The keys of the frame, None once the movie is over
+    pub fn frame(&self, index: usize) -> Option<u16> {
+        self.frames.get(index).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // A movie only plays back in sync with the ROM it was recorded with
+    pub fn check_rom(&self, rom_hash: u32) -> Result<(), StateError> {
+        if self.rom_hash != rom_hash {
+            return Err(StateError::WrongRom {
+                expected: rom_hash,
+                found: self.rom_hash,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.write_bytes(MOVIE_MAGIC);
+        writer.write_u16(MOVIE_VERSION);
+        writer.write_u32(self.rom_hash);
+        writer.write_u64(self.seed);
+        writer.write_u32(self.instructions_per_second);
+        self.quirks.save_state(&mut writer);
+        writer.write_u32(self.frames.len() as u32);
+        for &keys in &self.frames {
+            writer.write_u16(keys);
+        }
+        writer.into_bytes()
+    }
+
+    // The ROM is checked by the caller with `check_rom`, it is the one that knows which ROM is
+    // loaded
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, StateError> {
+        let mut reader = StateReader::new(data);
+        if reader.read_bytes(MOVIE_MAGIC.len())? != MOVIE_MAGIC {
+            return Err(StateError::InvalidFormat);
+        }
+        let version = reader.read_u16()?;
+        if version != MOVIE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let mut movie = Movie {
+            rom_hash: reader.read_u32()?,
+            seed: reader.read_u64()?,
+            instructions_per_second: reader.read_u32()?,
+            quirks: Quirks::load_state(&mut reader)?,
+            frames: Vec::new(),
+        };
+        let frame_count = reader.read_u32()?;
+        for _ in 0..frame_count {
+            movie.frames.push(reader.read_u16()?);
+        }
+        reader.finish()?;
+        Ok(movie)
+    }
+}
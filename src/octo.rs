@@ -0,0 +1,1025 @@
+use crate::assembler::{Assembly, AssemblyError};
+use crate::cpu::PROGRAM_START;
+use crate::instruction::Instruction;
+use crate::memory::XO_CHIP_MEMORY_SIZE;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+// Deeper than this a macro is most likely expanding itself
+const MAX_MACRO_DEPTH: usize = 64;
+// Words of the language, they can't be labels, constants, aliases or macros
+const KEYWORDS: [&str; 41] = [
+    "clear",
+    "return",
+    "exit",
+    "lores",
+    "hires",
+    "scroll-down",
+    "scroll-up",
+    "scroll-left",
+    "scroll-right",
+    "bcd",
+    "save",
+    "load",
+    "saveflags",
+    "loadflags",
+    "sprite",
+    "jump",
+    "jump0",
+    "native",
+    "audio",
+    "plane",
+    "pitch",
+    "delay",
+    "buzzer",
+    "key",
+    "-key",
+    "random",
+    "hex",
+    "bighex",
+    "long",
+    "i",
+    "if",
+    "then",
+    "begin",
+    "else",
+    "end",
+    "loop",
+    "again",
+    "while",
+    "HERE",
+    "PI",
+    "E",
+];
+
+// Compiles an Octo program, the language of the Octo IDE and of most of the Octojam entries:
+//  :alias x v0                  # Registers can have names
+//  :const SPEED 2
+//  :calc HALF { SPEED / 2 }     # Evaluated right to left, without precedence
+//  :macro move R { R += SPEED }
+//  : main
+//    loop
+//      move x
+//      if x == 64 then x := 0
+//      i := sprite
+//      sprite x x 3
+//    again
+//  : sprite 0xF0 0x90 0xF0      # Bare numbers are bytes, bare names are calls
+// The program starts with a jump to `main`, like the Octo IDE does
+pub fn compile(source: &str) -> Result<Assembly, AssemblyError> {
+    Compiler::new("<source>", source).run()
+}
+
+pub fn compile_file(path: &Path) -> Result<Assembly, AssemblyError> {
+    let name = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|e| AssemblyError {
+        file: name.clone(),
+        line: 0,
+        message: format!("Failed to read the file: {}", e),
+    })?;
+    Compiler::new(&name, &source).run()
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    // How many macros were expanded to get it
+    depth: usize,
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+}
+
+enum Block {
+    // The jump to the `else` or the `end`, taken when the condition is false
+    If { jump: usize },
+    // The jump over the `else` branch, at the end of the `if` one
+    Else { jump: usize },
+    // The jumps of the `while`, to the address after `again`
+    Loop { start: usize, breaks: Vec<usize> },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FixupKind {
+    // The NNN of an instruction
+    Address,
+    // The 2 bytes after F000, or a `:pointer`
+    Long,
+    // `:unpack`, the high nibble of the address goes in the NN of `v0 := `
+    UnpackHigh,
+    // And the low byte in the NN of `v1 := `
+    UnpackLow,
+}
+
+// A label used before its definition, written once all of them are known
+struct Fixup {
+    address: usize,
+    kind: FixupKind,
+    name: String,
+    line: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Register(usize),
+    Byte(u8),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Comparison {
+    Equal,
+    Different,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+    Key,
+    NotKey,
+}
+
+impl Comparison {
+    fn negated(self) -> Comparison {
+        match self {
+            Comparison::Equal => Comparison::Different,
+            Comparison::Different => Comparison::Equal,
+            Comparison::Less => Comparison::GreaterOrEqual,
+            Comparison::GreaterOrEqual => Comparison::Less,
+            Comparison::Greater => Comparison::LessOrEqual,
+            Comparison::LessOrEqual => Comparison::Greater,
+            Comparison::Key => Comparison::NotKey,
+            Comparison::NotKey => Comparison::Key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Condition {
+    x: usize,
+    comparison: Comparison,
+    // Nothing for the keys
+    operand: Option<Operand>,
+}
+
+struct Compiler {
+    file: String,
+    tokens: VecDeque<Token>,
+    // Line of the last token read, for the errors
+    line: usize,
+    // From PROGRAM_START to the last byte written
+    rom: Vec<u8>,
+    address: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+}
+
+impl Compiler {
+    fn new(file: &str, source: &str) -> Compiler {
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(index, text)| {
+                let text = text.split('#').next().unwrap_or("");
+                text.split_whitespace()
+                    .map(|word| Token {
+                        text: word.to_string(),
+                        line: index + 1,
+                        depth: 0,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        Compiler {
+            file: file.to_string(),
+            tokens,
+            line: 0,
+            rom: Vec::new(),
+            address: PROGRAM_START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Result<Assembly, AssemblyError> {
+        if let Err(message) = self.compile() {
+            return Err(AssemblyError {
+                file: self.file,
+                line: self.line,
+                message,
+            });
+        }
+
+        let mut symbols: BTreeMap<String, i64> = self
+            .constants
+            .iter()
+            .map(|(name, value)| (name.clone(), value.floor() as i64))
+            .collect();
+        symbols.extend(
+            self.labels
+                .iter()
+                .map(|(name, address)| (name.clone(), *address as i64)),
+        );
+        Ok(Assembly {
+            rom: self.rom,
+            symbols,
+        })
+    }
+
+    fn compile(&mut self) -> Result<(), String> {
+        self.emit_with_address(Instruction::Jump { nnn: 0 }, "main")?;
+        while let Some(token) = self.tokens.pop_front() {
+            self.line = token.line;
+            self.statement(token)?;
+        }
+        match self.blocks.last() {
+            Some(Block::Loop { .. }) => return Err("A loop has no again".to_string()),
+            Some(_) => return Err("A begin has no end".to_string()),
+            None => {}
+        }
+        if !self.labels.contains_key("main") {
+            return Err("The program has no main label".to_string());
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            self.line = fixup.line;
+            let value = self
+                .value(&fixup.name)
+                .map_err(|_| format!("Unknown label: {}", fixup.name))?;
+            self.patch(fixup.address, fixup.kind, value)?;
+        }
+        Ok(())
+    }
+
+    fn next(&mut self, what: &str) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .pop_front()
+            .ok_or_else(|| format!("Missing {}", what))?;
+        self.line = token.line;
+        Ok(token)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        let token = self.next(text)?;
+        if token.text != text {
+            return Err(format!("Expected {} instead of {}", text, token.text));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), String> {
+        let text = token.text.as_str();
+        if self.macros.contains_key(text) {
+            return self.expand_macro(&token);
+        }
+        if let Some(x) = self.register(text) {
+            return self.register_statement(x);
+        }
+        if let Some(value) = parse_number(text) {
+            let byte = to_byte(value)?;
+            return self.emit_byte(byte);
+        }
+
+        match text {
+            ":" => {
+                let name = self.new_name()?;
+                self.labels.insert(name, self.address);
+            }
+            ":next" => {
+                // The label is on the second byte of the next instruction, to modify its NN
+                let name = self.new_name()?;
+                self.labels.insert(name, self.address + 1);
+            }
+            ":alias" => {
+                let name = self.next("a name")?.text;
+                if !self.aliases.contains_key(&name) {
+                    self.check_name(&name)?;
+                }
+                let register = self.next("a register")?;
+                let register = self
+                    .register(&register.text)
+                    .ok_or_else(|| format!("{} is not a register", register.text))?;
+                self.aliases.insert(name, register);
+            }
+            ":const" => {
+                let name = self.new_name()?;
+                let value = self.next("a value")?;
+                let value = self.value(&value.text)?;
+                self.constants.insert(name, value);
+            }
+            ":calc" => {
+                let name = self.new_name()?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":macro" => self.define_macro()?,
+            ":org" => {
+                let value = self.next("an address")?;
+                let address = to_integer(self.value(&value.text)?);
+                if !(PROGRAM_START as i64..XO_CHIP_MEMORY_SIZE as i64).contains(&address) {
+                    return Err(format!(":org can't be 0x{:X}", address));
+                }
+                self.address = address as usize;
+            }
+            ":byte" => {
+                let value = if self.tokens.front().is_some_and(|token| token.text == "{") {
+                    self.calc()?
+                } else {
+                    let value = self.next("a byte")?;
+                    self.value(&value.text)?
+                };
+                self.emit_byte(to_byte(value)?)?;
+            }
+            ":pointer" => {
+                let target = self.next("an address")?;
+                let address = self.address;
+                self.emit_byte(0)?;
+                self.emit_byte(0)?;
+                self.reference(address, FixupKind::Long, &target.text)?;
+            }
+            ":unpack" => {
+                // v0 gets the nibble with the high bits of the address, v1 its low byte
+                let nibble = self.next("a nibble")?;
+                let nibble = to_nibble(self.value(&nibble.text)?)?;
+                let target = self.next("a label")?;
+                let address = self.address;
+                self.emit(Instruction::SetNnToX {
+                    x: 0,
+                    nn: nibble << 4,
+                })?;
+                self.emit(Instruction::SetNnToX { x: 1, nn: 0 })?;
+                self.reference(address, FixupKind::UnpackHigh, &target.text)?;
+                self.reference(address + 2, FixupKind::UnpackLow, &target.text)?;
+            }
+            // Hints for the Octo debugger and forward declarations, nothing to emit
+            ":breakpoint" | ":proto" => {
+                self.next("a name")?;
+            }
+            ":monitor" => {
+                self.next("an address")?;
+                self.next("a format")?;
+            }
+            "clear" => self.emit(Instruction::Clear)?,
+            "return" | ";" => self.emit(Instruction::Return)?,
+            "exit" => self.emit(Instruction::Exit)?,
+            "lores" => self.emit(Instruction::LowResolution)?,
+            "hires" => self.emit(Instruction::HighResolution)?,
+            "scroll-left" => self.emit(Instruction::ScrollLeft)?,
+            "scroll-right" => self.emit(Instruction::ScrollRight)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollDown { n })?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollUp { n })?;
+            }
+            "audio" => self.emit(Instruction::LoadAudioPattern)?,
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::SelectPlanes { n })?;
+            }
+            "bcd" => {
+                let x = self.register_operand()?;
+                self.emit(Instruction::StoreBcdOfXOnI { x })?;
+            }
+            "save" | "load" => {
+                let x = self.register_operand()?;
+                let instruction = if self.tokens.front().is_some_and(|token| token.text == "-") {
+                    self.next("-")?;
+                    let y = self.register_operand()?;
+                    if text == "save" {
+                        Instruction::StoreXToYOnI { x, y }
+                    } else {
+                        Instruction::LoadXToYFromI { x, y }
+                    }
+                } else if text == "save" {
+                    Instruction::StoreV0ToXOnI { x }
+                } else {
+                    Instruction::LoadV0ToXFromI { x }
+                };
+                self.emit(instruction)?;
+            }
+            "saveflags" => {
+                let x = self.register_operand()?;
+                self.emit(Instruction::StoreV0ToXOnFlags { x })?;
+            }
+            "loadflags" => {
+                let x = self.register_operand()?;
+                self.emit(Instruction::LoadV0ToXFromFlags { x })?;
+            }
+            "sprite" => {
+                let x = self.register_operand()?;
+                let y = self.register_operand()?;
+                let n = self.nibble()?;
+                self.emit(Instruction::Draw { x, y, n })?;
+            }
+            "jump" | "jump0" | "native" => {
+                let target = self.next("an address")?;
+                let instruction = match text {
+                    "jump" => Instruction::Jump { nnn: 0 },
+                    "jump0" => Instruction::JumpToNnnPlusV0 { nnn: 0 },
+                    _ => Instruction::MachineCodeRoutine { nnn: 0 },
+                };
+                self.emit_with_address(instruction, &target.text)?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register_operand()?;
+                self.emit(match text {
+                    "delay" => Instruction::SetXToDelayTimer { x },
+                    "buzzer" => Instruction::SetXToSoundTimer { x },
+                    _ => Instruction::SetPitchToX { x },
+                })?;
+            }
+            "i" => self.i_statement()?,
+            "if" => {
+                let condition = self.condition()?;
+                let keyword = self.next("then or begin")?;
+                match keyword.text.as_str() {
+                    "then" => self.emit_skip_unless(condition)?,
+                    "begin" => {
+                        self.emit_skip_unless(Condition {
+                            comparison: condition.comparison.negated(),
+                            ..condition
+                        })?;
+                        let jump = self.address;
+                        self.emit(Instruction::Jump { nnn: 0 })?;
+                        self.blocks.push(Block::If { jump });
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Expected then or begin instead of {}",
+                            keyword.text
+                        ));
+                    }
+                }
+            }
+            "else" => {
+                let Some(Block::If { jump }) = self.blocks.pop() else {
+                    return Err("else without begin".to_string());
+                };
+                let jump_over = self.address;
+                self.emit(Instruction::Jump { nnn: 0 })?;
+                self.patch(jump, FixupKind::Address, self.address as f64)?;
+                self.blocks.push(Block::Else { jump: jump_over });
+            }
+            "end" => {
+                let (Some(Block::If { jump }) | Some(Block::Else { jump })) = self.blocks.pop()
+                else {
+                    return Err("end without begin".to_string());
+                };
+                self.patch(jump, FixupKind::Address, self.address as f64)?;
+            }
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.address,
+                breaks: Vec::new(),
+            }),
+            "while" => {
+                // Leaves the loop when the condition is false
+                let condition = self.condition()?;
+                self.emit_skip_unless(Condition {
+                    comparison: condition.comparison.negated(),
+                    ..condition
+                })?;
+                let jump = self.address;
+                self.emit(Instruction::Jump { nnn: 0 })?;
+                let Some(Block::Loop { breaks, .. }) = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|block| matches!(block, Block::Loop { .. }))
+                else {
+                    return Err("while outside of a loop".to_string());
+                };
+                breaks.push(jump);
+            }
+            "again" => {
+                let Some(Block::Loop { start, breaks }) = self.blocks.pop() else {
+                    return Err("again without loop".to_string());
+                };
+                let jump_back = self.address;
+                self.emit(Instruction::Jump { nnn: 0 })?;
+                self.patch(jump_back, FixupKind::Address, start as f64)?;
+                for jump in breaks {
+                    self.patch(jump, FixupKind::Address, self.address as f64)?;
+                }
+            }
+            _ if is_identifier(text) => {
+                self.emit_with_address(Instruction::Call { nnn: 0 }, text)?;
+            }
+            _ => return Err(format!("Unexpected {}", text)),
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: usize) -> Result<(), String> {
+        let operator = self.next("an operator")?.text;
+        let operand = self.next("an operand")?.text;
+        let y = self.register(&operand);
+        let instruction = match (operator.as_str(), y) {
+            (":=", Some(y)) => Instruction::StoreYToX { x, y },
+            (":=", None) => match operand.as_str() {
+                "delay" => Instruction::SetDelayTimerToX { x },
+                "key" => Instruction::WaitKeyToX { x },
+                "random" => Instruction::RandomAndNnToX {
+                    x,
+                    nn: self.byte()?,
+                },
+                _ => Instruction::SetNnToX {
+                    x,
+                    nn: to_byte(self.value(&operand)?)?,
+                },
+            },
+            ("+=", Some(y)) => Instruction::SumYToX { x, y },
+            ("+=", None) => Instruction::SumNnToX {
+                x,
+                nn: to_byte(self.value(&operand)?)?,
+            },
+            ("-=", Some(y)) => Instruction::SubtractYFromX { x, y },
+            // There is no 7XNN for the subtraction, the opposite is added
+            ("-=", None) => Instruction::SumNnToX {
+                x,
+                nn: to_byte(self.value(&operand)?)?.wrapping_neg(),
+            },
+            ("=-", Some(y)) => Instruction::SubtractXFromY { x, y },
+            ("|=", Some(y)) => Instruction::OrYToX { x, y },
+            ("&=", Some(y)) => Instruction::AndYToX { x, y },
+            ("^=", Some(y)) => Instruction::XorYToX { x, y },
+            (">>=", Some(y)) => Instruction::ShiftRightX { x, y },
+            ("<<=", Some(y)) => Instruction::ShiftLeftX { x, y },
+            _ => return Err(format!("Invalid operands for {}", operator)),
+        };
+        self.emit(instruction)
+    }
+
+    fn i_statement(&mut self) -> Result<(), String> {
+        let operator = self.next("an operator")?.text;
+        let operand = self.next("an operand")?.text;
+        match (operator.as_str(), operand.as_str()) {
+            (":=", "hex") => {
+                let x = self.register_operand()?;
+                self.emit(Instruction::SetIToFontOfX { x })
+            }
+            (":=", "bighex") => {
+                let x = self.register_operand()?;
+                self.emit(Instruction::SetIToBigFontOfX { x })
+            }
+            (":=", "long") => {
+                let target = self.next("an address")?;
+                let address = self.address;
+                self.emit(Instruction::SetNextWordToI)?;
+                self.emit_byte(0)?;
+                self.emit_byte(0)?;
+                self.reference(address + 2, FixupKind::Long, &target.text)
+            }
+            (":=", _) => self.emit_with_address(Instruction::SetNnnToI { nnn: 0 }, &operand),
+            ("+=", _) => {
+                let x = self
+                    .register(&operand)
+                    .ok_or_else(|| format!("{} is not a register", operand))?;
+                self.emit(Instruction::SumXToI { x })
+            }
+            _ => Err(format!("Invalid operands for {}", operator)),
+        }
+    }
+
+    // `vx == vy`, `vx != 5`, `vx < vy`, `vx key`, `vx -key`...
+    fn condition(&mut self) -> Result<Condition, String> {
+        let x = self.register_operand()?;
+        let comparison = self.next("a comparison")?.text;
+        let comparison = match comparison.as_str() {
+            "key" => Comparison::Key,
+            "-key" => Comparison::NotKey,
+            "==" => Comparison::Equal,
+            "!=" => Comparison::Different,
+            "<" => Comparison::Less,
+            ">" => Comparison::Greater,
+            "<=" => Comparison::LessOrEqual,
+            ">=" => Comparison::GreaterOrEqual,
+            _ => return Err(format!("Unknown comparison: {}", comparison)),
+        };
+        if matches!(comparison, Comparison::Key | Comparison::NotKey) {
+            return Ok(Condition {
+                x,
+                comparison,
+                operand: None,
+            });
+        }
+
+        let operand = self.next("an operand")?.text;
+        let operand = match self.register(&operand) {
+            Some(y) => Operand::Register(y),
+            None => Operand::Byte(to_byte(self.value(&operand)?)?),
+        };
+        Ok(Condition {
+            x,
+            comparison,
+            operand: Some(operand),
+        })
+    }
+
+    // Skips the next instruction when the condition is false. The order comparisons subtract
+    // in VF and look at the borrow
+    fn emit_skip_unless(&mut self, condition: Condition) -> Result<(), String> {
+        let x = condition.x;
+        let Some(operand) = condition.operand else {
+            return self.emit(match condition.comparison {
+                Comparison::Key => Instruction::SkipIfKeyOnXNotPressed { x },
+                _ => Instruction::SkipIfKeyOnXPressed { x },
+            });
+        };
+        let register = Operand::Register(x);
+        let (left, right, flag_when_true) = match (condition.comparison, operand) {
+            (Comparison::Equal, Operand::Register(y)) => {
+                return self.emit(Instruction::SkipIfXDiffersY { x, y });
+            }
+            (Comparison::Equal, Operand::Byte(nn)) => {
+                return self.emit(Instruction::SkipIfXDiffersNn { x, nn });
+            }
+            (Comparison::Different, Operand::Register(y)) => {
+                return self.emit(Instruction::SkipIfXEqualsY { x, y });
+            }
+            (Comparison::Different, Operand::Byte(nn)) => {
+                return self.emit(Instruction::SkipIfXEqualsNn { x, nn });
+            }
+            (Comparison::Less, _) => (register, operand, 0),
+            (Comparison::GreaterOrEqual, _) => (register, operand, 1),
+            (Comparison::Greater, _) => (operand, register, 0),
+            _ => (operand, register, 1),
+        };
+        if x == 0xF || matches!(operand, Operand::Register(0xF)) {
+            return Err("VF can't be compared with <, >, <= or >=".to_string());
+        }
+
+        // VF is 1 when left >= right and 0 when it borrows
+        match (left, right) {
+            (Operand::Register(left), Operand::Register(right)) => {
+                self.emit(Instruction::StoreYToX { x: 0xF, y: left })?;
+                self.emit(Instruction::SubtractYFromX { x: 0xF, y: right })?;
+            }
+            (Operand::Byte(left), Operand::Register(right)) => {
+                self.emit(Instruction::SetNnToX { x: 0xF, nn: left })?;
+                self.emit(Instruction::SubtractYFromX { x: 0xF, y: right })?;
+            }
+            (Operand::Register(left), Operand::Byte(right)) => {
+                self.emit(Instruction::SetNnToX { x: 0xF, nn: right })?;
+                self.emit(Instruction::SubtractXFromY { x: 0xF, y: left })?;
+            }
+            (Operand::Byte(_), Operand::Byte(_)) => unreachable!("X is always a register"),
+        }
+        self.emit(Instruction::SkipIfXDiffersNn {
+            x: 0xF,
+            nn: flag_when_true,
+        })
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.new_name()?;
+        let mut parameters = Vec::new();
+        loop {
+            let token = self.next("{")?;
+            if token.text == "{" {
+                break;
+            }
+            parameters.push(token.text);
+        }
+        let body = self.block()?;
+        self.macros.insert(name, Macro { parameters, body });
+        Ok(())
+    }
+
+    // The tokens until the `}` closing a `{` already read
+    fn block(&mut self) -> Result<Vec<Token>, String> {
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next("}")?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(body),
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+    }
+
+    fn expand_macro(&mut self, invocation: &Token) -> Result<(), String> {
+        if invocation.depth == MAX_MACRO_DEPTH {
+            return Err(format!("{} expands itself", invocation.text));
+        }
+        let parameters = self.macros[&invocation.text].parameters.clone();
+        let mut arguments = HashMap::new();
+        for parameter in parameters {
+            let argument = self.next(&format!("{} for {}", parameter, invocation.text))?;
+            arguments.insert(parameter, argument.text);
+        }
+
+        // The errors in the body point to the line using the macro
+        let body: Vec<Token> = self.macros[&invocation.text]
+            .body
+            .iter()
+            .map(|token| Token {
+                text: arguments
+                    .get(&token.text)
+                    .unwrap_or(&token.text)
+                    .to_string(),
+                line: invocation.line,
+                depth: invocation.depth + 1,
+            })
+            .collect();
+        for token in body.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    // `{ expression }`, evaluated right to left: `2 * 3 + 1` is 8
+    fn calc(&mut self) -> Result<f64, String> {
+        self.expect("{")?;
+        let tokens = self.block()?;
+        let mut position = 0;
+        let value = self.calc_expression(&tokens, &mut position)?;
+        match tokens.get(position) {
+            Some(token) => Err(format!("Unexpected {} in the expression", token.text)),
+            None => Ok(value),
+        }
+    }
+
+    fn calc_expression(&self, tokens: &[Token], position: &mut usize) -> Result<f64, String> {
+        let left = self.calc_term(tokens, position)?;
+        let Some(operator) = tokens.get(*position).filter(|token| token.text != ")") else {
+            return Ok(left);
+        };
+        *position += 1;
+        let right = self.calc_expression(tokens, position)?;
+        let (left_integer, right_integer) = (to_integer(left), to_integer(right));
+        let boolean = |value: bool| if value { 1.0 } else { 0.0 };
+        Ok(match operator.text.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" | "%" if right == 0.0 => return Err("Division by zero".to_string()),
+            "/" => left / right,
+            "%" => left % right,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "&" => (left_integer & right_integer) as f64,
+            "|" => (left_integer | right_integer) as f64,
+            "^" => (left_integer ^ right_integer) as f64,
+            "<<" => left_integer.checked_shl(right_integer as u32).unwrap_or(0) as f64,
+            ">>" => left_integer.checked_shr(right_integer as u32).unwrap_or(0) as f64,
+            "<" => boolean(left < right),
+            ">" => boolean(left > right),
+            "<=" => boolean(left <= right),
+            ">=" => boolean(left >= right),
+            "==" => boolean(left == right),
+            "!=" => boolean(left != right),
+            _ => return Err(format!("Unknown operator: {}", operator.text)),
+        })
+    }
+
+    fn calc_term(&self, tokens: &[Token], position: &mut usize) -> Result<f64, String> {
+        let token = tokens
+            .get(*position)
+            .ok_or("Missing a value in the expression")?;
+        *position += 1;
+        let unary = |function: fn(f64) -> f64, position: &mut usize| -> Result<f64, String> {
+            Ok(function(self.calc_term(tokens, position)?))
+        };
+        match token.text.as_str() {
+            "(" => {
+                let value = self.calc_expression(tokens, position)?;
+                if tokens.get(*position).is_none_or(|token| token.text != ")") {
+                    return Err("Missing )".to_string());
+                }
+                *position += 1;
+                Ok(value)
+            }
+            "-" => unary(|value| -value, position),
+            "~" => unary(|value| !to_integer(value) as f64, position),
+            "!" => unary(|value| if value == 0.0 { 1.0 } else { 0.0 }, position),
+            "sin" => unary(f64::sin, position),
+            "cos" => unary(f64::cos, position),
+            "tan" => unary(f64::tan, position),
+            "exp" => unary(f64::exp, position),
+            "log" => unary(f64::ln, position),
+            "abs" => unary(f64::abs, position),
+            "sqrt" => unary(f64::sqrt, position),
+            "sign" => unary(f64::signum, position),
+            "ceil" => unary(f64::ceil, position),
+            "floor" => unary(f64::floor, position),
+            // The byte already compiled at that address
+            "@" => {
+                let address = to_integer(self.calc_term(tokens, position)?);
+                let byte = address
+                    .checked_sub(PROGRAM_START as i64)
+                    .and_then(|offset| usize::try_from(offset).ok())
+                    .and_then(|offset| self.rom.get(offset))
+                    .ok_or_else(|| format!("Nothing is compiled at 0x{:X}", address))?;
+                Ok(*byte as f64)
+            }
+            "HERE" => Ok(self.address as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            text => self.value(text),
+        }
+    }
+
+    // A number, a constant or a label already defined
+    fn value(&self, text: &str) -> Result<f64, String> {
+        if let Some(value) = parse_number(text) {
+            return Ok(value);
+        }
+        if let Some(value) = self.constants.get(text) {
+            return Ok(*value);
+        }
+        if let Some(address) = self.labels.get(text) {
+            return Ok(*address as f64);
+        }
+        Err(format!("Unknown value: {}", text))
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        let token = self.next("a byte")?;
+        to_byte(self.value(&token.text)?)
+    }
+
+    fn nibble(&mut self) -> Result<u8, String> {
+        let token = self.next("a nibble")?;
+        to_nibble(self.value(&token.text)?)
+    }
+
+    fn register(&self, text: &str) -> Option<usize> {
+        if let Some(register) = self.aliases.get(text) {
+            return Some(*register);
+        }
+        let digit = text.strip_prefix(['v', 'V'])?;
+        if digit.len() != 1 {
+            return None;
+        }
+        usize::from_str_radix(digit, 16).ok()
+    }
+
+    fn register_operand(&mut self) -> Result<usize, String> {
+        let token = self.next("a register")?;
+        self.register(&token.text)
+            .ok_or_else(|| format!("{} is not a register", token.text))
+    }
+
+    fn new_name(&mut self) -> Result<String, String> {
+        let name = self.next("a name")?.text;
+        self.check_name(&name)?;
+        Ok(name)
+    }
+
+    fn check_name(&self, name: &str) -> Result<(), String> {
+        if !is_identifier(name) || KEYWORDS.contains(&name) || self.register(name).is_some() {
+            return Err(format!("Invalid name: {}", name));
+        }
+        if self.labels.contains_key(name)
+            || self.constants.contains_key(name)
+            || self.aliases.contains_key(name)
+            || self.macros.contains_key(name)
+        {
+            return Err(format!("{} is already defined", name));
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<(), String> {
+        let [high, low] = instruction.encode().to_be_bytes();
+        self.emit_byte(high)?;
+        self.emit_byte(low)
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), String> {
+        if self.address >= XO_CHIP_MEMORY_SIZE {
+            return Err("The program doesn't fit in the memory".to_string());
+        }
+        let offset = self.address - PROGRAM_START;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0x00);
+        }
+        self.rom[offset] = byte;
+        self.address += 1;
+        Ok(())
+    }
+
+    // The instruction is emitted with a zero NNN, filled now or at the end
+    fn emit_with_address(&mut self, instruction: Instruction, target: &str) -> Result<(), String> {
+        let address = self.address;
+        self.emit(instruction)?;
+        self.reference(address, FixupKind::Address, target)
+    }
+
+    fn reference(&mut self, address: usize, kind: FixupKind, target: &str) -> Result<(), String> {
+        match self.value(target) {
+            Ok(value) => self.patch(address, kind, value),
+            Err(_) if is_identifier(target) => {
+                self.fixups.push(Fixup {
+                    address,
+                    kind,
+                    name: target.to_string(),
+                    line: self.line,
+                });
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn patch(&mut self, address: usize, kind: FixupKind, value: f64) -> Result<(), String> {
+        let value = to_integer(value);
+        let max = match kind {
+            FixupKind::Long => 0xFFFF,
+            _ => 0xFFF,
+        };
+        if !(0..=max).contains(&value) {
+            return Err(format!("An address can't be {}", value));
+        }
+        let [high, low] = (value as u16).to_be_bytes();
+        let offset = address - PROGRAM_START;
+        match kind {
+            FixupKind::Address | FixupKind::UnpackHigh => {
+                // The NNN of an instruction, or the low nibble of the NN of `v0 := `
+                let nibble_offset = match kind {
+                    FixupKind::Address => offset,
+                    _ => offset + 1,
+                };
+                self.rom[nibble_offset] |= high;
+                if let FixupKind::Address = kind {
+                    self.rom[offset + 1] = low;
+                }
+            }
+            FixupKind::Long => {
+                self.rom[offset] = high;
+                self.rom[offset + 1] = low;
+            }
+            FixupKind::UnpackLow => self.rom[offset + 1] = low,
+        }
+        Ok(())
+    }
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+// The calculations are done on floats, the instructions take the integer part
+fn to_integer(value: f64) -> i64 {
+    value.floor() as i64
+}
+
+// Negative bytes are stored as two's complement
+fn to_byte(value: f64) -> Result<u8, String> {
+    let value = to_integer(value);
+    if !(-0x80..=0xFF).contains(&value) {
+        return Err(format!("A byte can't be {}", value));
+    }
+    Ok(value as u8)
+}
+
+fn to_nibble(value: f64) -> Result<u8, String> {
+    let value = to_integer(value);
+    if !(0..=0xF).contains(&value) {
+        return Err(format!("A nibble can't be {}", value));
+    }
+    Ok(value as u8)
+}
+
+// Octo names can have dashes: `draw-player`
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
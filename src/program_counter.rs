@@ -1,5 +0,0 @@
-struct PogramCounter {
-    current_instruction: u8;
-}
-
-
@@ -0,0 +1,175 @@
+use crate::memory::{MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+// The instruction set the ROM was written for, each one extends the previous so they can be
+// compared to know if an instruction is available
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Chip8,
+    // 128x64 hires mode, scrolling, 16x16 sprites, the big font and the RPL flags
+    SuperChip,
+    // 64 KiB of memory, two planes of colour, audio patterns and a few new instructions
+    XoChip,
+}
+
+impl Platform {
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::XoChip => XO_CHIP_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+
+    // FX75 and FX85 reach V0 to V7 on the SUPER-CHIP 1.x, the XO-CHIP extended them to VF
+    pub fn rpl_flags(&self) -> usize {
+        match self {
+            Platform::XoChip => 16,
+            _ => 8,
+        }
+    }
+}
+
+// How much FX55 and FX65 move the I register after the registers are stored or loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    // I keeps pointing to the start of the registers
+    Unchanged,
+    // I ends on the last register, what the CHIP-48 does
+    ByX,
+    // I ends right after the last register, what the COSMAC VIP does
+    ByXPlusOne,
+}
+
+// The instructions that behave differently depending on the interpreter a ROM was written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    pub platform: Platform,
+    // 8XY6 and 8XYE shift VY and store the result in VX instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    pub index_increment: IndexIncrement,
+    // BNNN jumps to XNN + VX instead of NNN + V0
+    pub jump_uses_vx: bool,
+    // 8XY1, 8XY2 and 8XY3 set VF to 0
+    pub logic_resets_vf: bool,
+    // Sprites going past the edges of the screen are cut instead of wrapping to the other side
+    pub clip_sprites: bool,
+    // DXYN waits for the next frame before the program continues, so there is only one draw
+    // per frame
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    // What most modern interpreters and the emulator did before the quirks were configurable
+    pub fn modern() -> Quirks {
+        Quirks {
+            platform: Platform::Chip8,
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::Unchanged,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            display_wait: false,
+        }
+    }
+
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            platform: Platform::Chip8,
+            shift_uses_vy: true,
+            index_increment: IndexIncrement::ByXPlusOne,
+            jump_uses_vx: false,
+            logic_resets_vf: true,
+            clip_sprites: true,
+            display_wait: true,
+        }
+    }
+
+    pub fn chip48() -> Quirks {
+        Quirks {
+            platform: Platform::Chip8,
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::ByX,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    pub fn super_chip() -> Quirks {
+        Quirks {
+            platform: Platform::SuperChip,
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::Unchanged,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    // The behaviour of Octo, where the XO-CHIP was defined
+    pub fn xo_chip() -> Quirks {
+        Quirks {
+            platform: Platform::XoChip,
+            shift_uses_vy: true,
+            index_increment: IndexIncrement::ByXPlusOne,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            display_wait: false,
+        }
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.platform as u8);
+        writer.write_bool(self.shift_uses_vy);
+        writer.write_u8(self.index_increment as u8);
+        writer.write_bool(self.jump_uses_vx);
+        writer.write_bool(self.logic_resets_vf);
+        writer.write_bool(self.clip_sprites);
+        writer.write_bool(self.display_wait);
+    }
+
+    pub(crate) fn load_state(reader: &mut StateReader) -> Result<Quirks, StateError> {
+        let platform = match reader.read_u8()? {
+            0 => Platform::Chip8,
+            1 => Platform::SuperChip,
+            2 => Platform::XoChip,
+            _ => return Err(StateError::InvalidFormat),
+        };
+        let shift_uses_vy = reader.read_bool()?;
+        let index_increment = match reader.read_u8()? {
+            0 => IndexIncrement::Unchanged,
+            1 => IndexIncrement::ByX,
+            2 => IndexIncrement::ByXPlusOne,
+            _ => return Err(StateError::InvalidFormat),
+        };
+        Ok(Quirks {
+            platform,
+            shift_uses_vy,
+            index_increment,
+            jump_uses_vx: reader.read_bool()?,
+            logic_resets_vf: reader.read_bool()?,
+            clip_sprites: reader.read_bool()?,
+            display_wait: reader.read_bool()?,
+        })
+    }
+
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name.to_ascii_lowercase().as_str() {
+            "modern" => Some(Quirks::modern()),
+            "vip" | "cosmac-vip" => Some(Quirks::cosmac_vip()),
+            "chip48" | "chip-48" => Some(Quirks::chip48()),
+            "schip" | "super-chip" => Some(Quirks::super_chip()),
+            "xochip" | "xo-chip" => Some(Quirks::xo_chip()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::modern()
+    }
+}
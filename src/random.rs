@@ -0,0 +1,88 @@
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+// Where CXNN gets its random bytes from. It is part of the machine, so the state is written in the
+// save states and a run with the same seed is always the same
+pub trait RandomSource {
+    fn next_byte(&mut self) -> u8;
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError>;
+}
+
+// SplitMix64, small enough to keep its whole state in a save state and the same on every
+// platform and version of the emulator
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> SeededRandom {
+        SeededRandom { state: seed }
+    }
+
+    // A different sequence on every run, for when nobody asked for a seed
+    pub fn from_entropy() -> SeededRandom {
+        SeededRandom::new(rand::random())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut value = self.state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        value ^ (value >> 31)
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_byte(&mut self) -> u8 {
+        // The highest bits are the best mixed ones
+        (self.next_u64() >> 56) as u8
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.state);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.state = reader.read_u64()?;
+        Ok(())
+    }
+}
+
+// Returns the given bytes in order and starts again after the last one, for tests that need to
+// know what CXNN will get
+pub struct ScriptedRandom {
+    values: Vec<u8>,
+    position: usize,
+}
+
+impl ScriptedRandom {
+    pub fn new(values: Vec<u8>) -> ScriptedRandom {
+        ScriptedRandom {
+            values,
+            position: 0,
+        }
+    }
+}
+
+impl RandomSource for ScriptedRandom {
+    // An empty script always returns 0
+    fn next_byte(&mut self) -> u8 {
+        if self.values.is_empty() {
+            return 0;
+        }
+        let value = self.values[self.position % self.values.len()];
+        self.position = (self.position + 1) % self.values.len();
+        value
+    }
+
+    // Only the position is written, the script itself is set up again by the test
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u32(self.position as u32);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.position = reader.read_u32()? as usize;
+        Ok(())
+    }
+}
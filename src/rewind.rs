@@ -0,0 +1,102 @@
+use crate::cpu::Cpu;
+use std::collections::VecDeque;
+
+// 10 snapshots per second at 60 frames per second
+pub const DEFAULT_REWIND_INTERVAL: u32 = 6;
+// A CHIP-8 snapshot is around 12 KiB, enough for a couple of minutes. The XO-CHIP ones are
+// bigger because of the memory, so there is less time to go back
+pub const DEFAULT_REWIND_BUDGET: usize = 16 * 1024 * 1024;
+
+// Snapshots of the machine taken every few frames, the oldest ones are dropped when they don't
+// fit in the memory budget anymore
+pub struct Rewind {
+    snapshots: VecDeque<Vec<u8>>,
+    // Frames between two snapshots
+    interval: u32,
+    // Maximum amount of bytes used by all the snapshots together
+    budget: usize,
+    used: usize,
+    frames_since_snapshot: u32,
+    // The latest snapshot was taken on this frame, rewinding to it would look like nothing
+    // happened
+    latest_is_current: bool,
+}
+
+pub trait RewindTrait {
+    // Called once per frame, takes a snapshot when the interval has passed
+    fn record(&mut self, cpu: &Cpu);
+    // Restores the latest snapshot and forgets it, so calling it again goes further back.
+    // Returns false when there is nothing left
+    fn rewind(&mut self, cpu: &mut Cpu) -> bool;
+}
+
+impl RewindTrait for Rewind {
+    fn record(&mut self, cpu: &Cpu) {
+        self.frames_since_snapshot += 1;
+        self.latest_is_current = false;
+        if self.frames_since_snapshot < self.interval {
+            return;
+        }
+        self.frames_since_snapshot = 0;
+
+        let snapshot = cpu.save_state();
+        if snapshot.len() > self.budget {
+            return;
+        }
+        self.used += snapshot.len();
+        self.snapshots.push_back(snapshot);
+        self.latest_is_current = true;
+        while self.used > self.budget {
+            if let Some(oldest) = self.snapshots.pop_front() {
+                self.used -= oldest.len();
+            }
+        }
+    }
+
+    fn rewind(&mut self, cpu: &mut Cpu) -> bool {
+        if self.latest_is_current && self.snapshots.len() > 1 {
+            self.pop();
+        }
+        // The snapshot loaded is forgotten, the next one is older than the machine now
+        self.latest_is_current = false;
+        self.frames_since_snapshot = 0;
+
+        match self.pop() {
+            // The snapshots come from the same machine, they can't be for another ROM
+            Some(snapshot) => cpu.load_state(&snapshot).is_ok(),
+            None => false,
+        }
+    }
+}
+
+impl Rewind {
+    pub fn new(interval: u32, budget: usize) -> Rewind {
+        Rewind {
+            snapshots: VecDeque::new(),
+            interval: interval.max(1),
+            budget,
+            used: 0,
+            frames_since_snapshot: 0,
+            latest_is_current: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    // Bytes used by the snapshots
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    fn pop(&mut self) -> Option<Vec<u8>> {
+        let snapshot = self.snapshots.pop_back()?;
+        self.used -= snapshot.len();
+        Some(snapshot)
+    }
+}
@@ -0,0 +1,61 @@
+use crate::checksum::crc32;
+use crate::cpu::PROGRAM_START;
+use crate::memory::{MemoryTrait, SharedMemory};
+use crate::octo;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Octo sources (.8o) are compiled first, the ROM returned is the compiled one
+pub fn load_rom(file_path: &str, memory: SharedMemory) -> Result<Vec<u8>, String> {
+    let mut rom_data = fs::read(file_path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => format!("ROM file not found: {}", file_path),
+        io::ErrorKind::PermissionDenied => format!("Permission denied reading: {}", file_path),
+        _ => format!("Failed to read ROM file: {}", e),
+    })?;
+    if is_octo_source(Path::new(file_path)) {
+        let source = String::from_utf8(rom_data)
+            .map_err(|_| format!("The Octo source is not UTF-8: {}", file_path))?;
+        rom_data = octo::compile(&source)
+            .map_err(|mut e| {
+                e.file = file_path.to_string();
+                e.to_string()
+            })?
+            .rom;
+    }
+
+    load_rom_bytes(&rom_data, memory)?;
+    Ok(rom_data)
+}
+
+pub fn is_octo_source(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "8o")
+}
+
+// Identifies the ROM in save states and recordings
+pub fn rom_hash(rom_data: &[u8]) -> u32 {
+    crc32(rom_data)
+}
+
+// Writes a ROM that is already in memory, like one built by a tool, at the start of the program
+// area
+pub fn load_rom_bytes(rom_data: &[u8], memory: SharedMemory) -> Result<(), String> {
+    let max_rom_size = memory.borrow().size() - PROGRAM_START;
+    if rom_data.len() > max_rom_size {
+        return Err(format!(
+            "ROM too large: {} bytes (max: {} bytes)",
+            rom_data.len(),
+            max_rom_size
+        ));
+    }
+
+    if rom_data.is_empty() {
+        return Err("ROM file is empty".to_string());
+    }
+
+    for (i, &byte) in rom_data.iter().enumerate() {
+        memory.borrow_mut().write(PROGRAM_START + i, byte);
+    }
+
+    Ok(())
+}
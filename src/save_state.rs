@@ -0,0 +1,147 @@
+use std::fmt;
+
+// Every save state starts with these bytes, followed by the version and the hash of the ROM
+pub const SAVE_STATE_MAGIC: &[u8; 4] = b"C8SS";
+// Incremented every time the layout changes, older states are rejected instead of misread
+pub const SAVE_STATE_VERSION: u16 = 3;
+
+// Also used by the movies, which share the format of the values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    // Doesn't start with the magic bytes or ends before everything was read
+    InvalidFormat,
+    UnsupportedVersion(u16),
+    // The state was saved while running another ROM
+    WrongRom { expected: u32, found: u32 },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::InvalidFormat => write!(f, "The file is invalid or truncated"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported file version {}", version)
+            }
+            StateError::WrongRom { expected, found } => write!(
+                f,
+                "The file is for another ROM (hash {:08X}, running {:08X})",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+// Appends the values in little endian, each part of the machine writes its own fields
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> StateWriter {
+        StateWriter::new()
+    }
+}
+
+// Reads the values back in the same order they were written
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, position: 0 }
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], StateError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(StateError::InvalidFormat)?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidFormat),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    // Fills the whole array, for the fixed size parts like the registers
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    // Anything left after the last field means the state was not written by this version
+    pub fn finish(&self) -> Result<(), StateError> {
+        if self.position == self.data.len() {
+            Ok(())
+        } else {
+            Err(StateError::InvalidFormat)
+        }
+    }
+}
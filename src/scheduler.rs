@@ -0,0 +1,175 @@
+use crate::cpu::{Cpu, CpuError, StepOutcome};
+use crate::timers::{TIMERS_FREQUENCY, TimersTrait};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A frame is the time between two ticks of the timers, the frontend presents the display once
+// per frame
+pub const FRAMES_PER_SECOND: u32 = TIMERS_FREQUENCY;
+pub const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 700;
+// When the host falls behind by more than this the lost frames are dropped instead of being run
+// back to back to catch up
+const MAX_FRAMES_BEHIND: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    // Frames are paced by the wall clock, 1.0 is real time, above it is turbo and below it is
+    // slow motion
+    Multiplier(f64),
+    // Frames run back to back as fast as the host allows
+    Unthrottled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameOutcome {
+    Completed,
+    Halted,
+    // The maximum number of instructions set on the scheduler was executed
+    LimitReached,
+    // Stopped before an instruction by `run_frame_until`, the frame isn't over
+    Paused,
+}
+
+pub struct Scheduler {
+    instructions_per_second: u32,
+    // Of the current frame, the rate rarely divides into 60 frames so it changes between frames
+    instructions_per_frame: u32,
+    // The part of a second's instructions that didn't make a whole one in the previous frames,
+    // in 1/60 of an instruction
+    leftover: u32,
+    speed: Speed,
+    next_frame: Option<Instant>,
+    frames: u64,
+    instructions: u64,
+    max_instructions: Option<u64>,
+    // Instructions already run in a frame that was paused
+    frame_instructions: u32,
+}
+
+pub trait SchedulerTrait {
+    // Runs the instructions of one frame, then ticks the timers
+    fn run_frame(&mut self, cpu: &mut Cpu) -> Result<FrameOutcome, CpuError>;
+    // Same, but `should_pause` is asked before every instruction, like a debugger does. The
+    // next call finishes the frame that was paused
+    fn run_frame_until(
+        &mut self,
+        cpu: &mut Cpu,
+        should_pause: &mut dyn FnMut(&Cpu) -> bool,
+    ) -> Result<FrameOutcome, CpuError>;
+    // Sleeps until it is time to start the next frame according to the speed
+    fn wait_for_next_frame(&mut self);
+}
+
+impl SchedulerTrait for Scheduler {
+    fn run_frame(&mut self, cpu: &mut Cpu) -> Result<FrameOutcome, CpuError> {
+        self.run_frame_until(cpu, &mut |_| false)
+    }
+
+    fn run_frame_until(
+        &mut self,
+        cpu: &mut Cpu,
+        should_pause: &mut dyn FnMut(&Cpu) -> bool,
+    ) -> Result<FrameOutcome, CpuError> {
+        while self.frame_instructions < self.instructions_per_frame {
+            if should_pause(cpu) {
+                return Ok(FrameOutcome::Paused);
+            }
+            if self
+                .max_instructions
+                .is_some_and(|max_instructions| self.instructions >= max_instructions)
+            {
+                return Ok(FrameOutcome::LimitReached);
+            }
+            self.instructions += 1;
+            self.frame_instructions += 1;
+
+            match cpu.step()? {
+                StepOutcome::Halted | StepOutcome::Exited => return Ok(FrameOutcome::Halted),
+                // Nothing will change until the keyboard is read again on the next frame
+                StepOutcome::WaitingForKey | StepOutcome::WaitingForVerticalBlank => break,
+                StepOutcome::Executed(_) => {}
+            }
+        }
+
+        cpu.timers_mut().tick();
+        cpu.vertical_blank();
+        self.frames += 1;
+        self.frame_instructions = 0;
+        self.plan_frame();
+        Ok(FrameOutcome::Completed)
+    }
+
+    fn wait_for_next_frame(&mut self) {
+        let multiplier = match self.speed {
+            Speed::Unthrottled => return,
+            Speed::Multiplier(multiplier) => multiplier,
+        };
+        let frame_duration = Duration::from_secs_f64(1.0 / (FRAMES_PER_SECOND as f64 * multiplier));
+
+        let now = Instant::now();
+        let next_frame = self.next_frame.unwrap_or(now) + frame_duration;
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+            self.next_frame = Some(next_frame);
+        } else if now - next_frame > frame_duration * MAX_FRAMES_BEHIND {
+            self.next_frame = Some(now);
+        } else {
+            self.next_frame = Some(next_frame);
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn new(instructions_per_second: u32) -> Scheduler {
+        let mut scheduler = Scheduler {
+            instructions_per_second,
+            instructions_per_frame: 0,
+            leftover: 0,
+            speed: Speed::Multiplier(1.0),
+            next_frame: None,
+            frames: 0,
+            instructions: 0,
+            max_instructions: None,
+            frame_instructions: 0,
+        };
+        scheduler.plan_frame();
+        scheduler
+    }
+
+    // Spreads the instructions of a second over the frames, 700 per second is 11, 12 and 12
+    fn plan_frame(&mut self) {
+        let instructions = self.instructions_per_second + self.leftover;
+        self.instructions_per_frame = instructions / FRAMES_PER_SECOND;
+        self.leftover = instructions % FRAMES_PER_SECOND;
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+        // Starts counting again from now so the new speed doesn't try to catch up
+        self.next_frame = None;
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    // Of the current frame
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    // How many frames were completed since the start
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // How many times the CPU was stepped since the start
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    // Stops the execution once this number of instructions is reached, None runs forever
+    pub fn set_max_instructions(&mut self, max_instructions: Option<u64>) {
+        self.max_instructions = max_instructions;
+    }
+}
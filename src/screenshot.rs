@@ -0,0 +1,193 @@
+use crate::backend::Framebuffer;
+use crate::checksum::{adler32, crc32_update};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+// The biggest block deflate can store without compression
+const MAX_STORED_BLOCK_SIZE: usize = 0xFFFF;
+
+// The RGB colour of each of the 4 pixel colours, the first one is the background
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [[u8; 3]; 4],
+}
+
+impl Default for Palette {
+    // White on black like the terminal, the second plane of the XO-CHIP in greys
+    fn default() -> Palette {
+        Palette {
+            colors: [
+                [0x00, 0x00, 0x00],
+                [0xFF, 0xFF, 0xFF],
+                [0x55, 0x55, 0x55],
+                [0xAA, 0xAA, 0xAA],
+            ],
+        }
+    }
+}
+
+impl Palette {
+    // Up to 4 colours in hexadecimal separated by commas, like `000000,FFFFFF`. The colours that
+    // are not given keep the default ones
+    pub fn parse(value: &str) -> Result<Palette, String> {
+        let mut palette = Palette::default();
+        let colors: Vec<&str> = value.split(',').map(str::trim).collect();
+        if colors.len() > palette.colors.len() {
+            return Err(format!("A palette has up to 4 colours: {}", value));
+        }
+        for (slot, color) in palette.colors.iter_mut().zip(colors) {
+            let color = color.trim_start_matches('#');
+            let rgb = u32::from_str_radix(color, 16)
+                .ok()
+                .filter(|_| color.len() == 6)
+                .ok_or_else(|| format!("Invalid colour: {}", color))?;
+            *slot = [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8];
+        }
+        Ok(palette)
+    }
+
+    // For formats without colour, the dark colours become black
+    fn is_dark(&self, color: u8) -> bool {
+        let [red, green, blue] = self.colors[color as usize & 0b11];
+        (red as u32 * 299 + green as u32 * 587 + blue as u32 * 114) / 1000 < 128
+    }
+}
+
+// Every pixel becomes a square of `scale` by `scale`
+fn scaled_rows(frame: &Framebuffer, scale: usize) -> impl Iterator<Item = Vec<u8>> + '_ {
+    let scale = scale.max(1);
+    (0..frame.height).flat_map(move |y| {
+        let row: Vec<u8> = (0..frame.width)
+            .flat_map(|x| std::iter::repeat_n(frame.pixel(x, y), scale))
+            .collect();
+        std::iter::repeat_n(row, scale)
+    })
+}
+
+// An indexed PNG with the palette, the image data is stored without compression as the frames
+// are tiny
+pub fn encode_png(frame: &Framebuffer, scale: usize, palette: &Palette) -> Vec<u8> {
+    let width = frame.width * scale.max(1);
+    let height = frame.height * scale.max(1);
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per pixel, colour type 3 (indexed), default compression, filter and no interlace
+    header.extend_from_slice(&[8, 3, 0, 0, 0]);
+
+    let colors: Vec<u8> = palette.colors.iter().flatten().copied().collect();
+
+    // Each row starts with the filter type, 0 is none
+    let mut image = Vec::with_capacity((width + 1) * height);
+    for row in scaled_rows(frame, scale) {
+        image.push(0);
+        image.extend_from_slice(&row);
+    }
+
+    let mut png = Vec::new();
+    png.extend_from_slice(&PNG_SIGNATURE);
+    write_png_chunk(&mut png, b"IHDR", &header);
+    write_png_chunk(&mut png, b"PLTE", &colors);
+    write_png_chunk(&mut png, b"IDAT", &zlib_stored(&image));
+    write_png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+// A binary PBM (P4), the pixels with a dark colour on the palette are black
+pub fn encode_pbm(frame: &Framebuffer, scale: usize, palette: &Palette) -> Vec<u8> {
+    let width = frame.width * scale.max(1);
+    let height = frame.height * scale.max(1);
+
+    let mut pbm = format!("P4\n{} {}\n", width, height).into_bytes();
+    for row in scaled_rows(frame, scale) {
+        // 8 pixels per byte, the last byte of the row is padded with zeros
+        for pixels in row.chunks(8) {
+            let mut byte = 0;
+            for (bit, &color) in pixels.iter().enumerate() {
+                if palette.is_dark(color) {
+                    byte |= 0x80 >> bit;
+                }
+            }
+            pbm.push(byte);
+        }
+    }
+    pbm
+}
+
+// A plain PGM (P2) with one grey level for each colour, 0 to 3. It is text, so the golden
+// images of the tests can be reviewed in a diff
+pub fn encode_pgm(frame: &Framebuffer) -> Vec<u8> {
+    let mut pgm = format!("P2\n{} {}\n3\n", frame.width, frame.height);
+    for y in 0..frame.height {
+        let row: Vec<String> = (0..frame.width)
+            .map(|x| frame.pixel(x, y).to_string())
+            .collect();
+        pgm.push_str(&row.join(" "));
+        pgm.push('\n');
+    }
+    pgm.into_bytes()
+}
+
+// Reads the images written by `encode_pgm`, comments are not supported
+pub fn decode_pgm(data: &[u8]) -> Result<Framebuffer, String> {
+    let text = std::str::from_utf8(data).map_err(|_| "The PGM is not text".to_string())?;
+    let mut values = text.split_ascii_whitespace();
+    if values.next() != Some("P2") {
+        return Err("Only plain PGM (P2) images are supported".to_string());
+    }
+    let mut next_number = |name: &str| -> Result<usize, String> {
+        values
+            .next()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| format!("Missing or invalid {} in the PGM", name))
+    };
+    let width = next_number("width")?;
+    let height = next_number("height")?;
+    if next_number("maximum value")? != 3 {
+        return Err("The PGM maximum value must be 3".to_string());
+    }
+    let mut pixels = Vec::with_capacity(width * height);
+    for _ in 0..width * height {
+        let color = next_number("pixel")?;
+        if color > 3 {
+            return Err(format!("Invalid colour {} in the PGM", color));
+        }
+        pixels.push(color as u8);
+    }
+    Ok(Framebuffer {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    // The CRC covers the type and the data, not the length
+    let crc = crc32_update(crc32_update(0, kind), data);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// A zlib stream made of deflate blocks stored as they are
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32 KiB window and no preset dictionary, 0x01 makes the header a multiple
+    // of 31
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK_SIZE).peekable();
+    if blocks.peek().is_none() {
+        // An empty image still needs one final block
+        zlib.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_last = blocks.peek().is_none();
+        zlib.push(is_last as u8);
+        let length = block.len() as u16;
+        zlib.extend_from_slice(&length.to_le_bytes());
+        zlib.extend_from_slice(&(!length).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(data).to_be_bytes());
+    zlib
+}
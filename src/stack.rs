@@ -1,5 +1,9 @@
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+const STACK_SIZE: usize = 50;
+
 pub struct Stack {
-    addresses: [u16; 50],
+    addresses: [u16; STACK_SIZE],
     size: usize,
 }
 
@@ -7,12 +11,17 @@ pub trait StackTrait {
     fn push(&mut self, address: u16) -> bool;
     fn pop(&mut self) -> u16;
     fn peek(&mut self) -> u16;
+    fn size(&self) -> usize;
 }
 
 impl StackTrait for Stack {
     fn push(&mut self, address: u16) -> bool {
+        if self.size == STACK_SIZE {
+            return false;
+        }
+
         self.addresses[self.size] = address;
-        self.size = self.size + 1;
+        self.size += 1;
         true
     }
 
@@ -22,12 +31,26 @@ impl StackTrait for Stack {
         }
 
         let address = self.addresses[self.size - 1];
-        self.size = self.size - 1;
+        self.size -= 1;
         address
     }
 
     fn peek(&mut self) -> u16 {
-        self.addresses[self.size]
+        if self.size == 0 {
+            return 0x00;
+        }
+
+        self.addresses[self.size - 1]
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Default for Stack {
+    fn default() -> Stack {
+        Stack::new()
     }
 }
 
@@ -35,7 +58,32 @@ impl Stack {
     pub fn new() -> Stack {
         Stack {
             size: 0,
-            addresses: [0; 50],
+            addresses: [0; STACK_SIZE],
+        }
+    }
+
+    // The return addresses from the bottom of the stack to the top
+    pub fn addresses(&self) -> &[u16] {
+        &self.addresses[..self.size]
+    }
+
+    // Only the used part of the stack is written
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.size as u8);
+        for &address in &self.addresses[..self.size] {
+            writer.write_u16(address);
+        }
+    }
+
+    pub(crate) fn load_state(reader: &mut StateReader) -> Result<Stack, StateError> {
+        let mut stack = Stack::new();
+        let size = reader.read_u8()? as usize;
+        if size > STACK_SIZE {
+            return Err(StateError::InvalidFormat);
+        }
+        for _ in 0..size {
+            stack.push(reader.read_u16()?);
         }
+        Ok(stack)
     }
 }
@@ -0,0 +1,213 @@
+use chip8_core::backend::DisplayBackend;
+use chip8_core::display::Display;
+use chip8_core::keyboard::{Keyboard, KeyboardTrait};
+use crossterm::{
+    ExecutableCommand,
+    cursor::{Hide, MoveTo, Show},
+    event::{Event, KeyCode, KeyEvent, KeyModifiers, poll, read},
+    terminal::{Clear, ClearType, disable_raw_mode, enable_raw_mode},
+};
+use std::io::{self, Write, stdin, stdout};
+use std::time::Duration;
+
+// How the screen is printed on every refresh
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    // Redraws the screen in place using crossterm
+    Terminal,
+    // Prints every frame below the previous one without any terminal control
+    Plain,
+    // Doesn't print anything, the pixels are only kept in memory
+    Headless,
+}
+
+impl BackendKind {
+    pub fn from_name(name: &str) -> Option<BackendKind> {
+        match name.to_ascii_lowercase().as_str() {
+            "terminal" => Some(BackendKind::Terminal),
+            "plain" => Some(BackendKind::Plain),
+            "headless" => Some(BackendKind::Headless),
+            _ => None,
+        }
+    }
+}
+
+// Number of quick-save slots, F1 to F4 save on them and F5 to F8 load them back
+pub const SAVE_SLOTS: u8 = 4;
+
+// Keys handled by the frontend instead of being sent to the CHIP-8 keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    SaveState(u8),
+    LoadState(u8),
+    // Backspace, goes back a few frames every time it is pressed
+    Rewind,
+    // F12, pauses the machine in the debugger
+    Break,
+    // Ctrl+C, the frontend stops after finishing what is pending like the recordings
+    Quit,
+}
+
+impl Hotkey {
+    fn from_key_code(code: KeyCode) -> Option<Hotkey> {
+        match code {
+            KeyCode::F(number) if (1..=SAVE_SLOTS).contains(&number) => {
+                Some(Hotkey::SaveState(number))
+            }
+            KeyCode::F(number) if (SAVE_SLOTS + 1..=SAVE_SLOTS * 2).contains(&number) => {
+                Some(Hotkey::LoadState(number - SAVE_SLOTS))
+            }
+            KeyCode::Backspace => Some(Hotkey::Rewind),
+            KeyCode::F(12) => Some(Hotkey::Break),
+            _ => None,
+        }
+    }
+}
+
+pub struct Renderer {
+    backend: BackendKind,
+    // How many characters are printed for each pixel, horizontally and vertically
+    scale: usize,
+}
+
+impl DisplayBackend for Renderer {
+    fn present(&mut self, display: &Display) {
+        match self.backend {
+            BackendKind::Terminal => self.print_with_crossterm(display),
+            BackendKind::Plain => self.print(display),
+            BackendKind::Headless => {}
+        }
+    }
+}
+
+impl Renderer {
+    pub fn new(backend: BackendKind, scale: usize) -> Renderer {
+        Renderer {
+            backend,
+            scale: scale.max(1),
+        }
+    }
+
+    // Every pixel becomes `scale` characters wide and `scale` rows tall, the palette has one
+    // character for each of the 4 colours
+    fn scaled_rows(&self, display: &Display, palette: [char; 4]) -> Vec<String> {
+        let mut rows = Vec::with_capacity(display.height() * self.scale);
+        for row in display.rows() {
+            let row_string: String = row
+                .iter()
+                .flat_map(|&color| std::iter::repeat_n(palette[color as usize], self.scale))
+                .collect();
+            for _ in 0..self.scale {
+                rows.push(row_string.clone());
+            }
+        }
+        rows
+    }
+
+    // print without lib
+    // The lines end with \r\n so it also works when the terminal is in raw mode
+    pub fn print(&self, display: &Display) {
+        print!("  \r\n");
+        print!("////\r\n");
+        print!("  \r\n");
+        for row in self.scaled_rows(display, ['_', '█', '▒', '▓']) {
+            print!("{}\r\n", row);
+        }
+        let _ = stdout().flush();
+    }
+
+    // A line of information below the screen, like the result of a quick-save
+    pub fn status(&self, display: &Display, message: &str) {
+        match self.backend {
+            BackendKind::Terminal => {
+                let mut stdout = stdout();
+                let row = (display.height() * self.scale) as u16 + 1;
+                let _ = stdout.execute(MoveTo(0, row));
+                let _ = stdout.execute(Clear(ClearType::CurrentLine));
+                print!("{}", message);
+                let _ = stdout.flush();
+            }
+            BackendKind::Plain => print!("{}\r\n", message),
+            BackendKind::Headless => {}
+        }
+    }
+
+    // Lines below the screen, like the panes of the debugger
+    pub fn panel(&self, display: &Display, lines: &[String]) {
+        match self.backend {
+            BackendKind::Terminal => {
+                let mut stdout = stdout();
+                let first_row = (display.height() * self.scale) as u16 + 1;
+                for (index, line) in lines.iter().enumerate() {
+                    let _ = stdout.execute(MoveTo(0, first_row + index as u16));
+                    let _ = stdout.execute(Clear(ClearType::CurrentLine));
+                    print!("{}", line);
+                }
+                let _ = stdout.execute(MoveTo(0, first_row + lines.len() as u16));
+                let _ = stdout.execute(Clear(ClearType::FromCursorDown));
+                let _ = stdout.flush();
+            }
+            BackendKind::Plain => {
+                for line in lines {
+                    print!("{}\r\n", line);
+                }
+            }
+            BackendKind::Headless => {}
+        }
+    }
+
+    fn print_with_crossterm(&self, display: &Display) {
+        let mut stdout = stdout();
+
+        stdout.execute(Clear(ClearType::All)).unwrap();
+        stdout.execute(Hide).unwrap();
+
+        for (row_idx, row_string) in self
+            .scaled_rows(display, ['·', '█', '▒', '▓'])
+            .iter()
+            .enumerate()
+        {
+            stdout.execute(MoveTo(0, row_idx as u16)).unwrap();
+            print!("{}", row_string);
+        }
+
+        stdout.execute(Show).unwrap();
+        stdout.flush().unwrap();
+    }
+}
+
+// Reads a whole line with the terminal out of raw mode, so it can be edited. None when the
+// input is closed
+pub fn read_line(prompt: &str) -> io::Result<Option<String>> {
+    disable_raw_mode()?;
+    print!("{}", prompt);
+    stdout().flush()?;
+    let mut line = String::new();
+    let read = stdin().read_line(&mut line);
+    enable_raw_mode()?;
+    Ok((read? > 0).then_some(line))
+}
+
+// Reads every pending terminal event without blocking and presses the mapped keys, the hotkeys
+// are returned for the frontend to handle
+pub fn process_any_input(keyboard: &mut Keyboard) -> Vec<Hotkey> {
+    let mut hotkeys = Vec::new();
+
+    while poll(Duration::from_millis(0)).unwrap() {
+        if let Ok(Event::Key(KeyEvent {
+            code, modifiers, ..
+        })) = read()
+        {
+            if modifiers.contains(KeyModifiers::CONTROL) && code == KeyCode::Char('c') {
+                hotkeys.push(Hotkey::Quit);
+            } else if let Some(hotkey) = Hotkey::from_key_code(code) {
+                hotkeys.push(hotkey);
+            } else if let KeyCode::Char(c) = code
+                && let Some(chip8_key) = keyboard.map_key_to_chip8(c)
+            {
+                keyboard.press_only(chip8_key);
+            }
+        }
+    }
+    hotkeys
+}
@@ -0,0 +1,74 @@
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+// Both timers count down at 60 Hz no matter how fast the CPU is running
+pub const TIMERS_FREQUENCY: u32 = 60;
+
+pub struct Timers {
+    delay_timer: u8,
+    sound_timer: u8,
+}
+
+pub trait TimersTrait {
+    fn delay_timer(&self) -> u8;
+    fn set_delay_timer(&mut self, value: u8);
+    fn sound_timer(&self) -> u8;
+    fn set_sound_timer(&mut self, value: u8);
+    // Decrements both timers once, what happens every 1/60 of a second
+    fn tick(&mut self);
+    // The buzzer sounds while the sound timer is above zero
+    fn is_buzzer_active(&self) -> bool;
+}
+
+impl TimersTrait for Timers {
+    fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    fn tick(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    fn is_buzzer_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Timers {
+        Timers::new()
+    }
+}
+
+impl Timers {
+    pub fn new() -> Timers {
+        Timers {
+            delay_timer: 0,
+            sound_timer: 0,
+        }
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.delay_timer);
+        writer.write_u8(self.sound_timer);
+    }
+
+    pub(crate) fn load_state(reader: &mut StateReader) -> Result<Timers, StateError> {
+        Ok(Timers {
+            delay_timer: reader.read_u8()?,
+            sound_timer: reader.read_u8()?,
+        })
+    }
+}
@@ -0,0 +1,135 @@
+use chip8_core::assembler::{assemble, assemble_file};
+use chip8_core::disassembler::disassemble;
+use chip8_core::instruction::Instruction;
+use chip8_core::quirks::Quirks;
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn every_opcode_round_trips() {
+    for opcode in 0..=0xFFFF {
+        let Ok(instruction) = Instruction::decode(opcode) else {
+            continue;
+        };
+        assert_eq!(instruction.encode(), opcode, "{}", instruction);
+
+        // F000 needs its address, which is not part of the mnemonic
+        let source = if instruction == Instruction::SetNextWordToI {
+            format!("{} 0x1234", instruction)
+        } else {
+            instruction.to_string()
+        };
+        let assembly = assemble(&source).unwrap_or_else(|e| panic!("{}: {}", source, e));
+        assert_eq!(assembly.rom[..2], opcode.to_be_bytes(), "{}", source);
+    }
+}
+
+#[test]
+fn disassembled_roms_round_trip() {
+    let ibm_logo = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("IBM Logo.ch8")).unwrap();
+    // Instructions of every platform, a skip over F000 NNNN and data that looks like code
+    let xo_chip = vec![
+        0x00, 0xFF, 0x30, 0x01, 0xF0, 0x00, 0x12, 0x34, 0x51, 0x32, 0xF3, 0x01, 0x22, 0x12, 0x12,
+        0x00, 0x00, 0xEE, 0xA2, 0x1A, 0x00, 0xEE, 0x00, 0xE0, 0xFF,
+    ];
+    // BXNN jumps relative to V2 with the SUPER-CHIP quirks
+    let jump = vec![0xB2, 0x34];
+    for (rom, quirks) in [
+        (ibm_logo, Quirks::modern()),
+        (xo_chip.clone(), Quirks::modern()),
+        (xo_chip, Quirks::xo_chip()),
+        (jump.clone(), Quirks::modern()),
+        (jump, Quirks::super_chip()),
+    ] {
+        let source = disassemble(&rom, quirks).source();
+        let assembly = assemble(&source).unwrap_or_else(|e| panic!("{}\n{}", e, source));
+        assert_eq!(assembly.rom, rom, "{}", source);
+    }
+}
+
+#[test]
+fn labels_constants_and_expressions() {
+    let source = "
+        ; Draws a sprite and waits forever
+        HEIGHT EQU end - sprite
+        X EQU (1 << 4) | 0x02   ; 18
+        start:  CLS
+                LD V0, X
+                ld v1, -1
+                LD I, sprite
+                DRW V0, V1, HEIGHT
+        loop:   JP loop
+                JP $ + 2 * 2
+        ORG 0x210
+        sprite: DB 0b11110000, 0x90 , 0xF0
+        end:    DW sprite, end - start, -2
+    ";
+    let assembly = assemble(source).unwrap();
+    assert_eq!(
+        assembly.rom,
+        vec![
+            0x00, 0xE0, 0x60, 0x12, 0x61, 0xFF, 0xA2, 0x10, 0xD0, 0x13, 0x12, 0x0A, 0x12, 0x10,
+            0x00, 0x00, 0xF0, 0x90, 0xF0, 0x02, 0x10, 0x00, 0x13, 0xFF, 0xFE,
+        ]
+    );
+    assert_eq!(assembly.symbols["loop"], 0x20A);
+    assert_eq!(assembly.symbols["HEIGHT"], 3);
+    assert!(
+        assembly
+            .symbol_map()
+            .starts_with("0x0003 HEIGHT\n0x0012 X\n0x0200 start\n")
+    );
+}
+
+#[test]
+fn includes_are_relative_to_the_file() {
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("assembler_include");
+    fs::create_dir_all(directory.join("lib")).unwrap();
+    fs::write(
+        directory.join("main.asm"),
+        "CALL draw\nJP 0x202\nINCLUDE \"lib/draw.asm\"\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("lib/draw.asm"),
+        "draw:\nINCLUDE \"ret.asm\"\n",
+    )
+    .unwrap();
+    fs::write(directory.join("lib/ret.asm"), "RET\n").unwrap();
+
+    let assembly = assemble_file(&directory.join("main.asm")).unwrap();
+    assert_eq!(assembly.rom, vec![0x22, 0x04, 0x12, 0x02, 0x00, 0xEE]);
+}
+
+#[test]
+fn errors_point_to_the_line() {
+    for (source, line, message) in [
+        ("CLS\nJP nowhere", 2, "Unknown symbol: nowhere"),
+        ("LD V0, 256", 1, "A byte can't be 256"),
+        ("DRW V0, V1, 16", 1, "A nibble can't be 16"),
+        ("JP 0x1000", 1, "An address can't be 4096"),
+        ("\n\nMOV V0, V1", 3, "Unknown instruction: MOV"),
+        ("ADD V0", 1, "Invalid operands for ADD"),
+        ("a: CLS\na: CLS", 2, "a is already defined"),
+        ("K: CLS", 1, "K is reserved"),
+        (
+            "SIZE EQU END + 1\nEND EQU SIZE",
+            1,
+            "SIZE depends on itself",
+        ),
+        ("ORG 0x300\nORG 0x200", 2, "ORG 0x200 goes back from 0x300"),
+        (
+            "JP V3, 0x234",
+            1,
+            "JP V3 needs an address from 0x300 to 0x3FF",
+        ),
+    ] {
+        let error = assemble(source).err().unwrap();
+        assert_eq!(
+            (error.line, error.message.as_str()),
+            (line, message),
+            "{}",
+            source
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use chip8_core::audio::{AudioConfig, AudioSink, Buzzer, BuzzerTrait, WavSink, Waveform};
+use chip8_core::timers::{Timers, TimersTrait};
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+// Keeps every sample for the test to look at
+struct Recorder(Rc<RefCell<Vec<i16>>>);
+
+impl AudioSink for Recorder {
+    fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        self.0.borrow_mut().extend_from_slice(samples);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn wav_header_and_samples() {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("samples.wav");
+    let mut sink = WavSink::create(path.to_str().unwrap(), 8000).unwrap();
+    sink.write_samples(&[1, -2, 0x1234]).unwrap();
+    sink.write_samples(&[i16::MIN]).unwrap();
+    sink.finish().unwrap();
+    drop(sink);
+
+    let mut expected = Vec::new();
+    expected.extend_from_slice(b"RIFF");
+    expected.extend_from_slice(&(36u32 + 8).to_le_bytes());
+    expected.extend_from_slice(b"WAVEfmt ");
+    // 16 bytes of format: PCM, mono, 8000 Hz, 16000 bytes per second, 2 bytes per sample, 16 bits
+    expected.extend_from_slice(&[16, 0, 0, 0, 1, 0, 1, 0]);
+    expected.extend_from_slice(&8000u32.to_le_bytes());
+    expected.extend_from_slice(&16000u32.to_le_bytes());
+    expected.extend_from_slice(&[2, 0, 16, 0]);
+    expected.extend_from_slice(b"data");
+    expected.extend_from_slice(&8u32.to_le_bytes());
+    expected.extend_from_slice(&[0x01, 0x00, 0xFE, 0xFF, 0x34, 0x12, 0x00, 0x80]);
+    assert_eq!(fs::read(&path).unwrap(), expected);
+}
+
+#[test]
+fn square_wave_follows_the_sound_timer() {
+    let samples = Rc::new(RefCell::new(Vec::new()));
+    // 8 samples for each cycle of the tone and 16 for each frame of 20 ms
+    let config = AudioConfig {
+        sample_rate: 800,
+        frequency: 100.0,
+        volume: 0.5,
+        waveform: Waveform::Square,
+    };
+    let mut buzzer = Buzzer::new(config, Box::new(Recorder(Rc::clone(&samples))));
+    let mut timers = Timers::new();
+    timers.set_sound_timer(2);
+    for frame in 0..5 {
+        if frame == 3 {
+            timers.set_sound_timer(1);
+        }
+        buzzer
+            .generate(timers.is_buzzer_active(), Duration::from_millis(20))
+            .unwrap();
+        timers.tick();
+    }
+    buzzer.finish().unwrap();
+
+    let cycle = [16383, 16383, 16383, 16383, -16383, -16383, -16383, -16383];
+    let tone: Vec<i16> = cycle.repeat(2);
+    let silence = vec![0; 16];
+    // The tone goes on across frames and starts over after a silence
+    let expected = [&tone[..], &tone, &silence, &tone, &silence].concat();
+    assert_eq!(*samples.borrow(), expected);
+}
@@ -0,0 +1,265 @@
+use chip8_core::assembler::assemble;
+use chip8_core::condition::Condition;
+use chip8_core::cpu::Cpu;
+use chip8_core::debugger::{
+    Command, Debugger, WatchKind, Watchpoint, disassembly_pane, registers_pane, watchpoints_pane,
+};
+use chip8_core::memory::{Memory, SharedMemory};
+use chip8_core::rom::load_rom_bytes;
+use chip8_core::scheduler::{FrameOutcome, Scheduler, SchedulerTrait};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const PROGRAM: &str = "
+    start:  LD V0, 1         ; 200
+            CALL add         ; 202
+            LD V2, 3         ; 204
+    loop:   JP loop          ; 206
+    add:    ADD V0, 1        ; 208
+            CALL nested      ; 20A
+            RET              ; 20C
+    nested: LD V1, 2         ; 20E
+            RET              ; 210
+";
+
+// Counts with V0, stores V0 and V1 at 0x300 on every turn, then reads them back
+const STORE: &str = "
+            LD I, 0x300      ; 200
+    loop:   ADD V0, 1        ; 202
+            LD [I], V1       ; 204
+            LD V1, [I]       ; 206
+            JP loop          ; 208
+";
+
+fn machine() -> (Cpu, Scheduler, Debugger) {
+    machine_with(PROGRAM)
+}
+
+fn machine_with(program: &str) -> (Cpu, Scheduler, Debugger) {
+    let memory: SharedMemory = Rc::new(RefCell::new(Memory::new()));
+    load_rom_bytes(&assemble(program).unwrap().rom, Rc::clone(&memory)).unwrap();
+    (Cpu::new(memory), Scheduler::new(700), Debugger::new())
+}
+
+// Runs frames until the debugger pauses the machine, returns the program counter
+fn run(cpu: &mut Cpu, scheduler: &mut Scheduler, debugger: &mut Debugger, command: &str) -> usize {
+    let command = Command::parse(command).unwrap();
+    debugger.run_command(command, cpu).unwrap();
+    for _ in 0..10 {
+        let outcome = scheduler.run_frame_until(cpu, &mut |cpu| debugger.should_pause(cpu));
+        if outcome.unwrap() == FrameOutcome::Paused {
+            return cpu.program_counter();
+        }
+    }
+    panic!("The debugger never paused");
+}
+
+#[test]
+fn steps_into_and_over_calls() {
+    let (mut cpu, mut scheduler, mut debugger) = machine();
+    assert!(debugger.is_paused());
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "step"), 0x202);
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "s"), 0x208);
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "next"), 0x20A);
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "next"), 0x20C);
+    assert_eq!(cpu.registers()[1], 2);
+    assert_eq!(
+        run(&mut cpu, &mut scheduler, &mut debugger, "finish"),
+        0x204
+    );
+    assert_eq!(debugger.stop_reason(), "Returned");
+    assert!(debugger.run_command(Command::Finish, &cpu).is_err());
+}
+
+#[test]
+fn breakpoints_stop_anywhere() {
+    let (mut cpu, mut scheduler, mut debugger) = machine();
+    debugger.run_command(Command::Break(0x20E), &cpu).unwrap();
+    debugger.run_command(Command::Break(0x206), &cpu).unwrap();
+    assert_eq!(
+        run(&mut cpu, &mut scheduler, &mut debugger, "continue"),
+        0x20E
+    );
+    assert_eq!(debugger.stop_reason(), "Breakpoint at 0x20E");
+    assert_eq!(cpu.stack().addresses(), [0x204, 0x20C]);
+    // The breakpoint is left before checking again, a jump to itself stops on every turn
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "c"), 0x206);
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "c"), 0x206);
+
+    debugger.run_command(Command::Delete(0x206), &cpu).unwrap();
+    assert!(debugger.run_command(Command::Delete(0x206), &cpu).is_err());
+    assert_eq!(debugger.breakpoints().len(), 1);
+}
+
+#[test]
+fn a_paused_frame_is_finished_later() {
+    let (mut cpu, mut scheduler, mut debugger) = machine();
+    debugger.run_command(Command::Break(0x206), &cpu).unwrap();
+    run(&mut cpu, &mut scheduler, &mut debugger, "c");
+    assert_eq!(scheduler.frames(), 0);
+    debugger.run_command(Command::Delete(0x206), &cpu).unwrap();
+    debugger.run_command(Command::Continue, &cpu).unwrap();
+    let outcome = scheduler.run_frame_until(&mut cpu, &mut |cpu| debugger.should_pause(cpu));
+    assert_eq!(outcome, Ok(FrameOutcome::Completed));
+    // 11 instructions for each frame at 700 per second
+    assert_eq!((scheduler.frames(), scheduler.instructions()), (1, 11));
+}
+
+#[test]
+fn watchpoints_report_the_instruction() {
+    let (mut cpu, mut scheduler, mut debugger) = machine_with(STORE);
+    let watch = Command::parse("watch write 300-301").unwrap();
+    debugger.run_command(watch, &cpu).unwrap();
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "c"), 0x206);
+    assert_eq!(
+        debugger.stop_reason(),
+        "Write of 0x01 at 0x300 (was 0x00) by 0x204: LD [I], V1"
+    );
+
+    // The same values are written again on the next turn
+    debugger.run_command(Command::Unwatch(1), &cpu).unwrap();
+    let watch = Command::parse("watch change 300").unwrap();
+    debugger.run_command(watch, &cpu).unwrap();
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "c"), 0x206);
+    assert_eq!(
+        debugger.stop_reason(),
+        "Write of 0x02 at 0x300 (was 0x01) by 0x204: LD [I], V1"
+    );
+
+    // The instructions fetched aren't reads of the program
+    debugger.run_command(Command::Unwatch(2), &cpu).unwrap();
+    let watch = Command::parse("watch read 202-301").unwrap();
+    debugger.run_command(watch, &cpu).unwrap();
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "c"), 0x208);
+    assert_eq!(
+        debugger.stop_reason(),
+        "Read of 0x02 at 0x300 by 0x206: LD V1, [I]"
+    );
+    assert!(debugger.run_command(Command::Unwatch(2), &cpu).is_err());
+
+    // But an instruction reading itself is
+    let (mut cpu, mut scheduler, mut debugger) = machine_with(
+        "
+                LD I, 0x202      ; 200
+                LD V1, [I]       ; 202
+        loop:   JP loop          ; 204
+        ",
+    );
+    let watch = Command::parse("watch read 202-203").unwrap();
+    debugger.run_command(watch, &cpu).unwrap();
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "c"), 0x204);
+    assert_eq!(
+        debugger.stop_reason(),
+        "Read of 0xF1 at 0x202 by 0x202: LD V1, [I]"
+    );
+    assert_eq!(cpu.registers()[..2], [0xF1, 0x65]);
+}
+
+#[test]
+fn conditions_pause_when_they_become_true() {
+    let (mut cpu, mut scheduler, mut debugger) = machine_with(STORE);
+    let watch = Command::parse("watch if V0 >= 3 && [I] == V0 - 1").unwrap();
+    debugger.run_command(watch, &cpu).unwrap();
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "c"), 0x204);
+    assert_eq!(
+        debugger.stop_reason(),
+        "V0 >= 3 && [I] == V0 - 1 became true after 0x202: ADD V0, 0x01"
+    );
+    assert_eq!(cpu.registers()[0], 3);
+    // Still true after the next instruction, it has to become false first
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "s"), 0x206);
+    assert_eq!(debugger.stop_reason(), "Stepped");
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "c"), 0x204);
+    assert_eq!(cpu.registers()[0], 4);
+
+    let (mut cpu, mut scheduler, mut debugger) = machine_with(STORE);
+    let breakpoint = Command::parse("break 208 if V0 == 5").unwrap();
+    debugger.run_command(breakpoint, &cpu).unwrap();
+    assert_eq!(run(&mut cpu, &mut scheduler, &mut debugger, "c"), 0x208);
+    assert_eq!(debugger.stop_reason(), "Breakpoint at 0x208");
+    assert_eq!(cpu.registers()[0], 5);
+    assert_eq!(watchpoints_pane(&debugger), ["Break 0x208 if V0 == 5"]);
+}
+
+#[test]
+fn parses_conditions() {
+    let cpu = machine().0;
+    let value = |text: &str| Condition::parse(text).unwrap().evaluate(&cpu);
+    assert_eq!(value("1 + 2 == 3"), 1);
+    assert_eq!(value("0x80 & 0xF0 == 0x80"), 1);
+    assert_eq!(value("!(PC == 0x200) || SP"), 0);
+    assert_eq!(value("[0x200] - 1"), 0x5F);
+    assert_eq!(value("-1 < DT"), 1);
+    assert_eq!(
+        Condition::parse("v3  ==  1").unwrap().to_string(),
+        "v3 == 1"
+    );
+    assert!(Condition::parse("V3 ==").is_err());
+    assert!(Condition::parse("VG").is_err());
+    assert!(Condition::parse("[I").is_err());
+    assert!(Condition::parse("V1 V2").is_err());
+}
+
+#[test]
+fn parses_commands() {
+    assert_eq!(Command::parse("b 2A0"), Ok(Command::Break(0x2A0)));
+    assert_eq!(Command::parse("break 0x2a0"), Ok(Command::Break(0x2A0)));
+    assert_eq!(Command::parse("  delete 200 "), Ok(Command::Delete(0x200)));
+    assert_eq!(Command::parse("q"), Ok(Command::Quit));
+    assert_eq!(
+        Command::parse("watch read 300-30f"),
+        Ok(Command::Watch(Watchpoint::Memory {
+            kind: WatchKind::Read,
+            start: 0x300,
+            end: 0x30F
+        }))
+    );
+    assert_eq!(
+        Command::parse("b 20E if V0 > 1"),
+        Ok(Command::BreakIf(0x20E, Condition::parse("V0 > 1").unwrap()))
+    );
+    assert_eq!(Command::parse("unwatch 2"), Ok(Command::Unwatch(2)));
+    assert!(Command::parse("break").is_err());
+    assert!(Command::parse("break 200 if").is_err());
+    assert!(Command::parse("watch 300").is_err());
+    assert!(Command::parse("watch write 30F-300").is_err());
+    assert_eq!(
+        Command::parse("watch write 1F0-210"),
+        Ok(Command::Watch(Watchpoint::Memory {
+            kind: WatchKind::Write,
+            start: 0x200,
+            end: 0x210
+        }))
+    );
+    assert!(Command::parse("watch change 100-1FF").is_err());
+    assert!(Command::parse("watch change 50").is_err());
+    assert!(Command::parse("watch read 50").is_ok());
+    assert!(Command::parse("break xyz").is_err());
+    assert!(Command::parse("step 2").is_err());
+    assert!(Command::parse("jump").is_err());
+}
+
+#[test]
+fn panes() {
+    let (mut cpu, mut scheduler, mut debugger) = machine();
+    debugger.run_command(Command::Break(0x20E), &cpu).unwrap();
+    run(&mut cpu, &mut scheduler, &mut debugger, "c");
+
+    let registers = registers_pane(&cpu);
+    assert_eq!(
+        registers[0],
+        "V0 02  V1 00  V2 00  V3 00  V4 00  V5 00  V6 00  V7 00"
+    );
+    assert_eq!(registers[2], "PC 20E  I 000  DT 00  ST 00");
+    assert_eq!(registers[3], "Stack: 204 20C");
+
+    let disassembly = disassembly_pane(&cpu, &debugger, 1);
+    assert_eq!(
+        disassembly,
+        [
+            "   20C  00EE  RET",
+            "*> 20E  6102  LD V1, 0x02",
+            "   210  00EE  RET",
+        ]
+    );
+}
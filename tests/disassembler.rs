@@ -0,0 +1,67 @@
+use chip8_core::disassembler::{Item, disassemble};
+use chip8_core::quirks::Quirks;
+
+// A subroutine at 0x2A4 and a sprite after the endless loop, with the words in between never
+// reached
+fn rom() -> Vec<u8> {
+    let mut rom = vec![
+        0x62, 0x37, // 200: LD V2, 0x37
+        0x22, 0xA4, // 202: CALL 0x2A4
+        0x32, 0x37, // 204: SE V2, 0x37
+        0x00, 0xE0, // 206: CLS, skipped or not
+        0x12, 0x08, // 208: JP 0x208
+        0xF0, 0x90, 0xF0, // 20A: the sprite of a 0
+    ];
+    rom.resize(0xA4, 0x12);
+    rom.extend_from_slice(&[
+        0xD0, 0x15, // 2A4: DRW V0, V1, 5
+        0x00, 0xEE, // 2A6: RET
+    ]);
+    rom
+}
+
+#[test]
+fn the_control_flow_separates_code_from_data() {
+    let disassembly = disassemble(&rom(), Quirks::modern());
+    let code: Vec<usize> = disassembly
+        .items()
+        .iter()
+        .filter(|item| matches!(item, Item::Instruction { .. }))
+        .map(|item| item.address())
+        .collect();
+    assert_eq!(code, [0x200, 0x202, 0x204, 0x206, 0x208, 0x2A4, 0x2A6]);
+    // Every byte in between is data, even the ones that would decode as jumps
+    let data: Vec<usize> = disassembly
+        .items()
+        .iter()
+        .filter(|item| matches!(item, Item::Data { .. }))
+        .map(|item| item.address())
+        .collect();
+    assert_eq!(data, (0x20A..0x2A4).collect::<Vec<_>>());
+    assert!(disassembly.is_target(0x208) && disassembly.is_target(0x2A4));
+    assert!(!disassembly.is_target(0x206));
+
+    let listing = disassembly.to_string();
+    for line in [
+        "  200  6237       LD V2, 0x37",
+        "  202  22A4       CALL 0x2A4",
+        "  204  3237       SE V2, 0x37",
+        "  206  00E0       CLS",
+        "L208:",
+        "  20A  F0         DB 0xF0  ; ####....",
+        "  20B  90         DB 0x90  ; #..#....",
+        "L2A4:",
+        "  2A4  D015       DRW V0, V1, 5",
+        "  2A6  00EE       RET",
+    ] {
+        assert!(listing.lines().any(|l| l == line), "{}\n{}", line, listing);
+    }
+}
+
+#[test]
+fn bnnn_names_the_register_of_the_quirks() {
+    let rom = [0xB2, 0x34];
+    let mnemonic = |quirks| disassemble(&rom, quirks).source();
+    assert_eq!(mnemonic(Quirks::modern()), "    JP V0, 0x234\n");
+    assert_eq!(mnemonic(Quirks::super_chip()), "    JP V2, 0x234\n");
+}
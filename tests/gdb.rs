@@ -0,0 +1,216 @@
+use chip8_core::assembler::assemble;
+use chip8_core::cpu::Cpu;
+use chip8_core::gdb::{GdbStub, Served};
+use chip8_core::memory::{AccessKind, Memory, MemoryTrait, SharedMemory};
+use chip8_core::rom::load_rom_bytes;
+use chip8_core::scheduler::{FrameOutcome, Scheduler, SchedulerTrait};
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::thread;
+
+const PROGRAM: &str = "
+    start:  LD V0, 1         ; 200
+            CALL add         ; 202
+            LD V2, 3         ; 204
+    loop:   JP loop          ; 206
+    add:    ADD V0, 1        ; 208
+            CALL nested      ; 20A
+            RET              ; 20C
+    nested: LD V1, 2         ; 20E
+            RET              ; 210
+";
+
+// The side of gdb, waiting for the reply of every packet
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+    }
+
+    fn read_packet(&mut self) -> String {
+        let mut packet = Vec::new();
+        let mut byte = [0];
+        loop {
+            self.stream.read_exact(&mut byte).unwrap();
+            match byte[0] {
+                b'+' if packet.is_empty() => {}
+                b'$' => packet.clear(),
+                b'#' => break,
+                byte => packet.push(byte),
+            }
+        }
+        let mut checksum = [0; 2];
+        self.stream.read_exact(&mut checksum).unwrap();
+        let sum = packet.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        assert_eq!(
+            std::str::from_utf8(&checksum).unwrap(),
+            format!("{:02x}", sum)
+        );
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(packet).unwrap()
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.read_packet()
+    }
+}
+
+// Runs the emulator on this thread like the frontend does, and the client on another one. The
+// stub stops serving when the client detaches or kills it
+fn session(script: impl FnOnce(&mut Client) + Send + 'static) -> Cpu {
+    session_with(PROGRAM, script)
+}
+
+fn session_with(program: &str, script: impl FnOnce(&mut Client) + Send + 'static) -> Cpu {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+        script(&mut Client { stream });
+    });
+
+    let memory: SharedMemory = Rc::new(RefCell::new(Memory::new()));
+    load_rom_bytes(&assemble(program).unwrap().rom, Rc::clone(&memory)).unwrap();
+    // Like a debugger watching the memory, gdb's own reads must not show up
+    memory.borrow_mut().record_accesses(true);
+    let mut cpu = Cpu::new(memory);
+    let mut scheduler = Scheduler::new(700);
+    let mut stub = GdbStub::accept(&listener).unwrap();
+    for _ in 0..100_000 {
+        stub.poll().unwrap();
+        if stub.is_paused() && stub.serve(&mut cpu).unwrap() != Served::Resumed {
+            break;
+        }
+        match scheduler.run_frame_until(&mut cpu, &mut |cpu| stub.should_pause(cpu)) {
+            Ok(outcome) => assert_ne!(outcome, FrameOutcome::Halted),
+            Err(error) => stub.report_error(&mut cpu, &error),
+        }
+    }
+    client.join().unwrap();
+    cpu
+}
+
+#[test]
+fn registers_and_memory() {
+    let cpu = session(|client| {
+        assert!(
+            client
+                .request("qSupported:swbreak+")
+                .contains("qXfer:features:read+")
+        );
+        assert_eq!(client.request("?"), "S05");
+        // V0 to VF, I and PC in little endian, then SP
+        assert_eq!(
+            client.request("g"),
+            format!("{}0000000200", "00".repeat(16))
+        );
+        assert_eq!(client.request("P10=0003"), "OK");
+        assert_eq!(client.request("p10"), "0003");
+        assert_eq!(client.request("P3=7f"), "OK");
+        assert_eq!(client.request("P11=0002"), "OK");
+        assert_eq!(client.request("P12=01"), "E01");
+        assert_eq!(client.request("p13"), "E01");
+        // Nothing is written when a register is wrong, here SP above the depth of the stack
+        let registers = client.request("g");
+        assert_eq!(
+            client.request(&format!("G{}0004000205", "11".repeat(16))),
+            "E01"
+        );
+        assert_eq!(client.request("g"), registers);
+
+        assert_eq!(client.request("m200,4"), "60012208");
+        assert_eq!(client.request("M300,2:abcd"), "OK");
+        assert_eq!(client.request("m2ff,3"), "00abcd");
+        // The interpreter area is read only, and nothing is past the end of the memory
+        assert_eq!(client.request("m50,1"), "f0");
+        assert_eq!(client.request("M50,1:00"), "E01");
+        assert_eq!(client.request("mfff,2"), "E01");
+        // Nor when part of the range can't be written
+        assert_eq!(client.request("M1ff,2:1111"), "E01");
+        assert_eq!(client.request("m200,1"), "60");
+        assert_eq!(client.request("Mffe,3:111111"), "E01");
+        assert_eq!(client.request("mffe,2"), "0000");
+
+        let description = client.request("qXfer:features:read:target.xml:0,2000");
+        assert!(description.starts_with("l<?xml"));
+        assert!(description.contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>"));
+        assert!(
+            client
+                .request("qXfer:features:read:target.xml:0,10")
+                .starts_with('m')
+        );
+        assert_eq!(client.request("vMustReplyEmpty"), "");
+        client.send("k");
+    });
+    assert_eq!(cpu.i_register(), 0x300);
+    assert_eq!(cpu.registers()[3], 0x7F);
+    let memory = cpu.memory().borrow();
+    assert_eq!(memory.peek(0x301), 0xCD);
+    let accesses = memory.take_accesses();
+    assert_eq!(accesses.len(), 2);
+    assert!(
+        accesses
+            .iter()
+            .all(|access| access.kind == AccessKind::Write)
+    );
+}
+
+#[test]
+fn breakpoints_steps_and_interrupts() {
+    let cpu = session(|client| {
+        assert_eq!(client.request("QStartNoAckMode"), "OK");
+        assert_eq!(client.request("Z0,20e,2"), "OK");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.request("p11"), "0e02");
+        assert_eq!(client.request("p12"), "02");
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p11"), "1002");
+        assert_eq!(client.request("p1"), "02");
+
+        // The breakpoint is left before checking again
+        assert_eq!(client.request("Z0,206,2"), "OK");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.request("p11"), "0602");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.request("z0,206,2"), "OK");
+        assert_eq!(client.request("Z2,300,1"), "");
+
+        // Ctrl+C stops the endless loop
+        client.send("c");
+        client.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(client.read_packet(), "S02");
+        assert_eq!(client.request("p11"), "0602");
+        assert_eq!(client.request("D"), "OK");
+    });
+    assert_eq!(cpu.registers()[..3], [2, 2, 3]);
+}
+
+#[test]
+fn errors_stop_the_machine_without_ending_the_session() {
+    let program = "
+                LD V0, 1         ; 200
+                RET              ; 202
+        loop:   JP loop          ; 204
+    ";
+    let cpu = session_with(program, |client| {
+        assert_eq!(client.request("c"), "S04");
+        assert_eq!(client.request("?"), "S04");
+        assert_eq!(client.request("p0"), "01");
+        assert_eq!(client.request("p11"), "0202");
+        // Running the same instruction fails again, until gdb moves past it
+        assert_eq!(client.request("c"), "S04");
+        assert_eq!(client.request("P11=0402"), "OK");
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p11"), "0402");
+        client.send("k");
+    });
+    assert_eq!(cpu.program_counter(), 0x204);
+}
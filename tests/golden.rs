@@ -0,0 +1,279 @@
+use chip8_core::assembler::assemble;
+use chip8_core::golden::{RomTest, check_golden};
+use chip8_core::quirks::Quirks;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Golden images are plain PGM files, run with UPDATE_GOLDEN=1 to write them again
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.pgm", name))
+}
+
+fn assert_golden(name: &str, test: &RomTest) {
+    let frame = test.run().unwrap();
+    let diff_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.diff.png", name));
+    if let Err(e) = check_golden(&frame, &golden_path(name), &diff_path) {
+        panic!("{}", e);
+    }
+}
+
+// Draws VA in decimal at VC, VD, four numbers on each row. Appended to the programs below, which
+// check one area each like the community test suites do and are small enough to check by hand
+const SHOW: &str = "
+    show:   LD I, digits
+            LD B, VA
+            LD V2, [I]
+            LD F, V0
+            DRW VC, VD, 5
+            ADD VC, 5
+            LD F, V1
+            DRW VC, VD, 5
+            ADD VC, 5
+            LD F, V2
+            DRW VC, VD, 5
+            ADD VC, 6
+            SE VC, 64
+            RET
+            LD VC, 0
+            ADD VD, 6
+            RET
+    digits: DB 0, 0, 0
+    buffer: DB 0, 0, 0, 0
+";
+
+fn program(source: &str) -> Vec<u8> {
+    let source = format!("{}\n    halt:   JP halt\n{}", source, SHOW);
+    assemble(&source)
+        .unwrap_or_else(|e| panic!("{}\n{}", e, source))
+        .rom
+}
+
+#[test]
+fn ibm_logo() {
+    let rom = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("IBM Logo.ch8")).unwrap();
+    assert_golden("ibm_logo", &RomTest::new(rom, 60));
+}
+
+#[test]
+fn waits_for_the_scripted_key() {
+    // Waits for a key and draws its digit: FX0A, FX29, DXY5 and a jump to itself
+    let rom = vec![0xF0, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06];
+    let mut test = RomTest::new(rom, 20);
+    // Nothing is drawn before A is pressed on frame 5
+    test.inputs = vec![(5, 1 << 0xA), (10, 0)];
+    assert_golden("scripted_key", &test);
+}
+
+#[test]
+fn arithmetic() {
+    // 070 016 048 064 / 063 012 051 200 / 065 130 100 077 / 222
+    let rom = program(
+        "
+            LD V3, 0x12
+            LD V4, 0x34
+            ADD V3, V4
+            LD VA, V3
+            CALL show
+            LD VA, 0xF0
+            ADD VA, 0x20
+            CALL show
+            LD V3, 0x50
+            LD V4, 0x20
+            SUB V3, V4
+            LD VA, V3
+            CALL show
+            LD V3, 0x10
+            LD V4, 0x50
+            SUBN V3, V4
+            LD VA, V3
+            CALL show
+
+            LD V3, 0x0F
+            LD V4, 0x30
+            OR V3, V4
+            LD VA, V3
+            CALL show
+            LD V3, 0x3C
+            LD V4, 0x0F
+            AND V3, V4
+            LD VA, V3
+            CALL show
+            LD V3, 0x3C
+            XOR V3, V4
+            LD VA, V3
+            CALL show
+            LD V4, 200
+            LD VA, V4
+            CALL show
+
+            LD VA, 0x82
+            SHR VA
+            CALL show
+            SHL VA
+            CALL show
+            ; Only the last ADD isn't skipped
+            LD VA, 0
+            LD V5, 200
+            LD V6, 1
+            SE V4, 200
+            ADD VA, 1
+            SNE V4, 7
+            ADD VA, 2
+            SE V4, V5
+            ADD VA, 4
+            SNE V4, V6
+            ADD VA, 8
+            SE V4, 7
+            ADD VA, 100
+            CALL show
+            LD I, buffer
+            LD V3, 3
+            ADD I, V3
+            LD V0, 77
+            LD [I], V0
+            LD V0, 0
+            LD I, buffer + 3
+            LD V0, [I]
+            LD VA, V0
+            CALL show
+
+            LD V0, 4
+            JP V0, table
+    table:  LD VA, 1
+            LD VA, 2
+            LD VA, 222
+            CALL show
+        ",
+    );
+    assert_golden("arithmetic", &RomTest::new(rom, 30));
+}
+
+#[test]
+fn flags() {
+    // The result and VF of each operation: 001 001 070 000 / 048 001 208 000 / 240 000 002 001 /
+    // 130 000, then VF used as VX, where the flag wins: 001 000 / 001
+    let mut source = String::new();
+    for (first, second, operation) in [
+        (0xFF, 0x02, "ADD V3, V4"),
+        (0x12, 0x34, "ADD V3, V4"),
+        (0x50, 0x20, "SUB V3, V4"),
+        (0x20, 0x50, "SUB V3, V4"),
+        (0x60, 0x50, "SUBN V3, V4"),
+        (0x05, 0x00, "SHR V3"),
+        (0x41, 0x00, "SHL V3"),
+    ] {
+        source += &format!(
+            "
+            LD V3, {}
+            LD V4, {}
+            {}
+            LD VB, VF
+            LD VA, V3
+            CALL show
+            LD VA, VB
+            CALL show
+            ",
+            first, second, operation
+        );
+    }
+    for (first, second, operation) in [
+        (0xFF, 0x02, "ADD VF, V4"),
+        (0x20, 0x50, "SUB VF, V4"),
+        (0x03, 0x00, "SHR VF"),
+    ] {
+        source += &format!(
+            "
+            LD VF, {}
+            LD V4, {}
+            {}
+            LD VA, VF
+            CALL show
+            ",
+            first, second, operation
+        );
+    }
+    assert_golden("flags", &RomTest::new(program(&source), 30));
+}
+
+// The shift of VY, the reset of VF by the logic instructions, I moved by FX55 and the sprites cut
+// at the edge of the screen
+const QUIRKS: &str = "
+            LD V3, 0x10
+            LD V4, 0x03
+            SHR V3, V4
+            LD VA, V3
+            CALL show
+            LD VF, 7
+            OR V3, V4
+            LD VA, VF
+            CALL show
+            LD I, buffer
+            LD V0, 1
+            LD V1, 2
+            LD [I], V1
+            LD V0, 33
+            LD [I], V0
+            LD I, buffer + 2
+            LD V0, [I]
+            LD VA, V0
+            CALL show
+            LD V0, 0
+            LD V1, 62
+            LD V2, 20
+            LD F, V0
+            DRW V1, V2, 5
+";
+
+#[test]
+fn quirks_modern() {
+    // 008 007 000, and the 0 wraps to the left edge
+    assert_golden("quirks_modern", &RomTest::new(program(QUIRKS), 30));
+}
+
+#[test]
+fn quirks_cosmac_vip() {
+    // 001 000 033, and the 0 is cut
+    let mut test = RomTest::new(program(QUIRKS), 60);
+    test.quirks = Quirks::cosmac_vip();
+    assert_golden("quirks_cosmac_vip", &test);
+}
+
+#[test]
+fn keypad() {
+    // The keys held, 001 005 015, the 13 others, then 007 pressed after they are released
+    let rom = program(
+        "
+            LD V3, 0
+    pressed: LD VA, V3
+            SKNP V3
+            CALL show
+            ADD V3, 1
+            SE V3, 16
+            JP pressed
+            LD V3, 0
+            LD V7, 0
+    released: SKP V3
+            ADD V7, 1
+            ADD V3, 1
+            SE V3, 16
+            JP released
+            LD VA, V7
+            CALL show
+            LD V3, 1
+    held:   SKNP V3
+            JP held
+            LD VA, K
+            CALL show
+        ",
+    );
+    let mut test = RomTest::new(rom, 80);
+    test.inputs = vec![
+        (0, 1 << 0x1 | 1 << 0x5 | 1 << 0xF),
+        (40, 0),
+        (50, 1 << 0x7),
+        (55, 0),
+    ];
+    assert_golden("keypad", &test);
+}
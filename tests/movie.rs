@@ -0,0 +1,124 @@
+use chip8_core::assembler::assemble;
+use chip8_core::backend::Framebuffer;
+use chip8_core::cpu::Cpu;
+use chip8_core::memory::{Memory, SharedMemory};
+use chip8_core::movie::{MOVIE_MAGIC, MOVIE_VERSION, Movie};
+use chip8_core::quirks::Quirks;
+use chip8_core::rom::{load_rom_bytes, rom_hash};
+use chip8_core::save_state::StateError;
+use chip8_core::scheduler::{Scheduler, SchedulerTrait};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Draws the digit of every key pressed at a random height, next to the previous one
+const PROGRAM: &str = "
+    loop:   LD V0, K
+            LD F, V0
+            RND V2, 0x1F
+            DRW V1, V2, 5
+            ADD V1, 5
+            JP loop
+";
+const FRAMES: usize = 60;
+
+fn machine(program: &str, quirks: Quirks, seed: u64) -> Cpu {
+    let rom = assemble(program).unwrap().rom;
+    let memory: SharedMemory = Rc::new(RefCell::new(Memory::new()));
+    load_rom_bytes(&rom, Rc::clone(&memory)).unwrap();
+    let mut cpu = Cpu::new(memory);
+    cpu.set_rom_hash(rom_hash(&rom));
+    cpu.set_quirks(quirks);
+    cpu.set_seed(seed);
+    cpu
+}
+
+// Keys held for a few frames, with nothing pressed in between
+fn keys_of_frame(frame: usize) -> u16 {
+    match frame {
+        5..=7 => 1 << 0x5,
+        20..=21 => 1 << 0xA,
+        40..=44 => 1 << 0x1 | 1 << 0xC,
+        _ => 0,
+    }
+}
+
+fn record() -> (Movie, Framebuffer) {
+    let quirks = Quirks::from_name("vip").unwrap();
+    let mut cpu = machine(PROGRAM, quirks, 7);
+    let mut scheduler = Scheduler::new(600);
+    let mut movie = Movie::new(cpu.rom_hash(), quirks, 7, 600);
+    for frame in 0..FRAMES {
+        cpu.keyboard_mut().set_state(keys_of_frame(frame));
+        movie.record_frame(cpu.keyboard().state());
+        scheduler.run_frame(&mut cpu).unwrap();
+    }
+    (movie, Framebuffer::from_display(cpu.display()))
+}
+
+#[test]
+fn a_replay_draws_the_same_frame() {
+    let (movie, recorded) = record();
+    let data = movie.to_bytes();
+    assert_eq!(&data[..4], MOVIE_MAGIC);
+    assert_eq!(data[4..6], MOVIE_VERSION.to_le_bytes());
+
+    // Everything comes from the movie, like `--replay` does
+    let movie = Movie::from_bytes(&data).unwrap();
+    assert_eq!(movie.len(), FRAMES);
+    assert_eq!(movie.frame(41), Some(1 << 0x1 | 1 << 0xC));
+    assert_eq!(movie.frame(FRAMES), None);
+    let mut cpu = machine(PROGRAM, movie.quirks, movie.seed);
+    movie.check_rom(cpu.rom_hash()).unwrap();
+    let mut scheduler = Scheduler::new(movie.instructions_per_second);
+    let mut frame = 0;
+    while let Some(keys) = movie.frame(frame) {
+        cpu.keyboard_mut().set_state(keys);
+        scheduler.run_frame(&mut cpu).unwrap();
+        frame += 1;
+    }
+    let replayed = Framebuffer::from_display(cpu.display());
+    assert_eq!(replayed, recorded);
+    // The keys made it to the screen
+    let blank = Framebuffer::from_display(machine(PROGRAM, movie.quirks, 7).display());
+    assert_ne!(replayed, blank);
+}
+
+#[test]
+fn a_movie_of_another_rom_is_rejected() {
+    let (movie, _) = record();
+    let other = machine("JP 0x200", movie.quirks, movie.seed);
+    assert_eq!(
+        movie.check_rom(other.rom_hash()),
+        Err(StateError::WrongRom {
+            expected: other.rom_hash(),
+            found: movie.rom_hash,
+        })
+    );
+}
+
+#[test]
+fn broken_movies_are_rejected() {
+    let data = record().0.to_bytes();
+    assert_eq!(
+        Movie::from_bytes(&data[..data.len() - 1]).err(),
+        Some(StateError::InvalidFormat)
+    );
+    let mut extra = data.clone();
+    extra.push(0);
+    assert_eq!(
+        Movie::from_bytes(&extra).err(),
+        Some(StateError::InvalidFormat)
+    );
+    let mut version = data.clone();
+    version[4..6].copy_from_slice(&2u16.to_le_bytes());
+    assert_eq!(
+        Movie::from_bytes(&version).err(),
+        Some(StateError::UnsupportedVersion(2))
+    );
+    let mut magic = data;
+    magic[..4].copy_from_slice(b"C8SS");
+    assert_eq!(
+        Movie::from_bytes(&magic).err(),
+        Some(StateError::InvalidFormat)
+    );
+}
@@ -0,0 +1,220 @@
+use chip8_core::cpu::{Cpu, StepOutcome};
+use chip8_core::memory::{Memory, SharedMemory};
+use chip8_core::octo::compile;
+use chip8_core::quirks::Quirks;
+use chip8_core::rom::{load_rom, load_rom_bytes};
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+fn words(source: &str) -> Vec<u16> {
+    let rom = compile(source)
+        .unwrap_or_else(|e| panic!("{}\n{}", e, source))
+        .rom;
+    rom.chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+// Runs the program until it exits and returns the registers
+fn run(rom: &[u8]) -> [u8; 16] {
+    let quirks = Quirks::from_name("xochip").unwrap();
+    let memory: SharedMemory = Rc::new(RefCell::new(Memory::with_size(
+        quirks.platform.memory_size(),
+    )));
+    load_rom_bytes(rom, Rc::clone(&memory)).unwrap();
+    let mut cpu = Cpu::new(memory);
+    cpu.set_quirks(quirks);
+    for _ in 0..10_000 {
+        if cpu.step().unwrap() == StepOutcome::Exited {
+            return *cpu.registers();
+        }
+    }
+    panic!("The program never exited");
+}
+
+#[test]
+fn every_statement() {
+    let source = "
+        : main
+          clear  v0 := 5  v1 := v0  v2 += 3  v2 += v1  v3 -= 1  v3 -= v2  v3 =- v2
+          v4 |= v5  v4 &= v5  v4 ^= v5  v4 >>= v5  v4 <<= v5
+          v6 := random 0x0F  v7 := key  v7 := delay  delay := v7  buzzer := v7
+          i := hex v1  i := bighex v1  i += v2  bcd v3
+          save v4  load v4  save v1 - v2  load v1 - v2  saveflags v5  loadflags v5
+          sprite v0 v1 15  scroll-down 4  scroll-up 2  scroll-left  scroll-right
+          hires  lores  plane 3  audio  pitch := v1
+          jump0 0x300  native 0x123  exit  return  ;
+    ";
+    assert_eq!(
+        words(source),
+        vec![
+            0x1202, 0x00E0, 0x6005, 0x8100, 0x7203, 0x8214, 0x73FF, 0x8325, 0x8327, 0x8451, 0x8452,
+            0x8453, 0x8456, 0x845E, 0xC60F, 0xF70A, 0xF707, 0xF715, 0xF718, 0xF129, 0xF130, 0xF21E,
+            0xF333, 0xF455, 0xF465, 0x5122, 0x5123, 0xF575, 0xF585, 0xD01F, 0x00C4, 0x00D2, 0x00FC,
+            0x00FB, 0x00FF, 0x00FE, 0xF301, 0xF002, 0xF13A, 0xB300, 0x0123, 0x00FD, 0x00EE, 0x00EE,
+        ]
+    );
+}
+
+#[test]
+fn control_flow() {
+    let source = "
+        : main
+          loop
+            if v0 == 3 then v1 := 1
+            if v0 key then v2 := 1
+            if v0 -key then v2 := 2
+            if v0 != v1 begin
+              v3 := 1
+            else
+              v3 := 2
+            end
+            v0 += 1
+            while v0 < 10
+            v4 := v0
+          again
+    ";
+    assert_eq!(
+        words(source),
+        vec![
+            0x1202, 0x4003, 0x6101, 0xE0A1, 0x6201, 0xE09E, 0x6202, 0x9010, 0x1216, 0x6301, 0x1218,
+            0x6302, 0x7001, 0x6F0A, 0x8F07, 0x4F01, 0x1226, 0x8400, 0x1202,
+        ]
+    );
+}
+
+#[test]
+fn comparisons() {
+    let values = [0, 1, 9, 10, 11, 255];
+    for (operator, expected) in [
+        ("==", (|a, b| a == b) as fn(u8, u8) -> bool),
+        ("!=", |a, b| a != b),
+        ("<", |a, b| a < b),
+        (">", |a, b| a > b),
+        ("<=", |a, b| a <= b),
+        (">=", |a, b| a >= b),
+    ] {
+        for a in values {
+            for b in values {
+                for right in ["v2", &b.to_string()] {
+                    let source = format!(
+                        ": main v1 := {} v2 := {} v3 := 0 if v1 {} {} then v3 := 1 exit",
+                        a, b, operator, right
+                    );
+                    let registers = run(&compile(&source).unwrap().rom);
+                    assert_eq!(registers[3] == 1, expected(a, b), "{}", source);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn labels_aliases_constants_and_macros() {
+    let source = "
+        :alias counter v5
+        :const STEP 3
+        :calc DOUBLE { STEP * 2 + 1 }   # Right to left: 3 * 3
+        :macro add-to REGISTER AMOUNT { REGISTER += AMOUNT }
+        : main
+          counter := 0
+          add-to counter STEP
+          add-to counter DOUBLE
+          i := logo
+          draw-sprite
+          :unpack 0xA logo
+          exit
+        : draw-sprite
+          sprite v0 v0 3
+          return
+        : logo 0xF0 -1 0b10010000
+          :byte { HERE - logo }
+          :pointer logo
+        :next patched
+          v0 := 0
+        :org 0x240
+          :byte 7
+    ";
+    let assembly = compile(source).unwrap();
+    let mut expected = vec![
+        0x12, 0x02, 0x65, 0x00, 0x75, 0x03, 0x75, 0x09, 0xA2, 0x16, 0x22, 0x12, 0x60, 0xA2, 0x61,
+        0x16, 0x00, 0xFD, 0xD0, 0x03, 0x00, 0xEE, 0xF0, 0xFF, 0x90, 0x03, 0x02, 0x16, 0x60, 0x00,
+    ];
+    expected.resize(0x40, 0x00);
+    expected.push(0x07);
+    assert_eq!(assembly.rom, expected);
+    assert_eq!(assembly.symbols["DOUBLE"], 9);
+    assert_eq!(assembly.symbols["patched"], 0x21D);
+    assert_eq!(run(&assembly.rom)[5], 12);
+}
+
+#[test]
+fn long_addresses() {
+    let source = ": main i := long data exit :org 0x2000 : data 1 2";
+    assert_eq!(&words(source)[..4], [0x1202, 0xF000, 0x2000, 0x00FD]);
+    assert!(compile(": main jump data :org 0x2000 : data 1").is_err());
+}
+
+#[test]
+fn errors_point_to_the_line() {
+    for (source, line, message) in [
+        (": main\njump nowhere", 2, "Unknown label: nowhere"),
+        ("v0 := 1", 1, "The program has no main label"),
+        (": main\n\nv0 := 256", 3, "A byte can't be 256"),
+        (": main\nsprite v0 v1 16", 2, "A nibble can't be 16"),
+        (": main\n: main", 2, "main is already defined"),
+        (": main\n: loop", 2, "Invalid name: loop"),
+        (": main\nv0 += i", 2, "Unknown value: i"),
+        (": main\nloop\nv0 += 1", 3, "A loop has no again"),
+        (": main\nif v0 == 1 begin", 2, "A begin has no end"),
+        (": main\nwhile v0 == 1", 2, "while outside of a loop"),
+        (
+            ": main\nif vf < v1 then",
+            2,
+            "VF can't be compared with <, >, <= or >=",
+        ),
+        (
+            ":macro forever { forever }\n: main forever",
+            2,
+            "forever expands itself",
+        ),
+        (": main\n:calc X { 1 / 0 }", 2, "Division by zero"),
+        (
+            ": main\n:calc X { @ 0x100 }",
+            2,
+            "Nothing is compiled at 0x100",
+        ),
+        (
+            ": main\n:calc X { @ ( 0 - 4294967296 * 4294967296 * 4294967296 ) }",
+            2,
+            "Nothing is compiled at 0x8000000000000000",
+        ),
+    ] {
+        let error = compile(source).err().unwrap();
+        assert_eq!(
+            (error.line, error.message.as_str()),
+            (line, message),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn octo_sources_are_loaded_as_roms() {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("loaded.8o");
+    fs::write(&path, ": main\n  v0 := 1\n  exit\n").unwrap();
+    let memory: SharedMemory = Rc::new(RefCell::new(Memory::new()));
+    let rom = load_rom(path.to_str().unwrap(), memory).unwrap();
+    assert_eq!(rom, vec![0x12, 0x02, 0x60, 0x01, 0x00, 0xFD]);
+
+    fs::write(&path, ": main\n  jump nowhere\n").unwrap();
+    let memory: SharedMemory = Rc::new(RefCell::new(Memory::new()));
+    let error = load_rom(path.to_str().unwrap(), memory).unwrap_err();
+    assert_eq!(
+        error,
+        format!("{}:2: Unknown label: nowhere", path.display())
+    );
+}
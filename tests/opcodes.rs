@@ -0,0 +1,1172 @@
+use chip8_core::cpu::{Cpu, CpuError, StepOutcome};
+use chip8_core::display::DisplayTrait;
+use chip8_core::memory::{FONT_START_ADDRESS, Memory, MemoryTrait};
+use chip8_core::quirks::Quirks;
+use chip8_core::random::ScriptedRandom;
+use chip8_core::stack::StackTrait;
+use chip8_core::timers::TimersTrait;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// The names given to `Quirks::from_name`
+const ALL: &[&str] = &["modern", "vip", "chip48", "schip", "xochip"];
+const CHIP8: &[&str] = &["modern", "vip", "chip48"];
+const SUPER_CHIP: &[&str] = &["schip", "xochip"];
+const XO_CHIP: &[&str] = &["xochip"];
+
+// Every instruction is tested from here
+const START: usize = 0x200;
+// Where the instructions of `Set::Run` are executed from
+const SCRATCH: usize = 0x400;
+// What CXNN always gets
+const RANDOM_BYTE: u8 = 0xAB;
+
+// The machine before the instruction, every register starts as 0
+enum Set {
+    Register(usize, u8),
+    I(usize),
+    Memory(usize, &'static [u8]),
+    Stack(&'static [u16]),
+    // Each key is a bit, like `Keyboard::set_state`
+    Keys(u16),
+    DelayTimer(u8),
+    // The first row of the font 0, 4 pixels starting on the given one
+    Sprite(usize, usize),
+    Hires(bool),
+    RplFlags(&'static [u8]),
+    // Instructions executed before, for the state only reachable through them
+    Run(&'static [u16]),
+}
+
+// The machine after the instruction. Registers, I and the stack not listed must be the same as
+// before, the program counter must be on the next instruction and the step must succeed
+enum Check {
+    Register(usize, u8),
+    I(usize),
+    Pc(usize),
+    Stack(&'static [u16]),
+    Memory(usize, &'static [u8]),
+    Pixel(usize, usize, bool),
+    DelayTimer(u8),
+    SoundTimer(u8),
+    Hires(bool),
+    Planes(u8),
+    RplFlags(&'static [u8]),
+    AudioPattern(&'static [u8]),
+    Pitch(u8),
+    Outcome(Result<StepOutcome, CpuError>),
+    // What the step after this one returns
+    NextOutcome(Result<StepOutcome, CpuError>),
+}
+
+struct Case {
+    name: &'static str,
+    opcode: u16,
+    profiles: &'static [&'static str],
+    set: &'static [Set],
+    check: &'static [Check],
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "00E0 clears the screen",
+        opcode: 0x00E0,
+        profiles: ALL,
+        set: &[Set::Sprite(0, 0)],
+        check: &[Check::Pixel(0, 0, false)],
+    },
+    Case {
+        name: "00EE returns to the top of the stack",
+        opcode: 0x00EE,
+        profiles: ALL,
+        set: &[Set::Stack(&[0x300, 0x340])],
+        check: &[Check::Pc(0x340), Check::Stack(&[0x300])],
+    },
+    Case {
+        name: "00EE with an empty stack",
+        opcode: 0x00EE,
+        profiles: ALL,
+        set: &[],
+        check: &[Check::Outcome(Err(CpuError::StackUnderflow {
+            program_counter: START,
+        }))],
+    },
+    Case {
+        name: "0NNN is ignored",
+        opcode: 0x0123,
+        profiles: ALL,
+        set: &[],
+        check: &[],
+    },
+    Case {
+        name: "1NNN jumps",
+        opcode: 0x1345,
+        profiles: ALL,
+        set: &[],
+        check: &[Check::Pc(0x345)],
+    },
+    Case {
+        name: "2NNN pushes the next instruction",
+        opcode: 0x2345,
+        profiles: ALL,
+        set: &[],
+        check: &[Check::Pc(0x345), Check::Stack(&[0x202])],
+    },
+    Case {
+        name: "2NNN with a full stack",
+        opcode: 0x2345,
+        profiles: ALL,
+        set: &[Set::Stack(&[0x300; 50])],
+        check: &[Check::Outcome(Err(CpuError::StackOverflow {
+            program_counter: START,
+        }))],
+    },
+    Case {
+        name: "3XNN skips when equal",
+        opcode: 0x3142,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x42)],
+        check: &[Check::Pc(0x204)],
+    },
+    Case {
+        name: "3XNN doesn't skip when different",
+        opcode: 0x3142,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x41)],
+        check: &[],
+    },
+    Case {
+        name: "3XNN skips the whole F000 NNNN",
+        opcode: 0x3142,
+        profiles: XO_CHIP,
+        set: &[Set::Register(1, 0x42), Set::Memory(0x202, &[0xF0, 0x00])],
+        check: &[Check::Pc(0x206)],
+    },
+    Case {
+        name: "3XNN skips only two bytes of F000",
+        opcode: 0x3142,
+        profiles: &["modern", "vip", "chip48", "schip"],
+        set: &[Set::Register(1, 0x42), Set::Memory(0x202, &[0xF0, 0x00])],
+        check: &[Check::Pc(0x204)],
+    },
+    Case {
+        name: "4XNN skips when different",
+        opcode: 0x4142,
+        profiles: ALL,
+        set: &[],
+        check: &[Check::Pc(0x204)],
+    },
+    Case {
+        name: "4XNN doesn't skip when equal",
+        opcode: 0x4142,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x42)],
+        check: &[],
+    },
+    Case {
+        name: "5XY0 skips when equal",
+        opcode: 0x5120,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x07), Set::Register(2, 0x07)],
+        check: &[Check::Pc(0x204)],
+    },
+    Case {
+        name: "5XY0 doesn't skip when different",
+        opcode: 0x5120,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x07)],
+        check: &[],
+    },
+    Case {
+        name: "6XNN sets VX",
+        opcode: 0x6142,
+        profiles: ALL,
+        set: &[],
+        check: &[Check::Register(1, 0x42)],
+    },
+    Case {
+        name: "7XNN adds to VX",
+        opcode: 0x7105,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x03)],
+        check: &[Check::Register(1, 0x08)],
+    },
+    Case {
+        name: "7XNN wraps around without touching VF",
+        opcode: 0x7102,
+        profiles: ALL,
+        set: &[Set::Register(1, 0xFF), Set::Register(0xF, 0x05)],
+        check: &[Check::Register(1, 0x01)],
+    },
+    Case {
+        name: "8XY0 copies VY",
+        opcode: 0x8120,
+        profiles: ALL,
+        set: &[Set::Register(2, 0x09)],
+        check: &[Check::Register(1, 0x09)],
+    },
+    Case {
+        name: "8XY1 ORs and resets VF",
+        opcode: 0x8121,
+        profiles: &["vip"],
+        set: &[
+            Set::Register(1, 0x0C),
+            Set::Register(2, 0x0A),
+            Set::Register(0xF, 0x05),
+        ],
+        check: &[Check::Register(1, 0x0E), Check::Register(0xF, 0x00)],
+    },
+    Case {
+        name: "8XY1 ORs",
+        opcode: 0x8121,
+        profiles: &["modern", "chip48", "schip", "xochip"],
+        set: &[
+            Set::Register(1, 0x0C),
+            Set::Register(2, 0x0A),
+            Set::Register(0xF, 0x05),
+        ],
+        check: &[Check::Register(1, 0x0E)],
+    },
+    Case {
+        name: "8XY2 ANDs and resets VF",
+        opcode: 0x8122,
+        profiles: &["vip"],
+        set: &[
+            Set::Register(1, 0x0C),
+            Set::Register(2, 0x0A),
+            Set::Register(0xF, 0x05),
+        ],
+        check: &[Check::Register(1, 0x08), Check::Register(0xF, 0x00)],
+    },
+    Case {
+        name: "8XY2 ANDs",
+        opcode: 0x8122,
+        profiles: &["modern", "chip48", "schip", "xochip"],
+        set: &[
+            Set::Register(1, 0x0C),
+            Set::Register(2, 0x0A),
+            Set::Register(0xF, 0x05),
+        ],
+        check: &[Check::Register(1, 0x08)],
+    },
+    Case {
+        name: "8XY3 XORs and resets VF",
+        opcode: 0x8123,
+        profiles: &["vip"],
+        set: &[
+            Set::Register(1, 0x0C),
+            Set::Register(2, 0x0A),
+            Set::Register(0xF, 0x05),
+        ],
+        check: &[Check::Register(1, 0x06), Check::Register(0xF, 0x00)],
+    },
+    Case {
+        name: "8XY3 XORs",
+        opcode: 0x8123,
+        profiles: &["modern", "chip48", "schip", "xochip"],
+        set: &[
+            Set::Register(1, 0x0C),
+            Set::Register(2, 0x0A),
+            Set::Register(0xF, 0x05),
+        ],
+        check: &[Check::Register(1, 0x06)],
+    },
+    Case {
+        name: "8XY4 without carry",
+        opcode: 0x8124,
+        profiles: ALL,
+        set: &[
+            Set::Register(1, 0x10),
+            Set::Register(2, 0x20),
+            Set::Register(0xF, 0x05),
+        ],
+        check: &[Check::Register(1, 0x30), Check::Register(0xF, 0x00)],
+    },
+    Case {
+        name: "8XY4 overflows with carry",
+        opcode: 0x8124,
+        profiles: ALL,
+        set: &[Set::Register(1, 0xFF), Set::Register(2, 0x02)],
+        check: &[Check::Register(1, 0x01), Check::Register(0xF, 0x01)],
+    },
+    Case {
+        name: "8XY4 on VF keeps the carry",
+        opcode: 0x8F14,
+        profiles: ALL,
+        set: &[Set::Register(0xF, 0x80), Set::Register(1, 0x10)],
+        check: &[Check::Register(0xF, 0x00)],
+    },
+    Case {
+        name: "8XY5 without borrow",
+        opcode: 0x8125,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x30), Set::Register(2, 0x10)],
+        check: &[Check::Register(1, 0x20), Check::Register(0xF, 0x01)],
+    },
+    Case {
+        name: "8XY5 with borrow",
+        opcode: 0x8125,
+        profiles: ALL,
+        set: &[
+            Set::Register(1, 0x10),
+            Set::Register(2, 0x30),
+            Set::Register(0xF, 0x05),
+        ],
+        check: &[Check::Register(1, 0xE0), Check::Register(0xF, 0x00)],
+    },
+    Case {
+        name: "8XY5 of equal registers has no borrow",
+        opcode: 0x8125,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x10), Set::Register(2, 0x10)],
+        check: &[Check::Register(1, 0x00), Check::Register(0xF, 0x01)],
+    },
+    Case {
+        name: "8XY5 on VF keeps the borrow",
+        opcode: 0x8F15,
+        profiles: ALL,
+        set: &[Set::Register(0xF, 0x30), Set::Register(1, 0x10)],
+        check: &[Check::Register(0xF, 0x01)],
+    },
+    Case {
+        name: "8XY6 shifts VY",
+        opcode: 0x8126,
+        profiles: &["vip", "xochip"],
+        set: &[Set::Register(1, 0x04), Set::Register(2, 0x03)],
+        check: &[Check::Register(1, 0x01), Check::Register(0xF, 0x01)],
+    },
+    Case {
+        name: "8XY6 shifts VX",
+        opcode: 0x8126,
+        profiles: &["modern", "chip48", "schip"],
+        set: &[
+            Set::Register(1, 0x04),
+            Set::Register(2, 0x03),
+            Set::Register(0xF, 0x05),
+        ],
+        check: &[Check::Register(1, 0x02), Check::Register(0xF, 0x00)],
+    },
+    Case {
+        name: "8XY6 of VY on VF keeps the shifted bit",
+        opcode: 0x8F16,
+        profiles: &["vip", "xochip"],
+        set: &[Set::Register(0xF, 0x05), Set::Register(1, 0x02)],
+        check: &[Check::Register(0xF, 0x00)],
+    },
+    Case {
+        name: "8XY6 of VX on VF keeps the shifted bit",
+        opcode: 0x8F16,
+        profiles: &["modern", "chip48", "schip"],
+        set: &[Set::Register(0xF, 0x05), Set::Register(1, 0x02)],
+        check: &[Check::Register(0xF, 0x01)],
+    },
+    Case {
+        name: "8XY7 without borrow",
+        opcode: 0x8127,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x10), Set::Register(2, 0x30)],
+        check: &[Check::Register(1, 0x20), Check::Register(0xF, 0x01)],
+    },
+    Case {
+        name: "8XY7 with borrow",
+        opcode: 0x8127,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x30), Set::Register(2, 0x10)],
+        check: &[Check::Register(1, 0xE0), Check::Register(0xF, 0x00)],
+    },
+    Case {
+        name: "8XY7 on VF keeps the borrow",
+        opcode: 0x8F17,
+        profiles: ALL,
+        set: &[Set::Register(0xF, 0x10), Set::Register(1, 0x30)],
+        check: &[Check::Register(0xF, 0x01)],
+    },
+    Case {
+        name: "8XYE shifts VY",
+        opcode: 0x812E,
+        profiles: &["vip", "xochip"],
+        set: &[Set::Register(1, 0x40), Set::Register(2, 0x81)],
+        check: &[Check::Register(1, 0x02), Check::Register(0xF, 0x01)],
+    },
+    Case {
+        name: "8XYE shifts VX",
+        opcode: 0x812E,
+        profiles: &["modern", "chip48", "schip"],
+        set: &[
+            Set::Register(1, 0x40),
+            Set::Register(2, 0x81),
+            Set::Register(0xF, 0x05),
+        ],
+        check: &[Check::Register(1, 0x80), Check::Register(0xF, 0x00)],
+    },
+    Case {
+        name: "8XYE of VY on VF keeps the shifted bit",
+        opcode: 0x8F1E,
+        profiles: &["vip", "xochip"],
+        set: &[Set::Register(0xF, 0x81), Set::Register(1, 0x40)],
+        check: &[Check::Register(0xF, 0x00)],
+    },
+    Case {
+        name: "8XYE of VX on VF keeps the shifted bit",
+        opcode: 0x8F1E,
+        profiles: &["modern", "chip48", "schip"],
+        set: &[Set::Register(0xF, 0x81), Set::Register(1, 0x40)],
+        check: &[Check::Register(0xF, 0x01)],
+    },
+    Case {
+        name: "9XY0 skips when different",
+        opcode: 0x9120,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x01), Set::Register(2, 0x02)],
+        check: &[Check::Pc(0x204)],
+    },
+    Case {
+        name: "9XY0 doesn't skip when equal",
+        opcode: 0x9120,
+        profiles: ALL,
+        set: &[],
+        check: &[],
+    },
+    Case {
+        name: "ANNN sets I",
+        opcode: 0xA345,
+        profiles: ALL,
+        set: &[],
+        check: &[Check::I(0x345)],
+    },
+    Case {
+        name: "BNNN jumps to NNN + V0",
+        opcode: 0xB234,
+        profiles: &["modern", "vip", "xochip"],
+        set: &[Set::Register(0, 0x10), Set::Register(2, 0x20)],
+        check: &[Check::Pc(0x244)],
+    },
+    Case {
+        name: "BXNN jumps to XNN + VX",
+        opcode: 0xB234,
+        profiles: &["chip48", "schip"],
+        set: &[Set::Register(0, 0x10), Set::Register(2, 0x20)],
+        check: &[Check::Pc(0x254)],
+    },
+    Case {
+        name: "CXNN masks the random byte",
+        opcode: 0xC10F,
+        profiles: ALL,
+        set: &[],
+        check: &[Check::Register(1, RANDOM_BYTE & 0x0F)],
+    },
+    Case {
+        name: "DXYN draws a sprite",
+        opcode: 0xD125,
+        profiles: ALL,
+        set: &[
+            Set::Register(1, 2),
+            Set::Register(2, 3),
+            Set::Register(0xF, 0x05),
+            Set::I(FONT_START_ADDRESS),
+        ],
+        check: &[
+            Check::Register(0xF, 0x00),
+            Check::Pixel(2, 3, true),
+            Check::Pixel(5, 3, true),
+            Check::Pixel(6, 3, false),
+            Check::Pixel(3, 4, false),
+            Check::Pixel(5, 7, true),
+        ],
+    },
+    Case {
+        name: "DXYN reports collisions",
+        opcode: 0xD121,
+        profiles: ALL,
+        set: &[
+            Set::Register(1, 2),
+            Set::Register(2, 3),
+            Set::I(FONT_START_ADDRESS),
+            Set::Sprite(2, 3),
+        ],
+        check: &[Check::Register(0xF, 0x01), Check::Pixel(2, 3, false)],
+    },
+    Case {
+        name: "DXYN clips sprites on the edge",
+        opcode: 0xD121,
+        profiles: &["vip", "chip48", "schip"],
+        set: &[Set::Register(1, 62), Set::I(FONT_START_ADDRESS)],
+        check: &[Check::Pixel(63, 0, true), Check::Pixel(0, 0, false)],
+    },
+    Case {
+        name: "DXYN wraps sprites on the edge",
+        opcode: 0xD121,
+        profiles: &["modern", "xochip"],
+        set: &[Set::Register(1, 62), Set::I(FONT_START_ADDRESS)],
+        check: &[Check::Pixel(63, 0, true), Check::Pixel(1, 0, true)],
+    },
+    Case {
+        name: "DXYN wraps the starting position",
+        opcode: 0xD121,
+        profiles: ALL,
+        set: &[
+            Set::Register(1, 66),
+            Set::Register(2, 33),
+            Set::I(FONT_START_ADDRESS),
+        ],
+        check: &[Check::Pixel(2, 1, true)],
+    },
+    Case {
+        name: "DXYN waits for the vertical blank",
+        opcode: 0xD121,
+        profiles: &["vip"],
+        set: &[Set::I(FONT_START_ADDRESS)],
+        check: &[Check::NextOutcome(Ok(StepOutcome::WaitingForVerticalBlank))],
+    },
+    Case {
+        name: "DXYN doesn't wait for the vertical blank",
+        opcode: 0xD121,
+        profiles: &["modern", "chip48", "schip", "xochip"],
+        set: &[Set::I(FONT_START_ADDRESS)],
+        check: &[Check::NextOutcome(Ok(StepOutcome::Executed(0x0000)))],
+    },
+    Case {
+        name: "DXY0 draws a 16x16 sprite",
+        opcode: 0xD120,
+        profiles: SUPER_CHIP,
+        set: &[Set::I(0x300), Set::Memory(0x300, &[0xFF; 32])],
+        check: &[
+            Check::Pixel(0, 0, true),
+            Check::Pixel(15, 15, true),
+            Check::Pixel(16, 0, false),
+        ],
+    },
+    Case {
+        name: "DXY0 draws nothing",
+        opcode: 0xD120,
+        profiles: CHIP8,
+        set: &[Set::I(0x300), Set::Memory(0x300, &[0xFF; 32])],
+        check: &[Check::Pixel(0, 0, false)],
+    },
+    Case {
+        name: "EX9E skips when the key is pressed",
+        opcode: 0xE19E,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x0A), Set::Keys(1 << 0xA)],
+        check: &[Check::Pc(0x204)],
+    },
+    Case {
+        name: "EX9E doesn't skip when the key is released",
+        opcode: 0xE19E,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x0A), Set::Keys(1 << 0xB)],
+        check: &[],
+    },
+    Case {
+        name: "EXA1 skips when the key is released",
+        opcode: 0xE1A1,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x0A)],
+        check: &[Check::Pc(0x204)],
+    },
+    Case {
+        name: "EXA1 doesn't skip when the key is pressed",
+        opcode: 0xE1A1,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x0A), Set::Keys(1 << 0xA)],
+        check: &[],
+    },
+    Case {
+        name: "FX07 reads the delay timer",
+        opcode: 0xF107,
+        profiles: ALL,
+        set: &[Set::DelayTimer(0x33)],
+        check: &[Check::Register(1, 0x33), Check::DelayTimer(0x33)],
+    },
+    Case {
+        name: "FX0A waits for a key",
+        opcode: 0xF10A,
+        profiles: ALL,
+        set: &[],
+        check: &[
+            Check::Outcome(Ok(StepOutcome::WaitingForKey)),
+            Check::Pc(START),
+        ],
+    },
+    Case {
+        name: "FX0A stores the lowest key pressed",
+        opcode: 0xF10A,
+        profiles: ALL,
+        set: &[Set::Keys((1 << 0x7) | (1 << 0xC))],
+        check: &[Check::Register(1, 0x07)],
+    },
+    Case {
+        name: "FX15 sets the delay timer",
+        opcode: 0xF115,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x20)],
+        check: &[Check::DelayTimer(0x20)],
+    },
+    Case {
+        name: "FX18 sets the sound timer",
+        opcode: 0xF118,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x20)],
+        check: &[Check::SoundTimer(0x20)],
+    },
+    Case {
+        name: "FX1E adds to I",
+        opcode: 0xF11E,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x10), Set::I(0x300)],
+        check: &[Check::I(0x310)],
+    },
+    Case {
+        name: "FX1E wraps I to 12 bits",
+        opcode: 0xF11E,
+        profiles: &["modern", "vip", "chip48", "schip"],
+        set: &[Set::Register(1, 0x02), Set::I(0xFFF)],
+        check: &[Check::I(0x001)],
+    },
+    Case {
+        name: "FX1E goes past 12 bits",
+        opcode: 0xF11E,
+        profiles: XO_CHIP,
+        set: &[Set::Register(1, 0x02), Set::I(0xFFF)],
+        check: &[Check::I(0x1001)],
+    },
+    Case {
+        name: "FX29 points I to the font",
+        opcode: 0xF129,
+        profiles: ALL,
+        set: &[Set::Register(1, 0x0A)],
+        check: &[Check::I(FONT_START_ADDRESS + 0x0A * 5)],
+    },
+    Case {
+        name: "FX33 stores the decimal digits",
+        opcode: 0xF133,
+        profiles: ALL,
+        set: &[Set::Register(1, 254), Set::I(0x300)],
+        check: &[Check::Memory(0x300, &[2, 5, 4])],
+    },
+    Case {
+        name: "FX55 stores without moving I",
+        opcode: 0xF255,
+        profiles: &["modern", "schip"],
+        set: &[
+            Set::Register(0, 1),
+            Set::Register(1, 2),
+            Set::Register(2, 3),
+            Set::Register(3, 4),
+            Set::I(0x300),
+        ],
+        check: &[Check::Memory(0x300, &[1, 2, 3, 0]), Check::I(0x300)],
+    },
+    Case {
+        name: "FX55 stores and moves I to X",
+        opcode: 0xF255,
+        profiles: &["chip48"],
+        set: &[
+            Set::Register(0, 1),
+            Set::Register(1, 2),
+            Set::Register(2, 3),
+            Set::I(0x300),
+        ],
+        check: &[Check::Memory(0x300, &[1, 2, 3]), Check::I(0x302)],
+    },
+    Case {
+        name: "FX55 stores and moves I past X",
+        opcode: 0xF255,
+        profiles: &["vip", "xochip"],
+        set: &[
+            Set::Register(0, 1),
+            Set::Register(1, 2),
+            Set::Register(2, 3),
+            Set::I(0x300),
+        ],
+        check: &[Check::Memory(0x300, &[1, 2, 3]), Check::I(0x303)],
+    },
+    Case {
+        name: "FX65 loads without moving I",
+        opcode: 0xF265,
+        profiles: &["modern", "schip"],
+        set: &[Set::Memory(0x300, &[4, 5, 6, 7]), Set::I(0x300)],
+        check: &[
+            Check::Register(0, 4),
+            Check::Register(1, 5),
+            Check::Register(2, 6),
+            Check::I(0x300),
+        ],
+    },
+    Case {
+        name: "FX65 loads and moves I to X",
+        opcode: 0xF265,
+        profiles: &["chip48"],
+        set: &[Set::Memory(0x300, &[4, 5, 6]), Set::I(0x300)],
+        check: &[
+            Check::Register(0, 4),
+            Check::Register(1, 5),
+            Check::Register(2, 6),
+            Check::I(0x302),
+        ],
+    },
+    Case {
+        name: "FX65 loads and moves I past X",
+        opcode: 0xF265,
+        profiles: &["vip", "xochip"],
+        set: &[Set::Memory(0x300, &[4, 5, 6]), Set::I(0x300)],
+        check: &[
+            Check::Register(0, 4),
+            Check::Register(1, 5),
+            Check::Register(2, 6),
+            Check::I(0x303),
+        ],
+    },
+    Case {
+        name: "00CN scrolls down",
+        opcode: 0x00C2,
+        profiles: SUPER_CHIP,
+        set: &[Set::Sprite(0, 0)],
+        check: &[Check::Pixel(0, 0, false), Check::Pixel(0, 2, true)],
+    },
+    Case {
+        name: "00CN is ignored",
+        opcode: 0x00C2,
+        profiles: CHIP8,
+        set: &[Set::Sprite(0, 0)],
+        check: &[Check::Pixel(0, 0, true), Check::Pixel(0, 2, false)],
+    },
+    Case {
+        name: "00FB scrolls right",
+        opcode: 0x00FB,
+        profiles: SUPER_CHIP,
+        set: &[Set::Sprite(0, 0)],
+        check: &[
+            Check::Pixel(3, 0, false),
+            Check::Pixel(4, 0, true),
+            Check::Pixel(7, 0, true),
+        ],
+    },
+    Case {
+        name: "00FC scrolls left",
+        opcode: 0x00FC,
+        profiles: SUPER_CHIP,
+        set: &[Set::Sprite(4, 0)],
+        check: &[
+            Check::Pixel(0, 0, true),
+            Check::Pixel(3, 0, true),
+            Check::Pixel(4, 0, false),
+        ],
+    },
+    Case {
+        name: "00FD exits",
+        opcode: 0x00FD,
+        profiles: SUPER_CHIP,
+        set: &[],
+        check: &[
+            Check::Outcome(Ok(StepOutcome::Exited)),
+            Check::NextOutcome(Ok(StepOutcome::Exited)),
+        ],
+    },
+    Case {
+        name: "00FE switches to lores",
+        opcode: 0x00FE,
+        profiles: SUPER_CHIP,
+        set: &[Set::Hires(true)],
+        check: &[Check::Hires(false)],
+    },
+    Case {
+        name: "00FF switches to hires",
+        opcode: 0x00FF,
+        profiles: SUPER_CHIP,
+        set: &[Set::Sprite(0, 0)],
+        check: &[Check::Hires(true), Check::Pixel(0, 0, false)],
+    },
+    Case {
+        name: "00FF is ignored",
+        opcode: 0x00FF,
+        profiles: CHIP8,
+        set: &[],
+        check: &[Check::Hires(false)],
+    },
+    Case {
+        name: "FX30 points I to the big font",
+        opcode: 0xF130,
+        profiles: SUPER_CHIP,
+        set: &[Set::Register(1, 0x02)],
+        check: &[Check::I(0xA0 + 2 * 10)],
+    },
+    Case {
+        name: "FX30 is unknown",
+        opcode: 0xF130,
+        profiles: CHIP8,
+        set: &[],
+        check: &[
+            Check::Outcome(Err(CpuError::UnknownOpcode {
+                opcode: 0xF130,
+                program_counter: START,
+            })),
+            Check::Pc(START),
+        ],
+    },
+    Case {
+        name: "FX75 stores the RPL flags",
+        opcode: 0xF275,
+        profiles: SUPER_CHIP,
+        set: &[
+            Set::Register(0, 1),
+            Set::Register(1, 2),
+            Set::Register(2, 3),
+            Set::Register(3, 4),
+        ],
+        check: &[Check::RplFlags(&[1, 2, 3, 0])],
+    },
+    Case {
+        name: "FX85 loads the RPL flags",
+        opcode: 0xF285,
+        profiles: SUPER_CHIP,
+        set: &[Set::RplFlags(&[4, 5, 6, 7])],
+        check: &[
+            Check::Register(0, 4),
+            Check::Register(1, 5),
+            Check::Register(2, 6),
+        ],
+    },
+    Case {
+        name: "FX75 only has 8 RPL flags on the SUPER-CHIP",
+        opcode: 0xF875,
+        profiles: &["schip"],
+        set: &[],
+        check: &[Check::Outcome(Err(CpuError::RplFlagsOutOfRange {
+            x: 8,
+            program_counter: START,
+        }))],
+    },
+    Case {
+        name: "FX85 only has 8 RPL flags on the SUPER-CHIP",
+        opcode: 0xFF85,
+        profiles: &["schip"],
+        set: &[],
+        check: &[Check::Outcome(Err(CpuError::RplFlagsOutOfRange {
+            x: 15,
+            program_counter: START,
+        }))],
+    },
+    Case {
+        name: "FX75 stores 16 RPL flags on the XO-CHIP",
+        opcode: 0xFF75,
+        profiles: XO_CHIP,
+        set: &[Set::Register(15, 9)],
+        check: &[Check::RplFlags(&[
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9,
+        ])],
+    },
+    Case {
+        name: "00DN scrolls up",
+        opcode: 0x00D1,
+        profiles: XO_CHIP,
+        set: &[Set::Sprite(0, 1)],
+        check: &[Check::Pixel(0, 0, true), Check::Pixel(0, 1, false)],
+    },
+    Case {
+        name: "5XY2 stores a range of registers",
+        opcode: 0x5132,
+        profiles: XO_CHIP,
+        set: &[
+            Set::Register(1, 1),
+            Set::Register(2, 2),
+            Set::Register(3, 3),
+            Set::I(0x300),
+        ],
+        check: &[Check::Memory(0x300, &[1, 2, 3])],
+    },
+    Case {
+        name: "5XY2 stores a range of registers backwards",
+        opcode: 0x5312,
+        profiles: XO_CHIP,
+        set: &[
+            Set::Register(1, 1),
+            Set::Register(2, 2),
+            Set::Register(3, 3),
+            Set::I(0x300),
+        ],
+        check: &[Check::Memory(0x300, &[3, 2, 1])],
+    },
+    Case {
+        name: "5XY2 is unknown",
+        opcode: 0x5132,
+        profiles: &["modern", "vip", "chip48", "schip"],
+        set: &[],
+        check: &[
+            Check::Outcome(Err(CpuError::UnknownOpcode {
+                opcode: 0x5132,
+                program_counter: START,
+            })),
+            Check::Pc(START),
+        ],
+    },
+    Case {
+        name: "5XY3 loads a range of registers",
+        opcode: 0x5133,
+        profiles: XO_CHIP,
+        set: &[Set::Memory(0x300, &[7, 8, 9]), Set::I(0x300)],
+        check: &[
+            Check::Register(1, 7),
+            Check::Register(2, 8),
+            Check::Register(3, 9),
+        ],
+    },
+    Case {
+        name: "F000 NNNN loads a 16 bits address",
+        opcode: 0xF000,
+        profiles: XO_CHIP,
+        set: &[Set::Memory(0x202, &[0x12, 0x34])],
+        check: &[Check::I(0x1234), Check::Pc(0x204)],
+    },
+    Case {
+        name: "FN01 selects the planes",
+        opcode: 0xF301,
+        profiles: XO_CHIP,
+        set: &[],
+        check: &[Check::Planes(0b11)],
+    },
+    Case {
+        name: "F002 loads the audio pattern",
+        opcode: 0xF002,
+        profiles: XO_CHIP,
+        set: &[
+            Set::Memory(
+                0x300,
+                &[0x0F, 0xF0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF],
+            ),
+            Set::I(0x300),
+        ],
+        check: &[
+            Check::AudioPattern(&[0x0F, 0xF0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF]),
+            Check::Pitch(64),
+        ],
+    },
+    Case {
+        name: "FX3A sets the pitch",
+        opcode: 0xF13A,
+        profiles: XO_CHIP,
+        set: &[Set::I(0x300), Set::Run(&[0xF002]), Set::Register(1, 112)],
+        check: &[Check::Pitch(112)],
+    },
+];
+
+// Stands in for the memory and keeps the addresses written, so a test sees every write of the
+// instruction and not only the ones it checks
+struct MockBus {
+    bytes: Vec<u8>,
+    writes: Vec<usize>,
+}
+
+impl MockBus {
+    // Starts as the real memory does, with the fonts in place
+    fn new(size: usize) -> MockBus {
+        let memory = Memory::with_size(size);
+        MockBus {
+            bytes: (0..size).map(|position| memory.peek(position)).collect(),
+            writes: Vec::new(),
+        }
+    }
+}
+
+impl MemoryTrait for MockBus {
+    fn write(&mut self, position: usize, value: u8) -> bool {
+        if !(START..self.bytes.len()).contains(&position) {
+            return false;
+        }
+        self.bytes[position] = value;
+        self.writes.push(position);
+        true
+    }
+
+    fn retrieve(&self, position: usize) -> u8 {
+        self.peek(position)
+    }
+
+    fn peek(&self, position: usize) -> u8 {
+        self.bytes.get(position).copied().unwrap_or(0x00)
+    }
+
+    fn size(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+type SharedBus = Rc<RefCell<MockBus>>;
+
+fn write_memory(memory: &SharedBus, address: usize, bytes: &[u8]) {
+    for (offset, &byte) in bytes.iter().enumerate() {
+        assert!(memory.borrow_mut().write(address + offset, byte));
+    }
+}
+
+fn opcode_bytes(opcode: u16) -> [u8; 2] {
+    opcode.to_be_bytes()
+}
+
+fn set_up(cpu: &mut Cpu<MockBus>, memory: &SharedBus, set: &Set) {
+    match *set {
+        Set::Register(index, value) => cpu.set_register(index, value),
+        Set::I(address) => cpu.set_i_register(address),
+        Set::Memory(address, bytes) => write_memory(memory, address, bytes),
+        Set::Stack(addresses) => {
+            for &address in addresses {
+                cpu.stack_mut().push(address);
+            }
+        }
+        Set::Keys(keys) => cpu.keyboard_mut().set_state(keys),
+        Set::DelayTimer(value) => cpu.timers_mut().set_delay_timer(value),
+        Set::Sprite(x, y) => {
+            cpu.display_mut().draw(x, y, 1, FONT_START_ADDRESS);
+        }
+        Set::Hires(hires) => cpu.display_mut().set_hires(hires),
+        Set::RplFlags(values) => {
+            let mut rpl_flags = *cpu.rpl_flags();
+            rpl_flags[..values.len()].copy_from_slice(values);
+            cpu.set_rpl_flags(rpl_flags);
+        }
+        Set::Run(opcodes) => {
+            for (index, &opcode) in opcodes.iter().enumerate() {
+                write_memory(memory, SCRATCH + index * 2, &opcode_bytes(opcode));
+            }
+            cpu.set_program_counter(SCRATCH);
+            for _ in opcodes {
+                cpu.step().unwrap();
+            }
+            cpu.set_program_counter(START);
+        }
+    }
+}
+
+fn run_case(case: &Case, profile: &str) {
+    let context = format!("{} (0x{:04X}) on {}", case.name, case.opcode, profile);
+    let quirks = Quirks::from_name(profile).unwrap();
+    let memory = Rc::new(RefCell::new(MockBus::new(quirks.platform.memory_size())));
+    write_memory(&memory, START, &opcode_bytes(case.opcode));
+    let mut cpu = Cpu::new(Rc::clone(&memory));
+    cpu.set_quirks(quirks);
+    cpu.set_random_source(Box::new(ScriptedRandom::new(vec![RANDOM_BYTE])));
+    for set in case.set {
+        set_up(&mut cpu, &memory, set);
+    }
+
+    let mut registers = *cpu.registers();
+    let mut i_register = cpu.i_register();
+    let mut program_counter = START + 2;
+    let mut stack = cpu.stack().addresses().to_vec();
+    let mut outcome = Ok(StepOutcome::Executed(case.opcode));
+    let mut written = Vec::new();
+
+    memory.borrow_mut().writes.clear();
+    let result = cpu.step();
+    for check in case.check {
+        match *check {
+            Check::Register(index, value) => registers[index] = value,
+            Check::I(address) => i_register = address,
+            Check::Pc(address) => program_counter = address,
+            Check::Stack(addresses) => stack = addresses.to_vec(),
+            Check::Outcome(expected) => outcome = expected,
+            Check::Memory(address, bytes) => {
+                written.extend(address..address + bytes.len());
+                for (offset, &byte) in bytes.iter().enumerate() {
+                    let found = memory.borrow().retrieve(address + offset);
+                    assert_eq!(
+                        found,
+                        byte,
+                        "{}: memory at 0x{:03X}",
+                        context,
+                        address + offset
+                    );
+                }
+            }
+            Check::Pixel(x, y, on) => {
+                assert_eq!(
+                    cpu.display().pixel(x, y),
+                    on,
+                    "{}: pixel {},{}",
+                    context,
+                    x,
+                    y
+                )
+            }
+            Check::DelayTimer(value) => {
+                assert_eq!(
+                    cpu.timers().delay_timer(),
+                    value,
+                    "{}: delay timer",
+                    context
+                )
+            }
+            Check::SoundTimer(value) => {
+                assert_eq!(
+                    cpu.timers().sound_timer(),
+                    value,
+                    "{}: sound timer",
+                    context
+                )
+            }
+            Check::Hires(hires) => {
+                assert_eq!(cpu.display().is_hires(), hires, "{}: hires", context)
+            }
+            Check::Planes(planes) => {
+                assert_eq!(
+                    cpu.display().selected_planes(),
+                    planes,
+                    "{}: planes",
+                    context
+                )
+            }
+            Check::RplFlags(values) => assert_eq!(
+                &cpu.rpl_flags()[..values.len()],
+                values,
+                "{}: RPL flags",
+                context
+            ),
+            Check::AudioPattern(buffer) => {
+                let pattern = cpu.audio_pattern().expect(&context);
+                assert_eq!(&pattern.buffer[..], buffer, "{}: audio pattern", context);
+            }
+            Check::Pitch(pitch) => {
+                let pattern = cpu.audio_pattern().expect(&context);
+                assert_eq!(pattern.pitch, pitch, "{}: pitch", context);
+            }
+            Check::NextOutcome(_) => {}
+        }
+    }
+
+    assert_eq!(result, outcome, "{}: outcome", context);
+    assert_eq!(cpu.registers(), &registers, "{}: registers", context);
+    assert_eq!(cpu.i_register(), i_register, "{}: I", context);
+    assert_eq!(cpu.program_counter(), program_counter, "{}: PC", context);
+    assert_eq!(cpu.stack().addresses(), &stack[..], "{}: stack", context);
+    for &address in &memory.borrow().writes {
+        assert!(
+            written.contains(&address),
+            "{}: unexpected write at 0x{:03X}",
+            context,
+            address
+        );
+    }
+
+    // Last, as it runs another instruction
+    for check in case.check {
+        if let Check::NextOutcome(expected) = *check {
+            assert_eq!(cpu.step(), expected, "{}: next outcome", context);
+        }
+    }
+}
+
+#[test]
+fn opcodes() {
+    for case in CASES {
+        for profile in case.profiles {
+            run_case(case, profile);
+        }
+    }
+}
+
+#[test]
+fn every_profile_is_known() {
+    for profile in ALL {
+        assert!(Quirks::from_name(profile).is_some(), "{}", profile);
+    }
+}
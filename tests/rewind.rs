@@ -0,0 +1,91 @@
+use chip8_core::cpu::Cpu;
+use chip8_core::memory::{Memory, MemoryTrait, SharedMemory};
+use chip8_core::rewind::{DEFAULT_REWIND_BUDGET, Rewind, RewindTrait};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Counts the frames in V0: ADD V0, 1 then a jump back to it
+fn machine() -> Cpu {
+    let memory: SharedMemory = Rc::new(RefCell::new(Memory::new()));
+    for (offset, byte) in [0x70, 0x01, 0x12, 0x00].into_iter().enumerate() {
+        memory.borrow_mut().write(0x200 + offset, byte);
+    }
+    Cpu::new(memory)
+}
+
+fn run_frame(cpu: &mut Cpu, rewind: &mut Rewind) {
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    rewind.record(cpu);
+}
+
+fn frame(cpu: &Cpu) -> u8 {
+    cpu.registers()[0]
+}
+
+#[test]
+fn rewinds_one_interval_at_a_time() {
+    let mut cpu = machine();
+    let mut rewind = Rewind::new(2, DEFAULT_REWIND_BUDGET);
+    for _ in 0..9 {
+        run_frame(&mut cpu, &mut rewind);
+    }
+    // Snapshots of the frames 2, 4, 6 and 8
+    assert_eq!(rewind.len(), 4);
+    assert!(rewind.rewind(&mut cpu));
+    assert_eq!(frame(&cpu), 8);
+    assert!(rewind.rewind(&mut cpu));
+    assert_eq!(frame(&cpu), 6);
+    assert!(rewind.rewind(&mut cpu));
+    assert_eq!(frame(&cpu), 4);
+
+    // Running again records from there
+    run_frame(&mut cpu, &mut rewind);
+    run_frame(&mut cpu, &mut rewind);
+    assert_eq!(frame(&cpu), 6);
+    assert!(rewind.rewind(&mut cpu));
+    assert_eq!(frame(&cpu), 2);
+    assert!(!rewind.rewind(&mut cpu));
+    assert_eq!(frame(&cpu), 2);
+    assert_eq!(rewind.used(), 0);
+}
+
+#[test]
+fn a_snapshot_of_the_current_frame_is_skipped() {
+    let mut cpu = machine();
+    let mut rewind = Rewind::new(3, DEFAULT_REWIND_BUDGET);
+    for _ in 0..6 {
+        run_frame(&mut cpu, &mut rewind);
+    }
+    assert!(rewind.rewind(&mut cpu));
+    assert_eq!(frame(&cpu), 3);
+    // Unless it is the only one
+    let mut rewind = Rewind::new(3, DEFAULT_REWIND_BUDGET);
+    for _ in 0..3 {
+        run_frame(&mut cpu, &mut rewind);
+    }
+    assert!(rewind.rewind(&mut cpu));
+    assert_eq!(frame(&cpu), 6);
+}
+
+#[test]
+fn the_oldest_snapshots_are_dropped_over_the_budget() {
+    let mut cpu = machine();
+    let size = cpu.save_state().len();
+    let mut rewind = Rewind::new(1, size * 3);
+    for _ in 0..5 {
+        run_frame(&mut cpu, &mut rewind);
+    }
+    assert_eq!((rewind.len(), rewind.used()), (3, size * 3));
+    for expected in [4, 3] {
+        assert!(rewind.rewind(&mut cpu));
+        assert_eq!(frame(&cpu), expected);
+    }
+    assert!(!rewind.rewind(&mut cpu));
+    assert!(rewind.is_empty());
+
+    // A snapshot bigger than the budget is never kept
+    let mut rewind = Rewind::new(1, size - 1);
+    run_frame(&mut cpu, &mut rewind);
+    assert!(rewind.is_empty());
+}
@@ -0,0 +1,99 @@
+use chip8_core::assembler::assemble;
+use chip8_core::backend::Framebuffer;
+use chip8_core::cpu::Cpu;
+use chip8_core::memory::{Memory, SharedMemory};
+use chip8_core::rom::{load_rom_bytes, rom_hash};
+use chip8_core::save_state::{SAVE_STATE_MAGIC, StateError};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Draws, writes to the memory and uses the random generator on every turn
+const PROGRAM: &str = "
+            LD I, 0x300
+    loop:   RND V1, 0xFF
+            ADD V0, 1
+            LD [I], V1
+            LD F, V0
+            DRW V0, V1, 5
+            LD I, 0x300
+            JP loop
+";
+
+fn machine(program: &str) -> Cpu {
+    let rom = assemble(program).unwrap().rom;
+    let memory: SharedMemory = Rc::new(RefCell::new(Memory::new()));
+    load_rom_bytes(&rom, Rc::clone(&memory)).unwrap();
+    let mut cpu = Cpu::new(memory);
+    cpu.set_seed(42);
+    cpu.set_rom_hash(rom_hash(&rom));
+    cpu
+}
+
+fn run(cpu: &mut Cpu, instructions: usize) {
+    for _ in 0..instructions {
+        cpu.step().unwrap();
+    }
+}
+
+#[test]
+fn a_loaded_state_runs_the_same() {
+    let mut cpu = machine(PROGRAM);
+    run(&mut cpu, 50);
+    let state = cpu.save_state();
+    run(&mut cpu, 70);
+    let later = cpu.save_state();
+    let frame = Framebuffer::from_display(cpu.display());
+
+    cpu.load_state(&state).unwrap();
+    assert_eq!(cpu.save_state(), state);
+    run(&mut cpu, 70);
+    assert_eq!(cpu.save_state(), later);
+    assert_eq!(Framebuffer::from_display(cpu.display()), frame);
+}
+
+#[test]
+fn broken_states_leave_the_machine_as_it_was() {
+    let mut cpu = machine(PROGRAM);
+    let state = cpu.save_state();
+    run(&mut cpu, 50);
+    let current = cpu.save_state();
+    let rejected = |cpu: &mut Cpu, data: &[u8]| {
+        let error = cpu.load_state(data).unwrap_err();
+        assert_eq!(cpu.save_state(), current);
+        error
+    };
+
+    let mut other = machine("JP 0x200");
+    assert!(matches!(
+        other.load_state(&state),
+        Err(StateError::WrongRom { .. })
+    ));
+
+    let mut version = state.clone();
+    version[SAVE_STATE_MAGIC.len()..SAVE_STATE_MAGIC.len() + 2]
+        .copy_from_slice(&9u16.to_le_bytes());
+    assert_eq!(
+        rejected(&mut cpu, &version),
+        StateError::UnsupportedVersion(9)
+    );
+    assert_eq!(
+        rejected(&mut cpu, &state[..state.len() - 1]),
+        StateError::InvalidFormat
+    );
+    assert_eq!(rejected(&mut cpu, b"C8"), StateError::InvalidFormat);
+    let mut magic = state.clone();
+    magic[0] = b'X';
+    assert_eq!(rejected(&mut cpu, &magic), StateError::InvalidFormat);
+
+    // The random generator is the last part, its size goes first. A byte too many is caught
+    // after the generator was loaded, which must be undone
+    let mut trailing = state.clone();
+    let size_at = trailing.len() - 8 - 4;
+    trailing[size_at..size_at + 4].copy_from_slice(&9u32.to_le_bytes());
+    trailing.push(0);
+    assert_eq!(rejected(&mut cpu, &trailing), StateError::InvalidFormat);
+    let mut short = state.clone();
+    short[size_at..size_at + 4].copy_from_slice(&7u32.to_le_bytes());
+    short.pop();
+    assert_eq!(rejected(&mut cpu, &short), StateError::InvalidFormat);
+}
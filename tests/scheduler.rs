@@ -0,0 +1,38 @@
+use chip8_core::assembler::assemble;
+use chip8_core::cpu::Cpu;
+use chip8_core::memory::{Memory, SharedMemory};
+use chip8_core::rom::load_rom_bytes;
+use chip8_core::scheduler::{FrameOutcome, Scheduler, SchedulerTrait};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn run_one_second(instructions_per_second: u32) -> Vec<u64> {
+    let memory: SharedMemory = Rc::new(RefCell::new(Memory::new()));
+    load_rom_bytes(&assemble("loop: JP loop").unwrap().rom, Rc::clone(&memory)).unwrap();
+    let mut cpu = Cpu::new(memory);
+    let mut scheduler = Scheduler::new(instructions_per_second);
+    let mut per_frame = Vec::new();
+    for _ in 0..60 {
+        let before = scheduler.instructions();
+        assert_eq!(scheduler.run_frame(&mut cpu), Ok(FrameOutcome::Completed));
+        per_frame.push(scheduler.instructions() - before);
+    }
+    assert_eq!(scheduler.frames(), 60);
+    per_frame
+}
+
+#[test]
+fn every_second_runs_the_configured_rate() {
+    for instructions_per_second in [700, 60, 1000, 30, 1] {
+        let per_frame = run_one_second(instructions_per_second);
+        assert_eq!(
+            per_frame.iter().sum::<u64>(),
+            instructions_per_second as u64
+        );
+        // Spread evenly instead of bunched up at the end of the second
+        let fewest = per_frame.iter().min().unwrap();
+        let most = per_frame.iter().max().unwrap();
+        assert!(most - fewest <= 1, "{:?}", per_frame);
+    }
+    assert_eq!(run_one_second(700)[..3], [11, 12, 12]);
+}
@@ -0,0 +1,129 @@
+use chip8_core::backend::Framebuffer;
+use chip8_core::checksum::{adler32, crc32};
+use chip8_core::screenshot::{Palette, encode_pbm, encode_png};
+
+// Written with Python's zlib, which has its own CRC and Adler implementations
+const TINY_PNG: [u8; 100] = [
+    0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, //
+    0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x02, //
+    0x08, 0x03, 0x00, 0x00, 0x00, 0xAA, 0xAA, 0x96, 0x28, 0x00, 0x00, 0x00, //
+    0x0C, 0x50, 0x4C, 0x54, 0x45, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0x55, //
+    0x55, 0x55, 0xAA, 0xAA, 0xAA, 0x2E, 0xBC, 0x97, 0x47, 0x00, 0x00, 0x00, //
+    0x13, 0x49, 0x44, 0x41, 0x54, 0x78, 0x01, 0x01, 0x08, 0x00, 0xF7, 0xFF, //
+    0x00, 0x00, 0x01, 0x02, 0x00, 0x03, 0x00, 0x01, 0x00, 0x22, 0x00, 0x08, //
+    0x29, 0xBE, 0x44, 0xF2, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4E, 0x44, //
+    0xAE, 0x42, 0x60, 0x82,
+];
+
+fn frame(width: usize, pixels: &[u8]) -> Framebuffer {
+    Framebuffer {
+        width,
+        height: pixels.len() / width,
+        pixels: pixels.to_vec(),
+    }
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+// The chunks of a PNG, with their CRC checked
+fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    let mut chunks = Vec::new();
+    let mut at = 8;
+    while at < png.len() {
+        let length = read_u32(png, at) as usize;
+        let kind_and_data = &png[at + 4..at + 8 + length];
+        assert_eq!(read_u32(png, at + 8 + length), crc32(kind_and_data));
+        let kind = String::from_utf8(kind_and_data[..4].to_vec()).unwrap();
+        chunks.push((kind, kind_and_data[4..].to_vec()));
+        at += 12 + length;
+    }
+    chunks
+}
+
+// Only the stored deflate blocks the encoder writes
+fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+    assert_eq!(zlib[..2], [0x78, 0x01]);
+    let mut data = Vec::new();
+    let mut at = 2;
+    loop {
+        let is_last = zlib[at] == 1;
+        let length = u16::from_le_bytes([zlib[at + 1], zlib[at + 2]]);
+        let complement = u16::from_le_bytes([zlib[at + 3], zlib[at + 4]]);
+        assert_eq!(length, !complement);
+        data.extend_from_slice(&zlib[at + 5..at + 5 + length as usize]);
+        at += 5 + length as usize;
+        if is_last {
+            break;
+        }
+    }
+    assert_eq!(read_u32(zlib, at), adler32(&data));
+    assert_eq!(at + 4, zlib.len());
+    data
+}
+
+#[test]
+fn checksums_match_the_reference_values() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    assert_eq!(adler32(b""), 1);
+}
+
+#[test]
+fn png_of_a_tiny_frame() {
+    let png = encode_png(&frame(3, &[0, 1, 2, 3, 0, 1]), 1, &Palette::default());
+    assert_eq!(png, TINY_PNG);
+}
+
+#[test]
+fn png_decodes_back_to_the_frame() {
+    // Over 64 KiB of pixels once scaled, which takes several deflate blocks
+    let pixels: Vec<u8> = (0..128 * 64).map(|index| (index % 7 % 4) as u8).collect();
+    let original = frame(128, &pixels);
+    let palette = Palette::parse("102030,405060").unwrap();
+    let png = encode_png(&original, 4, &palette);
+
+    let chunks = chunks(&png);
+    let kinds: Vec<&str> = chunks.iter().map(|(kind, _)| kind.as_str()).collect();
+    assert_eq!(kinds, ["IHDR", "PLTE", "IDAT", "IEND"]);
+    let header = &chunks[0].1;
+    assert_eq!((read_u32(header, 0), read_u32(header, 4)), (512, 256));
+    assert_eq!(header[4 * 2..], [8, 3, 0, 0, 0]);
+    assert_eq!(chunks[1].1[..6], [0x10, 0x20, 0x30, 0x40, 0x50, 0x60]);
+
+    let image = inflate_stored(&chunks[2].1);
+    assert_eq!(image.len(), (512 + 1) * 256);
+    for (y, row) in image.chunks(512 + 1).enumerate() {
+        assert_eq!(row[0], 0);
+        for (x, &color) in row[1..].iter().enumerate() {
+            assert_eq!(color, original.pixel(x / 4, y / 4));
+        }
+    }
+}
+
+#[test]
+fn pbm_packs_8_pixels_in_a_byte() {
+    // Black and the dark grey are dark with the default palette, white and the light grey aren't
+    let pbm = encode_pbm(
+        &frame(
+            10,
+            &[0, 1, 2, 3, 0, 1, 1, 1, 1, 0, 1, 1, 1, 1, 1, 1, 1, 1, 3, 3],
+        ),
+        1,
+        &Palette::default(),
+    );
+    assert_eq!(pbm, b"P4\n10 2\n\xA8\x40\x00\x00");
+
+    // Each row is padded on its own once scaled
+    let pbm = encode_pbm(&frame(3, &[0, 1, 0]), 3, &Palette::default());
+    assert_eq!(pbm, b"P4\n9 3\n\xE3\x80\xE3\x80\xE3\x80");
+    let pbm = encode_pbm(
+        &frame(1, &[1]),
+        1,
+        &Palette::parse("FFFFFF,000000").unwrap(),
+    );
+    assert_eq!(pbm, b"P4\n1 1\n\x80");
+}